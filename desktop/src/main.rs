@@ -3,6 +3,7 @@ extern crate nessy;
 use std::collections::HashMap;
 
 use nessy::{
+    apu::{mixer::AudioOutput, rate_control::RateControlConfig, ring::AudioConsumer},
     controller::{Joypad, JoypadStatus},
     cpu::rom::ROM,
     Nes, SCREEN_HEIGHT, SCREEN_WIDTH,
@@ -17,6 +18,7 @@ use sdl2::{
 
 const SCALE_FACTOR: usize = 2;
 const SAMPLE_RATE: f64 = 44_100.0;
+const AUDIO_CALLBACK_FRAMES: u16 = 1024;
 
 fn build_controller_map() -> HashMap<Keycode, JoypadStatus> {
     let mut controller_map = HashMap::new();
@@ -31,16 +33,18 @@ fn build_controller_map() -> HashMap<Keycode, JoypadStatus> {
     controller_map
 }
 
-struct APUCallback<'a> {
-    nes: &'a mut Nes,
-    avoid_underruns: bool,
+// runs on the audio thread while the emulation runs on the main thread
+struct APUCallback {
+    consumer: AudioConsumer,
 }
 
-impl<'a> AudioCallback for APUCallback<'a> {
+impl AudioCallback for APUCallback {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        self.nes.fill_audio_buffer(out, self.avoid_underruns);
+        let count = self.consumer.pop(out);
+        // silence on underruns
+        out[count..].fill(0.0);
     }
 }
 
@@ -74,23 +78,33 @@ fn handle_events(
 }
 
 fn main() {
-    let args = std::env::args().take(2).collect::<Vec<_>>();
+    let args = std::env::args().take(3).collect::<Vec<_>>();
 
     if args.len() < 2 {
-        eprintln!("usage: nessy rom.nes");
+        eprintln!("usage: nessy rom.nes [--stereo]");
     } else {
         let rom_path = &args[1];
         let bytes = std::fs::read(rom_path).unwrap();
         let rom = ROM::new(bytes).unwrap();
         let mut nes = Nes::new(rom, SAMPLE_RATE);
 
+        if args.get(2).is_some_and(|arg| arg == "--stereo") {
+            nes.set_audio_output(AudioOutput::Stereo);
+        }
+
+        // the frames are paced by vsync, the sample rate follows the audio device
+        nes.enable_audio_rate_control(RateControlConfig {
+            target_frames: 2 * AUDIO_CALLBACK_FRAMES as usize,
+            ..Default::default()
+        });
+
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
         let audio_subsystem = sdl_context.audio().unwrap();
         let desired_audio_spec = AudioSpecDesired {
             freq: Some(SAMPLE_RATE as i32),
-            channels: Some(1), // mono,
-            samples: Some(1024),
+            channels: Some(nes.get_audio_output().channels() as u8),
+            samples: Some(AUDIO_CALLBACK_FRAMES),
         };
 
         let window = video_subsystem
@@ -125,11 +139,9 @@ fn main() {
         let mut event_pump = sdl_context.event_pump().unwrap();
         let controller_map = build_controller_map();
 
+        let consumer = nes.take_audio_consumer().unwrap();
         let audio_device = audio_subsystem
-            .open_playback(None, &desired_audio_spec, |_| APUCallback {
-                nes: &mut nes,
-                avoid_underruns: false,
-            })
+            .open_playback(None, &desired_audio_spec, |_| APUCallback { consumer })
             .unwrap();
 
         audio_device.resume();
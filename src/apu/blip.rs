@@ -0,0 +1,196 @@
+use std::f64::consts::PI;
+
+// band-limited step synthesis: instead of sampling the mixer output, each change of its amplitude
+// is added as a step smoothed by a windowed sinc kernel, placed at its exact position between
+// two output samples, the output is the running sum of the steps, see blip_buf by Shay Green
+// http://www.slack.net/~ant/libs/audio.html#Blip_Buffer
+
+const KERNEL_WIDTH: usize = 16;
+// the kernel is interpolated between phases to place a step between two samples
+const PHASES: usize = 32;
+// deltas can be written up to KERNEL_WIDTH samples ahead of the next sample read
+const BUFFER_SIZE: usize = 64;
+const BUFFER_MASK: usize = BUFFER_SIZE - 1;
+// the kernel cuts off slightly below the Nyquist frequency of the output
+const CUTOFF: f64 = 0.9;
+const INTEGRATION_STEPS: usize = 64;
+
+pub struct BlipBuffer {
+    // for each phase, the increase of the step in each sample of the kernel, summing to 1
+    kernel: Box<[[f32; KERNEL_WIDTH]; PHASES + 1]>,
+    deltas: [f32; BUFFER_SIZE],
+    // integrated output
+    output: f32,
+    amplitude: f32,
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// low-pass kernel centered on 0 and windowed with a Blackman window over the kernel width
+fn impulse(x: f64) -> f64 {
+    let half_width = (KERNEL_WIDTH / 2) as f64;
+
+    if x.abs() >= half_width {
+        return 0.0;
+    }
+
+    let w = (x + half_width) / (2.0 * half_width);
+    let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+
+    CUTOFF * sinc(CUTOFF * x) * window
+}
+
+fn build_kernel() -> Box<[[f32; KERNEL_WIDTH]; PHASES + 1]> {
+    let mut kernel = Box::new([[0.0; KERNEL_WIDTH]; PHASES + 1]);
+    let half_width = (KERNEL_WIDTH / 2) as f64;
+
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let offset = phase as f64 / PHASES as f64;
+        let mut steps = [0.0; KERNEL_WIDTH];
+
+        // the step increases by the integral of the impulse over the sample
+        for (i, step) in steps.iter_mut().enumerate() {
+            let end = i as f64 + 1.0 - half_width - offset;
+            let dx = 1.0 / INTEGRATION_STEPS as f64;
+
+            *step = (0..INTEGRATION_STEPS)
+                .map(|j| impulse(end - 1.0 + (j as f64 + 0.5) * dx) * dx)
+                .sum();
+        }
+
+        // a step must end at its full height
+        let total: f64 = steps.iter().sum();
+
+        for (tap, step) in taps.iter_mut().zip(steps) {
+            *tap = (step / total) as f32;
+        }
+    }
+
+    kernel
+}
+
+impl BlipBuffer {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        BlipBuffer {
+            kernel: build_kernel(),
+            deltas: [0.0; BUFFER_SIZE],
+            output: 0.0,
+            amplitude: 0.0,
+        }
+    }
+
+    /// `time` is in output samples, steps must be added in order and after the last sample read,
+    /// the output is delayed by half the kernel width
+    pub fn set_amplitude(&mut self, time: f64, amplitude: f32) {
+        let delta = amplitude - self.amplitude;
+
+        if delta == 0.0 {
+            return;
+        }
+
+        self.amplitude = amplitude;
+
+        let sample = time.floor();
+        let phase = (time - sample) * PHASES as f64;
+        let phase_index = phase as usize;
+        let interp = (phase - phase_index as f64) as f32;
+        let start = sample as usize;
+
+        let [prev, next] = [phase_index, phase_index + 1].map(|i| &self.kernel[i]);
+
+        for i in 0..KERNEL_WIDTH {
+            let tap = prev[i] + (next[i] - prev[i]) * interp;
+            self.deltas[(start + i) & BUFFER_MASK] += delta * tap;
+        }
+    }
+
+    /// reads the sample `index`, once every step up to `index + 1` was added
+    pub fn read_sample(&mut self, index: u32) -> f32 {
+        let delta = &mut self.deltas[index as usize & BUFFER_MASK];
+        self.output += std::mem::take(delta);
+        self.output
+    }
+
+    /// drops the pending steps, the output jumps to the current amplitude
+    pub fn clear(&mut self) {
+        self.deltas.fill(0.0);
+        self.output = self.amplitude;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    // output samples after a step from 0 to 1 at `time`
+    fn step_response(time: f64) -> Vec<f32> {
+        let mut blip = BlipBuffer::new();
+        blip.set_amplitude(time, 1.0);
+
+        (0..2 * KERNEL_WIDTH as u32)
+            .map(|index| blip.read_sample(index))
+            .collect()
+    }
+
+    #[test]
+    fn kernel_phases_sum_to_one() {
+        for (phase, taps) in build_kernel().iter().enumerate() {
+            let sum: f32 = taps.iter().sum();
+            assert!((sum - 1.0).abs() < EPSILON, "phase {phase}: {sum}");
+        }
+    }
+
+    #[test]
+    fn step_settles_after_the_kernel_width() {
+        let output = step_response(0.0);
+
+        // the kernel is symmetric, the step is halfway up at its center
+        let half = KERNEL_WIDTH / 2;
+        assert!((output[half - 1] - 0.5).abs() < EPSILON, "{output:?}");
+
+        for sample in &output[KERNEL_WIDTH - 1..] {
+            assert!((sample - 1.0).abs() < EPSILON, "{output:?}");
+        }
+    }
+
+    #[test]
+    fn steps_between_samples_interpolate() {
+        // a later step has risen less during its transition
+        let [early, middle, late] = [0.0, 0.25, 0.5].map(step_response);
+        let half = KERNEL_WIDTH / 2;
+
+        for i in half - 1..=half {
+            assert!(early[i] > middle[i] && middle[i] > late[i], "sample {i}");
+        }
+
+        // between two phases of the kernel, the step is the average of both
+        let phase = 1.0 / PHASES as f64;
+        let [start, between, end] = [0.0, phase / 2.0, phase].map(step_response);
+
+        for i in 0..KERNEL_WIDTH {
+            let average = (start[i] + end[i]) / 2.0;
+            assert!((between[i] - average).abs() < EPSILON, "sample {i}");
+        }
+    }
+
+    #[test]
+    fn clear_jumps_to_the_current_amplitude() {
+        let mut blip = BlipBuffer::new();
+        blip.set_amplitude(0.0, 0.7);
+        blip.set_amplitude(3.5, 0.4);
+        blip.clear();
+
+        for index in 0..2 * KERNEL_WIDTH as u32 {
+            assert_eq!(blip.read_sample(index), 0.4);
+        }
+    }
+}
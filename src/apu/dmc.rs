@@ -1,12 +1,16 @@
+use crate::region::Region;
+
 use super::common::Timer;
 
-const DELTA_MODULATION_RATES: [u16; 16] = [
+const NTSC_DELTA_MODULATION_RATES: [u16; 16] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
 ];
 
-#[derive(Default)]
+const PAL_DELTA_MODULATION_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
 pub struct DeltaModulationChannel {
-    enabled: bool,
     pub interrupt_flag: bool,
     loop_flag: bool,
     output_level: u8,
@@ -18,14 +22,44 @@ pub struct DeltaModulationChannel {
     silence_flag: bool,
     output_bits_remaining: u8,
     irq_enabled: bool,
-    pub cpu_stall: u32,
-    pub memory_read_request: Option<u16>,
+    // the byte fetched by the last DMA, loaded into the shift register when an output cycle starts
+    sample_buffer: Option<u8>,
+    // address of the byte requested to the DMA unit while the sample buffer is empty
+    dma_request: Option<u16>,
     timer: Timer,
+    rates: &'static [u16; 16],
 }
 
 impl DeltaModulationChannel {
     pub fn new() -> Self {
-        Self::default()
+        DeltaModulationChannel {
+            interrupt_flag: false,
+            loop_flag: false,
+            output_level: 0,
+            sample_addr: 0,
+            sample_len: 0,
+            current_addr: 0,
+            bytes_remaining: 0,
+            shift_register: 0,
+            silence_flag: true,
+            output_bits_remaining: 0,
+            irq_enabled: false,
+            sample_buffer: None,
+            dma_request: None,
+            timer: Timer::default(),
+            rates: &NTSC_DELTA_MODULATION_RATES,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = match region {
+            Region::Ntsc | Region::Dendy => &NTSC_DELTA_MODULATION_RATES,
+            Region::Pal => &PAL_DELTA_MODULATION_RATES,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.output_level &= 1;
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
@@ -34,7 +68,7 @@ impl DeltaModulationChannel {
                 self.irq_enabled = val & 0b1000_0000 != 0;
                 self.loop_flag = val & 0b0100_0000 != 0;
                 let rate_index = (val & 0b1111) as usize;
-                self.timer.period = DELTA_MODULATION_RATES[rate_index];
+                self.timer.period = self.rates[rate_index];
             }
             0x4011 => {
                 self.output_level = val & 0b0111_1111;
@@ -50,20 +84,35 @@ impl DeltaModulationChannel {
     }
 
     pub fn step_timer(&mut self) {
-        if self.enabled {
-            self.step_reader();
-
-            if self.memory_read_request.is_none() && self.timer.step() {
-                self.step_shifter();
-            }
+        if self.timer.step() {
+            self.step_shifter();
         }
+
+        self.step_reader();
     }
 
-    pub fn set_memory_read_response(&mut self, val: u8) {
-        self.shift_register = val;
+    pub fn get_dma_request(&self) -> Option<u16> {
+        self.dma_request
+    }
 
-        if self.timer.step() {
-            self.step_shifter();
+    // https://www.nesdev.org/wiki/APU_DMC#Memory_reader
+    pub fn set_dma_response(&mut self, val: u8) {
+        self.dma_request = None;
+        self.sample_buffer = Some(val);
+
+        self.current_addr = match self.current_addr {
+            0xFFFF => 0x8000,
+            _ => self.current_addr + 1,
+        };
+
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt_flag = true;
+            }
         }
     }
 
@@ -72,50 +121,47 @@ impl DeltaModulationChannel {
         self.bytes_remaining = self.sample_len;
     }
 
+    // the DMA unit fills the sample buffer a few cycles after the request, see Bus::run_dma
     fn step_reader(&mut self) {
-        if self.output_bits_remaining == 0 && self.bytes_remaining > 0 {
-            // TODO: the stall duration varies depending on the timing of the read
-            self.cpu_stall += 4;
-
-            // the bus will update the shift register with the read value right after the apu.step() call
-            // ideally we would have: self.shift_register = bus.read_word(self.current_addr);
-            self.memory_read_request = Some(self.current_addr);
-
-            self.output_bits_remaining = 8;
-            self.current_addr = match self.current_addr {
-                0xFFFF => 0x8000,
-                _ => self.current_addr + 1,
-            };
-
-            self.bytes_remaining -= 1;
-
-            if self.bytes_remaining == 0 && self.loop_flag {
-                self.restart();
-            } else if self.bytes_remaining == 0 && self.interrupt_flag {
-                self.interrupt_flag = true;
-            }
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 && self.dma_request.is_none() {
+            self.dma_request = Some(self.current_addr);
         }
     }
 
+    // https://www.nesdev.org/wiki/APU_DMC#Output_unit
     fn step_shifter(&mut self) {
-        if self.output_bits_remaining != 0 {
-            if !self.silence_flag {
-                match self.shift_register & 1 {
-                    1 => {
-                        if self.output_level <= 125 {
-                            self.output_level += 2
-                        }
+        if !self.silence_flag {
+            match self.shift_register & 1 {
+                1 => {
+                    if self.output_level <= 125 {
+                        self.output_level += 2
                     }
-                    _ => {
-                        if self.output_level >= 2 {
-                            self.output_level -= 2
-                        }
+                }
+                _ => {
+                    if self.output_level >= 2 {
+                        self.output_level -= 2
                     }
-                };
-            }
+                }
+            };
+        }
+
+        self.shift_register >>= 1;
+        self.output_bits_remaining = self.output_bits_remaining.saturating_sub(1);
 
-            self.shift_register >>= 1;
-            self.output_bits_remaining -= 1;
+        if self.output_bits_remaining == 0 {
+            self.start_output_cycle();
+        }
+    }
+
+    fn start_output_cycle(&mut self) {
+        self.output_bits_remaining = 8;
+
+        match self.sample_buffer.take() {
+            Some(val) => {
+                self.shift_register = val;
+                self.silence_flag = false;
+            }
+            None => self.silence_flag = true,
         }
     }
 
@@ -127,11 +173,13 @@ impl DeltaModulationChannel {
         self.interrupt_flag = false;
     }
 
+    // the output unit keeps running while the channel is disabled and outputs silence
+    // once the sample buffer is empty
     pub fn set_enabled(&mut self, enabled: bool) {
-        self.enabled = enabled;
-
         if !enabled {
-            self.bytes_remaining = 0
+            // a pending DMA is aborted
+            self.bytes_remaining = 0;
+            self.dma_request = None;
         } else if self.bytes_remaining == 0 {
             self.restart();
         }
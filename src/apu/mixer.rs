@@ -0,0 +1,457 @@
+// https://www.nesdev.org/wiki/APU_Mixer
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl AudioChannel {
+    pub const COUNT: usize = 5;
+    pub const ALL: [AudioChannel; AudioChannel::COUNT] = [
+        AudioChannel::Pulse1,
+        AudioChannel::Pulse2,
+        AudioChannel::Triangle,
+        AudioChannel::Noise,
+        AudioChannel::Dmc,
+    ];
+}
+
+impl From<AudioChannel> for u8 {
+    fn from(channel: AudioChannel) -> Self {
+        channel as u8
+    }
+}
+
+impl TryFrom<u8> for AudioChannel {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        AudioChannel::ALL.get(val as usize).copied().ok_or(val)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelSettings {
+    pub enabled: bool,
+    /// when at least one channel is soloed, only the soloed channels are heard
+    pub solo: bool,
+    /// 1.0 is the volume of the console
+    pub volume: f32,
+    /// stereo balance, from -1.0 (left only) to 1.0 (right only),
+    /// a centered channel is heard at full volume on both sides
+    pub pan: f32,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            enabled: true,
+            solo: false,
+            volume: 1.0,
+            pan: 0.0,
+        }
+    }
+}
+
+/// the samples of a frame are interleaved: left then right in stereo, one sample per channel
+/// in the order of `AudioChannel::ALL` for the stems, each stem is the channel mixed alone
+/// so the stems don't add up exactly to the mono output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioOutput {
+    Mono,
+    Stems,
+    Stereo,
+}
+
+impl AudioOutput {
+    pub fn channels(&self) -> usize {
+        match self {
+            AudioOutput::Mono => 1,
+            AudioOutput::Stems => AudioChannel::COUNT,
+            AudioOutput::Stereo => 2,
+        }
+    }
+}
+
+impl From<AudioOutput> for u8 {
+    fn from(output: AudioOutput) -> Self {
+        match output {
+            AudioOutput::Mono => 0,
+            AudioOutput::Stems => 1,
+            AudioOutput::Stereo => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for AudioOutput {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(AudioOutput::Mono),
+            1 => Ok(AudioOutput::Stems),
+            2 => Ok(AudioOutput::Stereo),
+            _ => Err(val),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const PULSE_MIXER_LOOKUP: [f32; 32] = [
+    0.0, 0.011609139, 0.02293948, 0.034000948,
+    0.044803, 0.05535466, 0.06566453, 0.07574082,
+    0.0855914, 0.09522375, 0.10464504, 0.11386215,
+    0.12288164, 0.1317098, 0.14035264, 0.14881596,
+    0.15710525, 0.16522588, 0.17318292, 0.18098126,
+    0.18862559, 0.19612046, 0.20347017, 0.21067894,
+    0.21775076, 0.2246895, 0.23149887, 0.23818247,
+    0.24474378, 0.25118607, 0.25751257, 0.26372638,
+];
+
+#[rustfmt::skip]
+const TRIANGLE_MIXER_LOOKUP: [f32; 204] = [
+    0.0, 0.006699824, 0.01334502, 0.019936256, 0.02647418, 0.032959443, 0.039392676, 0.0457745, 
+    0.052105535, 0.05838638, 0.064617634, 0.07079987, 0.07693369, 0.08301962, 0.08905826, 0.095050134, 
+    0.100995794, 0.10689577, 0.11275058, 0.118560754, 0.12432679, 0.13004918, 0.13572845, 0.14136505, 
+    0.1469595, 0.15251222, 0.1580237, 0.1634944, 0.16892476, 0.17431524, 0.17966628, 0.1849783, 
+    0.19025174, 0.19548698, 0.20068447, 0.20584463, 0.21096781, 0.21605444, 0.22110492, 0.2261196, 
+    0.23109888, 0.23604311, 0.24095272, 0.245828, 0.25066936, 0.2554771, 0.26025164, 0.26499328, 
+    0.26970237, 0.27437922, 0.27902418, 0.28363758, 0.28821972, 0.29277095, 0.29729152, 0.3017818, 
+    0.3062421, 0.31067267, 0.31507385, 0.31944588, 0.32378912, 0.32810378, 0.3323902, 0.3366486, 
+    0.3408793, 0.34508255, 0.34925863, 0.35340777, 0.35753027, 0.36162636, 0.36569634, 0.36974037, 
+    0.37375876, 0.37775174, 0.38171956, 0.38566244, 0.38958064, 0.39347437, 0.39734384, 0.4011893, 
+    0.405011, 0.40880907, 0.41258383, 0.41633546, 0.42006415, 0.42377013, 0.4274536, 0.43111476, 
+    0.43475384, 0.43837097, 0.44196644, 0.4455404, 0.449093, 0.45262453, 0.45613506, 0.4596249, 
+    0.46309412, 0.46654293, 0.46997157, 0.47338015, 0.47676894, 0.48013794, 0.48348752, 0.4868177, 
+    0.49012873, 0.4934207, 0.49669388, 0.49994832, 0.50318426, 0.50640184, 0.5096012, 0.51278245, 
+    0.51594585, 0.5190914, 0.5222195, 0.52533007, 0.52842325, 0.5314993, 0.53455836, 0.5376005, 
+    0.54062593, 0.5436348, 0.54662704, 0.54960304, 0.55256283, 0.55550647, 0.5584343, 0.56134623, 
+    0.5642425, 0.56712323, 0.5699885, 0.5728384, 0.5756732, 0.57849294, 0.5812977, 0.5840876, 
+    0.5868628, 0.58962345, 0.59236956, 0.59510136, 0.5978189, 0.6005223, 0.6032116, 0.605887, 
+    0.60854864, 0.6111966, 0.6138308, 0.61645156, 0.619059, 0.62165314, 0.624234, 0.62680185, 
+    0.6293567, 0.63189864, 0.6344277, 0.6369442, 0.63944805, 0.64193934, 0.64441824, 0.64688486, 
+    0.6493392, 0.6517814, 0.6542115, 0.65662974, 0.65903604, 0.6614306, 0.6638134, 0.66618466, 
+    0.66854435, 0.6708926, 0.67322946, 0.67555505, 0.67786944, 0.68017274, 0.68246496, 0.6847462, 
+    0.6870166, 0.6892762, 0.69152504, 0.6937633, 0.6959909, 0.69820803, 0.7004148, 0.7026111, 
+    0.7047972, 0.7069731, 0.7091388, 0.7112945, 0.7134401, 0.7155759, 0.7177018, 0.7198179, 
+    0.72192425, 0.72402096, 0.726108, 0.72818565, 0.7302538, 0.73231256, 0.73436195, 0.7364021, 
+    0.7384331, 0.7404549, 0.7424676, 0.7444713,
+];
+
+// the lookup tables are sampled from these approximations of the non-linear DACs,
+// they are used directly once the channel levels are scaled by their volume
+fn pulse_dac(level: f32) -> f32 {
+    if level <= 0.0 {
+        0.0
+    } else {
+        95.52 / (8128.0 / level + 100.0)
+    }
+}
+
+// `level` is 3 * triangle + 2 * noise + dmc
+fn tnd_dac(level: f32) -> f32 {
+    if level <= 0.0 {
+        0.0
+    } else {
+        163.67 / (24329.0 / level + 100.0)
+    }
+}
+
+fn dac_mix([p1, p2, t, n, dmc]: [f32; AudioChannel::COUNT]) -> f32 {
+    pulse_dac(p1 + p2) + tnd_dac(3.0 * t + 2.0 * n + dmc)
+}
+
+fn scale(
+    levels: [u8; AudioChannel::COUNT],
+    gains: &[f32; AudioChannel::COUNT],
+) -> [f32; AudioChannel::COUNT] {
+    let mut scaled = [0.0; AudioChannel::COUNT];
+
+    for ((scaled, level), gain) in scaled.iter_mut().zip(levels).zip(gains) {
+        *scaled = level as f32 * gain;
+    }
+
+    scaled
+}
+
+pub struct Mixer {
+    settings: [ChannelSettings; AudioChannel::COUNT],
+    gains: [f32; AudioChannel::COUNT],
+    // gains of the left and right sides, including the volume
+    stereo_gains: [[f32; AudioChannel::COUNT]; 2],
+    // every gain is 1.0, the lookup tables can be used
+    unity: bool,
+    // both sides are the mono output
+    centered: bool,
+}
+
+impl Mixer {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Mixer {
+            settings: [ChannelSettings::default(); AudioChannel::COUNT],
+            gains: [1.0; AudioChannel::COUNT],
+            stereo_gains: [[1.0; AudioChannel::COUNT]; 2],
+            unity: true,
+            centered: true,
+        }
+    }
+
+    pub fn get_settings(&self, channel: AudioChannel) -> ChannelSettings {
+        self.settings[channel as usize]
+    }
+
+    pub fn set_settings(&mut self, channel: AudioChannel, settings: ChannelSettings) {
+        self.settings[channel as usize] = settings;
+
+        let solo = self.settings.iter().any(|settings| settings.solo);
+
+        for (gain, settings) in self.gains.iter_mut().zip(self.settings) {
+            *gain = if !settings.enabled || (solo && !settings.solo) {
+                0.0
+            } else {
+                settings.volume.max(0.0)
+            };
+        }
+
+        let [left, right] = &mut self.stereo_gains;
+
+        for (i, settings) in self.settings.iter().enumerate() {
+            let pan = settings.pan.clamp(-1.0, 1.0);
+            left[i] = self.gains[i] * (1.0 - pan).min(1.0);
+            right[i] = self.gains[i] * (1.0 + pan).min(1.0);
+        }
+
+        self.unity = self.gains.iter().all(|&gain| gain == 1.0);
+        self.centered = self.settings.iter().all(|settings| settings.pan == 0.0);
+    }
+
+    /// `levels` are the outputs of the channels in the order of `AudioChannel::ALL`
+    pub fn mix(&self, levels: [u8; AudioChannel::COUNT]) -> f32 {
+        let [p1, p2, t, n, dmc] = levels;
+
+        if self.unity {
+            let pulse_out = PULSE_MIXER_LOOKUP[(p1 + p2) as usize];
+            let tnd_out = TRIANGLE_MIXER_LOOKUP[(3 * t + 2 * n + dmc) as usize];
+            return pulse_out + tnd_out;
+        }
+
+        dac_mix(scale(levels, &self.gains))
+    }
+
+    /// left and right outputs
+    pub fn stereo(&self, levels: [u8; AudioChannel::COUNT]) -> [f32; 2] {
+        if self.centered {
+            let out = self.mix(levels);
+            return [out, out];
+        }
+
+        self.stereo_gains
+            .each_ref()
+            .map(|gains| dac_mix(scale(levels, gains)))
+    }
+
+    /// output of each channel mixed alone
+    pub fn stems(&self, levels: [u8; AudioChannel::COUNT]) -> [f32; AudioChannel::COUNT] {
+        let [p1, p2, t, n, dmc] = scale(levels, &self.gains);
+
+        [
+            pulse_dac(p1),
+            pulse_dac(p2),
+            tnd_dac(3.0 * t),
+            tnd_dac(2.0 * n),
+            tnd_dac(dmc),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+    const LEVELS: [u8; AudioChannel::COUNT] = [5, 12, 9, 3, 100];
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < EPSILON, "{a} != {b}");
+    }
+
+    // levels with only the given channels kept
+    fn only(channels: &[AudioChannel]) -> [u8; AudioChannel::COUNT] {
+        let mut levels = LEVELS;
+
+        for channel in AudioChannel::ALL {
+            if !channels.contains(&channel) {
+                levels[channel as usize] = 0;
+            }
+        }
+
+        levels
+    }
+
+    #[test]
+    fn dac_formulas_match_the_lookup_tables() {
+        for (level, &out) in PULSE_MIXER_LOOKUP.iter().enumerate() {
+            assert_close(pulse_dac(level as f32), out);
+        }
+
+        for (level, &out) in TRIANGLE_MIXER_LOOKUP.iter().enumerate() {
+            assert_close(tnd_dac(level as f32), out);
+        }
+    }
+
+    #[test]
+    fn unity_gains_use_the_lookup_tables() {
+        let mut mixer = Mixer::new();
+        let lookup = mixer.mix(LEVELS);
+
+        // a volume which is not 1.0 goes through the formulas
+        mixer.set_settings(
+            AudioChannel::Noise,
+            ChannelSettings {
+                volume: 1.0 + f32::EPSILON,
+                ..Default::default()
+            },
+        );
+        assert!(!mixer.unity);
+        assert_close(mixer.mix(LEVELS), lookup);
+
+        mixer.set_settings(AudioChannel::Noise, ChannelSettings::default());
+        assert!(mixer.unity);
+        assert_eq!(mixer.mix(LEVELS), lookup);
+    }
+
+    #[test]
+    fn mute() {
+        let mut mixer = Mixer::new();
+        let reference = Mixer::new();
+
+        mixer.set_settings(
+            AudioChannel::Pulse1,
+            ChannelSettings {
+                enabled: false,
+                ..Default::default()
+            },
+        );
+
+        assert_close(
+            mixer.mix(LEVELS),
+            reference.mix(only(&[
+                AudioChannel::Pulse2,
+                AudioChannel::Triangle,
+                AudioChannel::Noise,
+                AudioChannel::Dmc,
+            ])),
+        );
+    }
+
+    #[test]
+    fn solo() {
+        let mut mixer = Mixer::new();
+        let reference = Mixer::new();
+        let solo = ChannelSettings {
+            solo: true,
+            ..Default::default()
+        };
+
+        mixer.set_settings(AudioChannel::Triangle, solo);
+        mixer.set_settings(AudioChannel::Dmc, solo);
+        assert_close(
+            mixer.mix(LEVELS),
+            reference.mix(only(&[AudioChannel::Triangle, AudioChannel::Dmc])),
+        );
+
+        // a muted channel stays silent when soloed
+        mixer.set_settings(
+            AudioChannel::Dmc,
+            ChannelSettings {
+                enabled: false,
+                ..solo
+            },
+        );
+        assert_close(
+            mixer.mix(LEVELS),
+            reference.mix(only(&[AudioChannel::Triangle])),
+        );
+
+        // no channel soloed anymore
+        mixer.set_settings(AudioChannel::Triangle, ChannelSettings::default());
+        mixer.set_settings(AudioChannel::Dmc, ChannelSettings::default());
+        assert_eq!(mixer.mix(LEVELS), reference.mix(LEVELS));
+    }
+
+    #[test]
+    fn stems_are_the_channels_mixed_alone() {
+        let mixer = Mixer::new();
+        let stems = mixer.stems(LEVELS);
+
+        for channel in AudioChannel::ALL {
+            assert_close(stems[channel as usize], mixer.mix(only(&[channel])));
+        }
+    }
+
+    fn pan(mixer: &mut Mixer, channel: AudioChannel, pan: f32) {
+        let settings = mixer.get_settings(channel);
+        mixer.set_settings(channel, ChannelSettings { pan, ..settings });
+    }
+
+    #[test]
+    fn centered_stereo_is_mono() {
+        let mut mixer = Mixer::new();
+        let out = mixer.mix(LEVELS);
+        assert_eq!(mixer.stereo(LEVELS), [out, out]);
+
+        mixer.set_settings(
+            AudioChannel::Pulse2,
+            ChannelSettings {
+                volume: 0.5,
+                ..Default::default()
+            },
+        );
+        let out = mixer.mix(LEVELS);
+        assert_eq!(mixer.stereo(LEVELS), [out, out]);
+    }
+
+    #[test]
+    fn panning_lowers_the_opposite_side() {
+        let mut mixer = Mixer::new();
+        let reference = Mixer::new();
+        pan(&mut mixer, AudioChannel::Triangle, 0.5);
+
+        let [left, right] = mixer.stereo(LEVELS);
+        // the channel is at full volume on its side and at half volume on the other
+        assert_close(right, reference.mix(LEVELS));
+        let [p1, p2, t, n, dmc] = LEVELS.map(f32::from);
+        assert_close(left, pulse_dac(p1 + p2) + tnd_dac(1.5 * t + 2.0 * n + dmc));
+    }
+
+    #[test]
+    fn hard_left_silences_the_right_side() {
+        let mut mixer = Mixer::new();
+        let reference = Mixer::new();
+
+        pan(&mut mixer, AudioChannel::Pulse1, -1.0);
+        let [left, right] = mixer.stereo(LEVELS);
+        assert_close(left, reference.mix(LEVELS));
+        assert_close(
+            right,
+            reference.mix(only(&[
+                AudioChannel::Pulse2,
+                AudioChannel::Triangle,
+                AudioChannel::Noise,
+                AudioChannel::Dmc,
+            ])),
+        );
+
+        for channel in AudioChannel::ALL {
+            pan(&mut mixer, channel, -1.0);
+        }
+        let [left, right] = mixer.stereo(LEVELS);
+        assert_close(left, reference.mix(LEVELS));
+        assert_eq!(right, 0.0);
+    }
+}
@@ -1,21 +1,34 @@
+use crate::region::Region;
+
 use self::{
+    blip::BlipBuffer,
     dmc::DeltaModulationChannel,
     filters::Filter,
+    mixer::{AudioChannel, AudioOutput, ChannelSettings, Mixer},
     noise::NoiseChannel,
     pulse::{PulseChannel, PulseChannelId},
+    rate_control::{AudioBufferHealth, RateControlConfig},
+    ring::{audio_ring, AudioConsumer, AudioProducer},
     triangle::TriangleChannel,
 };
 
+mod blip;
 mod common;
 mod dmc;
 mod filters;
+pub mod mixer;
 mod noise;
 mod pulse;
+pub mod rate_control;
+pub mod ring;
 mod triangle;
 
-const BUFFER_SIZE: usize = 8 * 1024; // 2^14
-const BUFFER_MASK: u16 = (BUFFER_SIZE as u16) - 1;
-const CPU_FREQ: f64 = 1789772.5;
+const BUFFER_SIZE: usize = 8 * 1024; // in samples
+
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+// in APU cycles, rounded up since the counter is clocked on odd CPU cycles
+const NTSC_FRAME_COUNTER_STEPS: [u32; 5] = [3729, 7457, 11186, 14915, 18641];
+const PAL_FRAME_COUNTER_STEPS: [u32; 5] = [4157, 8314, 12470, 16627, 20783];
 
 #[derive(Clone, Copy)]
 enum FrameMode {
@@ -42,77 +55,78 @@ impl From<FrameMode> for u8 {
     }
 }
 
+// an output channel of the APU, the mixer output or a stem
+struct OutputChannel {
+    blip: BlipBuffer,
+    filters: [Filter; 3],
+}
+
+impl OutputChannel {
+    fn new(sample_rate: f64) -> Self {
+        OutputChannel {
+            blip: BlipBuffer::new(),
+            filters: [
+                Filter::new_high_pass(sample_rate as f32, 90.0),
+                Filter::new_high_pass(sample_rate as f32, 440.0),
+                Filter::new_low_pass(sample_rate as f32, 14_000.0),
+            ],
+        }
+    }
+
+    fn read_sample(&mut self, index: u32) -> f32 {
+        let sample = self.blip.read_sample(index);
+        let sample = self.filters[0].filter(sample);
+        let sample = self.filters[1].filter(sample);
+        self.filters[2].filter(sample)
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 pub struct APU {
+    region: Region,
+    sample_rate: f64,
     cycles_per_sample: f64,
-    buffer: Box<[f32; BUFFER_SIZE]>, // avoid stack overflow in WASM
-    front_ptr: u16,
-    back_ptr: u16,
+    // the sample clock restarts from this cycle and time (in samples) when the rate changes
+    clock_cycle: u32,
+    clock_time: f64,
+    rate_control: Option<RateControlConfig>,
+    frame_counter_steps: &'static [u32; 5],
+    producer: AudioProducer,
+    // read by `fill` until it is taken to be read from another thread
+    consumer: Option<AudioConsumer>,
     cycle: u32,
     frame_counter: u32,
     frame_interrupt: bool,
     frame_mode: FrameMode,
-    current_sample: Option<f32>,
+    // the last samples pushed, one per output channel, until they are pulled
+    current_frame: [f32; AudioChannel::COUNT],
+    has_frame: bool,
     samples_pushed: u32,
     irq_inhibit: bool,
-    prev_irq: bool,
     pulse1: PulseChannel,
     pulse2: PulseChannel,
     triangle: TriangleChannel,
     noise: NoiseChannel,
     dmc: DeltaModulationChannel,
-    filters: [Filter; 3],
+    mixer: Mixer,
+    output: AudioOutput,
+    outputs: Vec<OutputChannel>,
 }
 
-#[rustfmt::skip]
-const PULSE_MIXER_LOOKUP: [f32; 32] = [
-    0.0, 0.011609139, 0.02293948, 0.034000948,
-    0.044803, 0.05535466, 0.06566453, 0.07574082,
-    0.0855914, 0.09522375, 0.10464504, 0.11386215,
-    0.12288164, 0.1317098, 0.14035264, 0.14881596,
-    0.15710525, 0.16522588, 0.17318292, 0.18098126,
-    0.18862559, 0.19612046, 0.20347017, 0.21067894,
-    0.21775076, 0.2246895, 0.23149887, 0.23818247,
-    0.24474378, 0.25118607, 0.25751257, 0.26372638,
-];
-
-#[rustfmt::skip]
-const TRIANGLE_MIXER_LOOKUP: [f32; 204] = [
-    0.0, 0.006699824, 0.01334502, 0.019936256, 0.02647418, 0.032959443, 0.039392676, 0.0457745, 
-    0.052105535, 0.05838638, 0.064617634, 0.07079987, 0.07693369, 0.08301962, 0.08905826, 0.095050134, 
-    0.100995794, 0.10689577, 0.11275058, 0.118560754, 0.12432679, 0.13004918, 0.13572845, 0.14136505, 
-    0.1469595, 0.15251222, 0.1580237, 0.1634944, 0.16892476, 0.17431524, 0.17966628, 0.1849783, 
-    0.19025174, 0.19548698, 0.20068447, 0.20584463, 0.21096781, 0.21605444, 0.22110492, 0.2261196, 
-    0.23109888, 0.23604311, 0.24095272, 0.245828, 0.25066936, 0.2554771, 0.26025164, 0.26499328, 
-    0.26970237, 0.27437922, 0.27902418, 0.28363758, 0.28821972, 0.29277095, 0.29729152, 0.3017818, 
-    0.3062421, 0.31067267, 0.31507385, 0.31944588, 0.32378912, 0.32810378, 0.3323902, 0.3366486, 
-    0.3408793, 0.34508255, 0.34925863, 0.35340777, 0.35753027, 0.36162636, 0.36569634, 0.36974037, 
-    0.37375876, 0.37775174, 0.38171956, 0.38566244, 0.38958064, 0.39347437, 0.39734384, 0.4011893, 
-    0.405011, 0.40880907, 0.41258383, 0.41633546, 0.42006415, 0.42377013, 0.4274536, 0.43111476, 
-    0.43475384, 0.43837097, 0.44196644, 0.4455404, 0.449093, 0.45262453, 0.45613506, 0.4596249, 
-    0.46309412, 0.46654293, 0.46997157, 0.47338015, 0.47676894, 0.48013794, 0.48348752, 0.4868177, 
-    0.49012873, 0.4934207, 0.49669388, 0.49994832, 0.50318426, 0.50640184, 0.5096012, 0.51278245, 
-    0.51594585, 0.5190914, 0.5222195, 0.52533007, 0.52842325, 0.5314993, 0.53455836, 0.5376005, 
-    0.54062593, 0.5436348, 0.54662704, 0.54960304, 0.55256283, 0.55550647, 0.5584343, 0.56134623, 
-    0.5642425, 0.56712323, 0.5699885, 0.5728384, 0.5756732, 0.57849294, 0.5812977, 0.5840876, 
-    0.5868628, 0.58962345, 0.59236956, 0.59510136, 0.5978189, 0.6005223, 0.6032116, 0.605887, 
-    0.60854864, 0.6111966, 0.6138308, 0.61645156, 0.619059, 0.62165314, 0.624234, 0.62680185, 
-    0.6293567, 0.63189864, 0.6344277, 0.6369442, 0.63944805, 0.64193934, 0.64441824, 0.64688486, 
-    0.6493392, 0.6517814, 0.6542115, 0.65662974, 0.65903604, 0.6614306, 0.6638134, 0.66618466, 
-    0.66854435, 0.6708926, 0.67322946, 0.67555505, 0.67786944, 0.68017274, 0.68246496, 0.6847462, 
-    0.6870166, 0.6892762, 0.69152504, 0.6937633, 0.6959909, 0.69820803, 0.7004148, 0.7026111, 
-    0.7047972, 0.7069731, 0.7091388, 0.7112945, 0.7134401, 0.7155759, 0.7177018, 0.7198179, 
-    0.72192425, 0.72402096, 0.726108, 0.72818565, 0.7302538, 0.73231256, 0.73436195, 0.7364021, 
-    0.7384331, 0.7404549, 0.7424676, 0.7444713,
-];
-
 impl APU {
-    pub fn new(sample_rate: f64) -> APU {
-        APU {
-            cycles_per_sample: CPU_FREQ / sample_rate,
-            buffer: Box::new([0.0; BUFFER_SIZE]),
-            front_ptr: 0,
-            back_ptr: 0,
+    pub fn new(sample_rate: f64, region: Region) -> APU {
+        let (producer, consumer) = audio_ring(BUFFER_SIZE, AudioOutput::Mono.channels());
+
+        let mut apu = APU {
+            region,
+            sample_rate,
+            cycles_per_sample: region.cpu_frequency() / sample_rate,
+            clock_cycle: 0,
+            clock_time: 0.0,
+            rate_control: None,
+            frame_counter_steps: &NTSC_FRAME_COUNTER_STEPS,
+            producer,
+            consumer: Some(consumer),
             cycle: 0,
             frame_counter: 0,
             frame_interrupt: false,
@@ -122,45 +136,214 @@ impl APU {
             triangle: TriangleChannel::new(),
             noise: NoiseChannel::new(),
             dmc: DeltaModulationChannel::new(),
-            current_sample: None,
+            current_frame: [0.0; AudioChannel::COUNT],
+            has_frame: false,
             samples_pushed: 0,
             irq_inhibit: false,
-            prev_irq: false,
-            filters: [
-                Filter::new_high_pass(sample_rate as f32, 90.0),
-                Filter::new_high_pass(sample_rate as f32, 440.0),
-                Filter::new_low_pass(sample_rate as f32, 14_000.0),
-            ],
+            mixer: Mixer::new(),
+            output: AudioOutput::Mono,
+            outputs: vec![OutputChannel::new(sample_rate)],
+        };
+
+        apu.set_region(region);
+        apu
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cycles_per_sample = self.nominal_cycles_per_sample();
+        self.frame_counter_steps = match region {
+            Region::Ntsc | Region::Dendy => &NTSC_FRAME_COUNTER_STEPS,
+            Region::Pal => &PAL_FRAME_COUNTER_STEPS,
+        };
+
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+
+        // the sample count is derived from the cycle count
+        self.cycle = 0;
+        self.clock_cycle = 0;
+        self.clock_time = 0.0;
+        self.samples_pushed = 0;
+
+        for output in &mut self.outputs {
+            output.blip.clear();
         }
     }
 
-    fn get_sample(&mut self) -> f32 {
-        // https://www.nesdev.org/wiki/APU_Mixer
-        let p1 = self.pulse1.output();
-        let p2 = self.pulse2.output();
-        let t = self.triangle.output();
-        let n = self.noise.output();
-        let dmc = self.dmc.output();
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
 
-        let pulse_out = PULSE_MIXER_LOOKUP[(p1 + p2) as usize];
-        let tnd_out = TRIANGLE_MIXER_LOOKUP[(3 * t + 2 * n + dmc) as usize];
+    pub fn get_sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
 
-        let sample = pulse_out + tnd_out;
-        let sample = self.filters[0].filter(sample);
-        let sample = self.filters[1].filter(sample);
-        self.filters[2].filter(sample)
+    fn nominal_cycles_per_sample(&self) -> f64 {
+        self.region.cpu_frequency() / self.sample_rate
+    }
+
+    // position of the current cycle in output samples
+    fn sample_time(&self) -> f64 {
+        self.clock_time + (self.cycle - self.clock_cycle) as f64 / self.cycles_per_sample
+    }
+
+    fn set_cycles_per_sample(&mut self, cycles_per_sample: f64) {
+        self.clock_time = self.sample_time();
+        self.clock_cycle = self.cycle;
+        self.cycles_per_sample = cycles_per_sample;
+    }
+
+    pub fn get_rate_control(&self) -> Option<RateControlConfig> {
+        self.rate_control
+    }
+
+    /// the rate is adjusted each sample while the rate control is enabled,
+    /// it goes back to the nominal rate otherwise
+    pub fn set_rate_control(&mut self, config: Option<RateControlConfig>) {
+        self.rate_control = config;
+
+        if config.is_none() {
+            self.set_cycles_per_sample(self.nominal_cycles_per_sample());
+        }
+    }
+
+    pub fn get_buffer_health(&self) -> AudioBufferHealth {
+        let channels = self.outputs.len();
+
+        AudioBufferHealth {
+            buffered_frames: self.remaining_samples() / channels,
+            capacity_frames: self.producer.capacity() / channels,
+            target_frames: self.rate_control.map(|config| config.target_frames),
+            rate_ratio: self.nominal_cycles_per_sample() / self.cycles_per_sample,
+            underruns: self.producer.underruns(),
+            overruns: self.producer.overruns(),
+        }
+    }
+
+    pub fn get_channel_settings(&self, channel: AudioChannel) -> ChannelSettings {
+        self.mixer.get_settings(channel)
+    }
+
+    pub fn set_channel_settings(&mut self, channel: AudioChannel, settings: ChannelSettings) {
+        self.mixer.set_settings(channel, settings);
+    }
+
+    pub fn get_output(&self) -> AudioOutput {
+        self.output
     }
 
-    fn push_sample(&mut self) {
-        let sample = self.get_sample();
-        self.current_sample = Some(sample);
-        self.buffer[self.front_ptr as usize] = sample;
+    /// the buffered samples are dropped since their layout changes
+    pub fn set_output(&mut self, output: AudioOutput) {
+        if output == self.output {
+            return;
+        }
+
+        self.output = output;
+        self.outputs = (0..output.channels())
+            .map(|_| OutputChannel::new(self.sample_rate))
+            .collect();
+        self.has_frame = false;
+        self.producer.set_channels(output.channels());
+        self.clear_buffer();
+    }
+
+    /// moves the audio settings and the audio buffer of `other` to this APU,
+    /// a consumer taken from `other` keeps receiving the samples
+    pub fn take_audio_from(&mut self, other: &mut APU) {
+        std::mem::swap(&mut self.mixer, &mut other.mixer);
+        std::mem::swap(&mut self.output, &mut other.output);
+        std::mem::swap(&mut self.outputs, &mut other.outputs);
+        std::mem::swap(&mut self.producer, &mut other.producer);
+        std::mem::swap(&mut self.consumer, &mut other.consumer);
+        self.rate_control = other.rate_control;
+    }
+
+    /// the consumer end of the audio buffer, to read the samples from another thread,
+    /// `fill` doesn't output anything afterwards
+    pub fn take_consumer(&mut self) -> Option<AudioConsumer> {
+        self.consumer.take()
+    }
+
+    fn levels(&self) -> [u8; AudioChannel::COUNT] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+
+    // the mixer output changes are recorded at CPU cycle resolution
+    fn update_outputs(&mut self) {
+        let time = self.sample_time();
+        let levels = self.levels();
+
+        match self.output {
+            AudioOutput::Mono => {
+                let amplitude = self.mixer.mix(levels);
+                self.outputs[0].blip.set_amplitude(time, amplitude);
+            }
+            AudioOutput::Stereo => {
+                let sides = self.mixer.stereo(levels);
+
+                for (output, amplitude) in self.outputs.iter_mut().zip(sides) {
+                    output.blip.set_amplitude(time, amplitude);
+                }
+            }
+            AudioOutput::Stems => {
+                let stems = self.mixer.stems(levels);
+
+                for (output, amplitude) in self.outputs.iter_mut().zip(stems) {
+                    output.blip.set_amplitude(time, amplitude);
+                }
+            }
+        }
+    }
+
+    fn push_samples(&mut self) {
+        let channels = self.outputs.len();
+
+        for (i, output) in self.outputs.iter_mut().enumerate() {
+            self.current_frame[i] = output.read_sample(self.samples_pushed);
+        }
+
+        self.producer.push(&self.current_frame[..channels]);
+        self.has_frame = true;
         self.samples_pushed += 1;
-        self.front_ptr = (self.front_ptr + 1) & BUFFER_MASK;
+
+        if let Some(config) = self.rate_control {
+            let buffered_frames = self.remaining_samples() / channels;
+            let ratio = config.rate_ratio(buffered_frames);
+            self.set_cycles_per_sample(self.nominal_cycles_per_sample() / ratio);
+        }
+    }
+
+    /// the samples pushed since the last call, one per output channel
+    pub fn pull_frame(&mut self) -> Option<&[f32]> {
+        if std::mem::take(&mut self.has_frame) {
+            Some(&self.current_frame[..self.outputs.len()])
+        } else {
+            None
+        }
     }
 
-    pub fn pull_sample(&mut self) -> Option<f32> {
-        self.current_sample.take()
+    // https://www.nesdev.org/wiki/CPU_power_up_state#After_reset
+    pub fn soft_reset(&mut self) {
+        self.write(0x4015, 0);
+
+        // $4017 keeps its value but is written again
+        let frame_mode = match self.frame_mode {
+            FrameMode::FourStep => 0,
+            FrameMode::FiveStep => 0b1000_0000,
+        };
+        let irq_inhibit = if self.irq_inhibit { 0b0100_0000 } else { 0 };
+        self.write(0x4017, frame_mode | irq_inhibit);
+        self.frame_interrupt = false;
+
+        self.triangle.reset();
+        self.dmc.reset();
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
@@ -201,6 +384,17 @@ impl APU {
     }
 
     pub fn read(&mut self, addr: u16) -> u8 {
+        let val = self.peek(addr);
+
+        if addr == 0x4015 {
+            self.frame_interrupt = false;
+        }
+
+        val
+    }
+
+    /// reads a register without clearing the frame interrupt flag
+    pub fn peek(&self, addr: u16) -> u8 {
         match addr {
             0x4015 => {
                 let mut val = 0;
@@ -232,8 +426,6 @@ impl APU {
                     val |= 128;
                 }
 
-                self.frame_interrupt = false;
-
                 val
             }
             _ => 0,
@@ -241,7 +433,7 @@ impl APU {
     }
 
     fn get_sample_count(&self) -> u32 {
-        (self.cycle as f64 / self.cycles_per_sample) as u32
+        self.sample_time() as u32
     }
 
     pub fn step(&mut self) {
@@ -260,14 +452,16 @@ impl APU {
             let mut quarter_frame = false;
             let mut half_frame = false;
 
+            let [step1, step2, step3, step4, step5] = *self.frame_counter_steps;
+
             match self.frame_counter {
-                3729 => quarter_frame = true,
-                7457 => {
+                c if c == step1 => quarter_frame = true,
+                c if c == step2 => {
                     quarter_frame = true;
                     half_frame = true;
                 }
-                11186 => quarter_frame = true,
-                14915 => {
+                c if c == step3 => quarter_frame = true,
+                c if c == step4 => {
                     if matches!(self.frame_mode, FrameMode::FourStep) {
                         quarter_frame = true;
                         half_frame = true;
@@ -277,7 +471,7 @@ impl APU {
                         }
                     }
                 }
-                18641 => {
+                c if c == step5 => {
                     // this only happens in 5 step mode
                     quarter_frame = true;
                     half_frame = true;
@@ -304,58 +498,106 @@ impl APU {
             }
         }
 
+        self.update_outputs();
+
         if self.samples_pushed != next_sample_count {
-            self.push_sample();
+            self.push_samples();
         }
     }
 
-    pub fn remaining_samples(&self) -> u16 {
-        if self.front_ptr >= self.back_ptr {
-            self.front_ptr - self.back_ptr
-        } else {
-            BUFFER_SIZE as u16 - self.back_ptr + self.front_ptr
+    /// CPU cycles until the APU may interrupt or stall the CPU or output a sample
+    pub fn cycles_until_event(&self) -> u32 {
+        if self.dmc.is_active() {
+            return 0;
         }
+
+        let next_sample = (self.clock_cycle as f64
+            + ((self.samples_pushed + 1) as f64 - self.clock_time) * self.cycles_per_sample)
+            as u32;
+        let sample_cycles = next_sample.saturating_sub(self.cycle);
+
+        let irq_step = match self.frame_mode {
+            FrameMode::FourStep if !self.irq_inhibit => Some(self.frame_counter_steps[3]),
+            FrameMode::FourStep => None,
+            FrameMode::FiveStep => Some(self.frame_counter_steps[4]),
+        };
+
+        // the frame counter is clocked every other CPU cycle
+        let irq_cycles =
+            irq_step.map_or(u32::MAX, |step| step.saturating_sub(self.frame_counter) * 2);
+
+        sample_cycles.min(irq_cycles).saturating_sub(1)
+    }
+
+    /// in samples, a frame holds one sample per output channel
+    pub fn remaining_samples(&self) -> usize {
+        self.producer.len()
     }
 
     pub fn fill(&mut self, buffer: &mut [f32]) {
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..buffer.len().min(self.remaining_samples() as usize) {
-            buffer[i] = self.buffer[self.back_ptr as usize];
-            self.back_ptr = (self.back_ptr + 1) & BUFFER_MASK;
+        if let Some(consumer) = &mut self.consumer {
+            consumer.pop(buffer);
         }
     }
 
+    /// when the consumer was taken, the samples are dropped on its next read
     pub fn clear_buffer(&mut self) {
-        self.front_ptr = 0;
-        self.back_ptr = 0;
-        self.buffer.fill(0.0);
+        match &mut self.consumer {
+            Some(consumer) => consumer.clear(),
+            None => self.producer.request_clear(),
+        }
     }
 
-    pub fn is_asserting_irq(&mut self) -> bool {
-        let irq = self.frame_interrupt || self.dmc.interrupt_flag;
-        let edge = irq && !self.prev_irq;
-        self.prev_irq = irq;
+    // the IRQ line stays asserted until the flags are acknowledged
+    pub fn is_asserting_irq(&self) -> bool {
+        self.frame_interrupt || self.dmc.interrupt_flag
+    }
 
-        edge
+    /// address of the byte the DMC needs, the DMA unit reads it on behalf of the DMC
+    pub fn get_dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.get_dma_request()
     }
 
-    pub fn is_stalling_cpu(&mut self) -> bool {
-        if self.dmc.cpu_stall > 0 {
-            self.dmc.cpu_stall -= 1;
-            true
-        } else {
-            false
-        }
+    pub fn push_dmc_dma_response(&mut self, val: u8) {
+        self.dmc.set_dma_response(val);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // hack to avoid having to pass a mutable reference of the bus to the DMC
-    // when it needs to read from memory
+    const EPSILON: f64 = 1e-9;
 
-    pub fn pull_memory_read_request(&mut self) -> Option<u16> {
-        self.dmc.memory_read_request.take()
+    fn run(apu: &mut APU, cycles: u32) {
+        for _ in 0..cycles {
+            apu.step();
+        }
     }
 
-    pub fn push_memory_read_response(&mut self, val: u8) {
-        self.dmc.set_memory_read_response(val);
+    #[test]
+    fn changing_the_rate_keeps_the_sample_time() {
+        let mut apu = APU::new(44100.0, Region::Ntsc);
+        let nominal = apu.nominal_cycles_per_sample();
+        run(&mut apu, 1000);
+
+        let time = apu.sample_time();
+        assert!((time - 1000.0 / nominal).abs() < EPSILON);
+
+        // the new rate only applies to the following cycles
+        let faster = nominal / 1.005;
+        apu.set_cycles_per_sample(faster);
+        assert_eq!(apu.sample_time(), time);
+
+        run(&mut apu, 1000);
+        let time = time + 1000.0 / faster;
+        assert!((apu.sample_time() - time).abs() < EPSILON);
+        assert_eq!(apu.get_sample_count(), time as u32);
+
+        // disabling the rate control goes back to the nominal rate from there
+        apu.set_rate_control(None);
+        assert_eq!(apu.sample_time(), time);
+        run(&mut apu, 1000);
+        assert!((apu.sample_time() - (time + 1000.0 / nominal)).abs() < EPSILON);
     }
 }
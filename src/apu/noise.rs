@@ -1,13 +1,20 @@
+use crate::region::Region;
+
 use super::common::{Envelope, LengthCounter, Timer};
 
-const NOISE_PERIOD_TABLE: [u16; 16] = [
+const NTSC_NOISE_PERIOD_TABLE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
 
+const PAL_NOISE_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
 pub struct NoiseChannel {
     enabled: bool,
     shift_register: u16,
     mode: bool,
+    period_table: &'static [u16; 16],
     length_counter: LengthCounter,
     envelope: Envelope,
     timer: Timer,
@@ -22,9 +29,17 @@ impl NoiseChannel {
             timer: Timer::default(),
             shift_register: 1,
             mode: false,
+            period_table: &NTSC_NOISE_PERIOD_TABLE,
         }
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.period_table = match region {
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIOD_TABLE,
+            Region::Pal => &PAL_NOISE_PERIOD_TABLE,
+        };
+    }
+
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
 
@@ -64,7 +79,7 @@ impl NoiseChannel {
             }
             0x400E => {
                 self.mode = val & 0b1000_0000 != 0;
-                self.timer.period = NOISE_PERIOD_TABLE[(val & 0b1111) as usize];
+                self.timer.period = self.period_table[(val & 0b1111) as usize];
             }
             0x400F => {
                 self.length_counter.set(val >> 3);
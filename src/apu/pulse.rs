@@ -106,14 +106,15 @@ impl PulseChannel {
     fn sweep_target_period(&self) -> u16 {
         let change_amount = self.timer.period >> self.sweep_shift;
 
-        // TODO: Handle difference between pulse 1 and 2
-
         if self.sweep_negate {
-            if change_amount > self.timer.period {
-                0
-            } else {
-                self.timer.period - change_amount
-            }
+            // pulse 1 adds the ones' complement of the change amount,
+            // pulse 2 adds the two's complement
+            let change_amount = match self.id {
+                PulseChannelId::Pulse1 => change_amount + 1,
+                PulseChannelId::Pulse2 => change_amount,
+            };
+
+            self.timer.period.saturating_sub(change_amount)
         } else {
             self.timer.period + change_amount
         }
@@ -0,0 +1,75 @@
+// dynamic rate control: the sample rate is nudged by a fraction of a percent depending on how
+// full the audio buffer is, so that a frontend paced by the display still produces exactly as
+// many samples as the audio device consumes, the pitch change is not audible
+// see "Dynamic Rate Control for Retro Game Emulators" by Hans-Kristian Arntzen
+
+const DEFAULT_TARGET_FRAMES: usize = 2048;
+const DEFAULT_MAX_DELTA: f64 = 0.005;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateControlConfig {
+    /// number of buffered frames to aim for, usually twice the size of the audio callback
+    pub target_frames: usize,
+    /// maximum relative change of the sample rate
+    pub max_delta: f64,
+}
+
+impl Default for RateControlConfig {
+    fn default() -> Self {
+        RateControlConfig {
+            target_frames: DEFAULT_TARGET_FRAMES,
+            max_delta: DEFAULT_MAX_DELTA,
+        }
+    }
+}
+
+impl RateControlConfig {
+    /// ratio between the adjusted and the nominal sample rate, above 1.0 when
+    /// the buffer is below its target and below 1.0 when it is above
+    pub fn rate_ratio(&self, buffered_frames: usize) -> f64 {
+        let fill = buffered_frames as f64 / self.target_frames.max(1) as f64;
+        let delta = self.max_delta * (1.0 - fill);
+
+        1.0 + delta.clamp(-self.max_delta, self.max_delta)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioBufferHealth {
+    /// frames waiting to be read by `fill_audio_buffer`
+    pub buffered_frames: usize,
+    /// maximum number of frames the buffer can hold
+    pub capacity_frames: usize,
+    /// None when the rate control is disabled
+    pub target_frames: Option<usize>,
+    /// ratio between the current and the nominal sample rate
+    pub rate_ratio: f64,
+    /// reads that asked for more samples than were buffered, since the buffer was cleared
+    pub underruns: u32,
+    /// frames dropped because the buffer was full, since the buffer was cleared
+    pub overruns: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-12;
+
+    #[test]
+    fn rate_ratio() {
+        let config = RateControlConfig {
+            target_frames: 1000,
+            max_delta: 0.005,
+        };
+        let ratio = |frames| config.rate_ratio(frames);
+
+        assert_eq!(ratio(1000), 1.0);
+        assert!((ratio(500) - 1.0025).abs() < EPSILON);
+        assert!((ratio(1500) - 0.9975).abs() < EPSILON);
+        // the change is at most max_delta
+        assert!((ratio(0) - 1.005).abs() < EPSILON);
+        assert!((ratio(2000) - 0.995).abs() < EPSILON);
+        assert!((ratio(10_000) - 0.995).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,304 @@
+// single producer single consumer ring buffer of samples, the emulation pushes the samples
+// on its thread while the audio callback pops them on another one without locking,
+// the samples are stored as the bits of atomic integers so no unsafe code is needed
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Arc,
+};
+
+struct Shared {
+    samples: Box<[AtomicU32]>,
+    mask: usize,
+    // positions of the next write and of the next read, they only ever increase (wrapping),
+    // each one is written by a single side
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    // samples per frame, set by the producer
+    channels: AtomicUsize,
+    // the consumer drops the buffered samples on its next read
+    clear_requested: AtomicBool,
+    underruns: AtomicU32,
+    overruns: AtomicU32,
+}
+
+impl Shared {
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        head.wrapping_sub(tail)
+    }
+}
+
+/// `capacity` is in samples and rounded up to a power of two
+pub fn audio_ring(capacity: usize, channels: usize) -> (AudioProducer, AudioConsumer) {
+    let capacity = capacity.next_power_of_two();
+    let shared = Arc::new(Shared {
+        samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        channels: AtomicUsize::new(channels),
+        clear_requested: AtomicBool::new(false),
+        underruns: AtomicU32::new(0),
+        overruns: AtomicU32::new(0),
+    });
+
+    (
+        AudioProducer {
+            shared: shared.clone(),
+        },
+        AudioConsumer { shared },
+    )
+}
+
+pub struct AudioProducer {
+    shared: Arc<Shared>,
+}
+
+impl AudioProducer {
+    /// the frame is dropped when the buffer is full, unlike the previous buffer which
+    /// dropped its oldest samples: the producer can't move the read position without
+    /// racing with the consumer, the overrun is counted instead
+    pub fn push(&mut self, frame: &[f32]) -> bool {
+        let shared = &*self.shared;
+
+        if shared.len() + frame.len() > shared.samples.len() {
+            shared.overruns.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let head = shared.head.load(Ordering::Relaxed);
+
+        for (i, sample) in frame.iter().enumerate() {
+            let index = head.wrapping_add(i) & shared.mask;
+            shared.samples[index].store(sample.to_bits(), Ordering::Relaxed);
+        }
+
+        shared
+            .head
+            .store(head.wrapping_add(frame.len()), Ordering::Release);
+
+        true
+    }
+
+    /// the buffer should be cleared since the layout of the buffered frames changes
+    pub fn set_channels(&mut self, channels: usize) {
+        self.shared.channels.store(channels, Ordering::Relaxed);
+    }
+
+    /// the samples buffered are dropped when the consumer reads next
+    pub fn request_clear(&mut self) {
+        self.shared.clear_requested.store(true, Ordering::Release);
+    }
+
+    /// buffered samples
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.samples.len()
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    pub fn overruns(&self) -> u32 {
+        self.shared.overruns.load(Ordering::Relaxed)
+    }
+}
+
+pub struct AudioConsumer {
+    shared: Arc<Shared>,
+}
+
+impl AudioConsumer {
+    /// returns the number of samples written, only whole frames are read so a `buffer`
+    /// whose length isn't a multiple of `channels` is never filled, the rest of it is left
+    /// untouched and counts as an underrun
+    pub fn pop(&mut self, buffer: &mut [f32]) -> usize {
+        if self.shared.clear_requested.swap(false, Ordering::Acquire) {
+            self.clear();
+        }
+
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let count = buffer.len().min(shared.len());
+        let count = count - count % self.channels().max(1);
+
+        for (i, sample) in buffer[..count].iter_mut().enumerate() {
+            let index = tail.wrapping_add(i) & shared.mask;
+            *sample = f32::from_bits(shared.samples[index].load(Ordering::Relaxed));
+        }
+
+        shared
+            .tail
+            .store(tail.wrapping_add(count), Ordering::Release);
+
+        if count < buffer.len() {
+            shared.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        count
+    }
+
+    /// drops the buffered samples and resets the counters
+    pub fn clear(&mut self) {
+        let shared = &*self.shared;
+        let head = shared.head.load(Ordering::Acquire);
+        shared.tail.store(head, Ordering::Release);
+        shared.underruns.store(0, Ordering::Relaxed);
+        shared.overruns.store(0, Ordering::Relaxed);
+    }
+
+    /// samples per frame, the samples of a frame are interleaved
+    pub fn channels(&self) -> usize {
+        self.shared.channels.load(Ordering::Relaxed)
+    }
+
+    /// buffered samples
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    pub fn overruns(&self) -> u32 {
+        self.shared.overruns.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let (mut producer, mut consumer) = audio_ring(8, 2);
+        let mut buffer = [0.0; 6];
+
+        for i in 0..10 {
+            let first = (i * 6) as f32;
+            let frames = [
+                first,
+                first + 1.0,
+                first + 2.0,
+                first + 3.0,
+                first + 4.0,
+                first + 5.0,
+            ];
+
+            for frame in frames.chunks(2) {
+                assert!(producer.push(frame));
+            }
+
+            assert_eq!(consumer.pop(&mut buffer), 6);
+            assert_eq!(buffer, frames);
+        }
+
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.underruns(), 0);
+        assert_eq!(consumer.overruns(), 0);
+    }
+
+    #[test]
+    fn drops_the_newest_frame_when_full() {
+        let (mut producer, mut consumer) = audio_ring(6, 2);
+        assert_eq!(producer.capacity(), 8);
+
+        for i in 0..4 {
+            assert!(producer.push(&[i as f32; 2]));
+        }
+
+        assert!(!producer.push(&[4.0; 2]));
+        assert_eq!(producer.overruns(), 1);
+        assert_eq!(producer.len(), 8);
+
+        let mut buffer = [-1.0; 10];
+        assert_eq!(consumer.pop(&mut buffer), 8);
+        assert_eq!(buffer, [0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, -1.0, -1.0]);
+        assert_eq!(consumer.underruns(), 1);
+    }
+
+    #[test]
+    fn reads_whole_frames() {
+        let (mut producer, mut consumer) = audio_ring(8, 2);
+
+        for i in 0..3 {
+            assert!(producer.push(&[i as f32, -(i as f32)]));
+        }
+
+        let mut buffer = [9.0; 5];
+        assert_eq!(consumer.pop(&mut buffer), 4);
+        assert_eq!(buffer, [0.0, -0.0, 1.0, -1.0, 9.0]);
+        assert_eq!(consumer.underruns(), 1);
+
+        // the last frame is still buffered whole
+        assert_eq!(consumer.len(), 2);
+        assert_eq!(consumer.pop(&mut buffer[..2]), 2);
+        assert_eq!(buffer[..2], [2.0, -2.0]);
+    }
+
+    #[test]
+    fn clear_requested_by_the_producer() {
+        let (mut producer, mut consumer) = audio_ring(8, 1);
+        producer.push(&[1.0, 2.0, 3.0]);
+        producer.request_clear();
+
+        // the samples stay buffered until the next read
+        assert_eq!(producer.len(), 3);
+
+        let mut buffer = [0.0; 2];
+        assert_eq!(consumer.pop(&mut buffer), 0);
+        assert!(consumer.is_empty());
+
+        producer.push(&[4.0, 5.0]);
+        assert_eq!(consumer.pop(&mut buffer), 2);
+        assert_eq!(buffer, [4.0, 5.0]);
+    }
+
+    #[test]
+    fn producer_and_consumer_on_two_threads() {
+        const FRAMES: usize = 100_000;
+        let (mut producer, mut consumer) = audio_ring(64, 2);
+
+        let thread = std::thread::spawn(move || {
+            for i in 0..FRAMES {
+                let frame = [i as f32, -(i as f32)];
+                while !producer.push(&frame) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut buffer = [0.0; 2];
+        let mut next = 0;
+
+        while next < FRAMES {
+            // the producer only pushes whole frames
+            match consumer.pop(&mut buffer) {
+                2 => {
+                    assert_eq!(buffer, [next as f32, -(next as f32)]);
+                    next += 1;
+                }
+                0 => std::thread::yield_now(),
+                count => panic!("popped {count} samples of a frame"),
+            }
+        }
+
+        thread.join().unwrap();
+        assert!(consumer.is_empty());
+    }
+}
@@ -23,6 +23,10 @@ impl TriangleChannel {
         Self::default()
     }
 
+    pub fn reset(&mut self) {
+        self.duty_cycle = 0;
+    }
+
     pub fn write(&mut self, addr: u16, val: u8) {
         match addr {
             0x4008 => {
@@ -3,7 +3,7 @@ use bitflags::bitflags;
 use crate::savestate::{self, SaveStateError};
 
 bitflags! {
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct JoypadStatus: u8 {
         const A = 0b0000_0001;
         const B = 0b0000_0010;
@@ -20,6 +20,8 @@ pub struct Joypad {
     strobe: bool,
     index: u8,
     pub status: JoypadStatus,
+    // when set, overrides `status` until the next frame (used by movies)
+    latched: Option<JoypadStatus>,
 }
 
 impl Joypad {
@@ -29,20 +31,29 @@ impl Joypad {
             strobe: false,
             index: 0,
             status: JoypadStatus::empty(),
+            latched: None,
         }
     }
 
     pub fn read(&mut self) -> u8 {
-        if self.index > 7 {
-            return 1;
-        }
-
-        let pressed = self.status.bits() & (1 << self.index) != 0;
+        let val = self.peek();
 
         if !self.strobe && self.index <= 7 {
             self.index += 1;
         }
 
+        val
+    }
+
+    /// next bit returned by `read`, without shifting
+    pub fn peek(&self) -> u8 {
+        if self.index > 7 {
+            return 1;
+        }
+
+        let status = self.latched.unwrap_or(self.status);
+        let pressed = status.bits() & (1 << self.index) != 0;
+
         if pressed {
             1
         } else {
@@ -61,13 +72,24 @@ impl Joypad {
     pub fn update(&mut self, val: u8) {
         *self.status.0.bits_mut() = val;
     }
+
+    /// the game sees `status` until unlatched, regardless of updates to `self.status`
+    pub fn latch(&mut self, status: JoypadStatus) {
+        self.latched = Some(status);
+    }
+
+    pub fn unlatch(&mut self) {
+        self.latched = None;
+    }
 }
 
 impl savestate::Save for Joypad {
     fn save(&self, parent: &mut savestate::Section) {
         parent.data.write_bool(self.strobe);
         parent.data.write_u8(self.index);
-        parent.data.write_u8(self.status.bits());
+        parent
+            .data
+            .write_u8(self.latched.unwrap_or(self.status).bits());
     }
 
     fn load(&mut self, parent: &mut savestate::Section) -> Result<(), SaveStateError> {
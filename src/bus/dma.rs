@@ -0,0 +1,223 @@
+// DMA units, the OAM DMA copies a page to OAM through $2004 and the DMC DMA fetches
+// the samples of the DMC, both halt the CPU on a read cycle and take over the bus
+// https://www.nesdev.org/wiki/DMA
+
+use super::Bus;
+use crate::cdl::PrgFlags;
+
+// the DMC needs a halt cycle and a dummy cycle before it can read
+const DMC_DMA_WAIT_CYCLES: u8 = 2;
+
+impl Bus {
+    // the DMA units can only halt the CPU on a read cycle
+    pub(super) fn halt_for_dma(&mut self, addr: u16) {
+        if self.oam_dma_page.is_some() || self.apu.get_dmc_dma_request().is_some() {
+            self.run_until_access();
+            self.run_dma(addr);
+        }
+    }
+
+    /// cycles stolen from the CPU by the DMA units since the last call,
+    /// the PPU and the APU already ran during these cycles
+    pub(crate) fn take_dma_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.dma_cycles)
+    }
+
+    // runs the pending transfers cycle by cycle, `addr` is the address of the halted CPU read
+    // which keeps being read while the DMA units are waiting, the CPU makes the read once they are done
+    fn run_dma(&mut self, addr: u16) {
+        // the controllers are only clocked by the first of consecutive reads of their register,
+        // other registers see every read: each repeated read of $2007 increments the VRAM address
+        let repeat_reads = !matches!(addr, 0x4016 | 0x4017);
+        let mut halted_read = false;
+
+        let mut oam_page = self.oam_dma_page.take();
+        let mut oam_cycles = 0u16;
+        let mut oam_latch = 0u8;
+        let mut dmc_wait = self.apu.get_dmc_dma_request().map(|_| DMC_DMA_WAIT_CYCLES);
+
+        // halt cycle
+        self.dma_halted_read(addr, repeat_reads, &mut halted_read);
+        self.end_dma_cycle(&mut dmc_wait);
+
+        loop {
+            // the DMC can request a byte while a sprite transfer is running,
+            // the sprite transfer cycles count as its halt and dummy cycles
+            if dmc_wait.is_none() && self.apu.get_dmc_dma_request().is_some() {
+                dmc_wait = Some(DMC_DMA_WAIT_CYCLES);
+            }
+
+            if oam_page.is_none() && dmc_wait.is_none() {
+                break;
+            }
+
+            if self.is_dma_get_cycle() {
+                match (dmc_wait, oam_page) {
+                    (Some(0), _) => {
+                        if let Some(dmc_addr) = self.apu.get_dmc_dma_request() {
+                            let val = self.read_unlogged(dmc_addr);
+                            self.ppu.rom.log_prg(dmc_addr, PrgFlags::PCM_DATA);
+                            self.apu.push_dmc_dma_response(val);
+                        }
+
+                        dmc_wait = None;
+                        halted_read = false;
+                    }
+                    (_, Some(page)) if oam_cycles & 1 == 0 => {
+                        oam_latch = self.read((page as u16) << 8 | oam_cycles >> 1);
+                        oam_cycles += 1;
+                        halted_read = false;
+                    }
+                    _ => self.dma_halted_read(addr, repeat_reads, &mut halted_read),
+                }
+            } else if oam_page.is_some() && oam_cycles & 1 == 1 {
+                self.ppu.catch_up();
+                self.ppu.write_register(0x2004, oam_latch);
+                oam_cycles += 1;
+                halted_read = false;
+
+                if oam_cycles == 512 {
+                    oam_page = None;
+                }
+            } else {
+                // alignment cycle
+                self.dma_halted_read(addr, repeat_reads, &mut halted_read);
+            }
+
+            self.end_dma_cycle(&mut dmc_wait);
+        }
+
+        self.update_event_deadline();
+    }
+
+    // the DMA units read on get cycles and write on put cycles, they alternate every CPU cycle
+    fn is_dma_get_cycle(&self) -> bool {
+        // the halted read is the last access of the instruction counted
+        let cycle = self.instruction_start + self.instruction_accesses - 1 + self.dma_cycles;
+        cycle & 1 == 1
+    }
+
+    fn dma_halted_read(&mut self, addr: u16, repeat_reads: bool, halted_read: &mut bool) {
+        if repeat_reads || !*halted_read {
+            if (0x2000..=0x3fff).contains(&addr) {
+                self.ppu.catch_up();
+            }
+
+            self.read_byte_inner(addr);
+        }
+
+        *halted_read = true;
+    }
+
+    fn end_dma_cycle(&mut self, dmc_wait: &mut Option<u8>) {
+        if let Some(wait) = dmc_wait {
+            *wait = wait.saturating_sub(1);
+        }
+
+        self.run(1);
+        self.dma_cycles += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::{memory::Memory, CpuMode},
+        tests::nrom,
+    };
+
+    fn bus() -> Bus {
+        let mut bus = Bus::new(nrom(), 44100.0);
+        bus.set_cpu_mode(CpuMode::Cycle);
+        bus
+    }
+
+    // the DMC requests its first byte once its sample is started
+    fn request_dmc_byte(bus: &mut Bus) {
+        bus.write_byte(0x4013, 0x01);
+        bus.write_byte(0x4015, 0x10);
+
+        while bus.apu.get_dmc_dma_request().is_none() {
+            bus.run(1);
+        }
+    }
+
+    // `STA $4014` then a read, the instruction starts at `cycle`
+    fn oam_dma_cycles(cycle: u32) -> u32 {
+        let mut bus = bus();
+
+        for i in 0..=255u8 {
+            bus.write_byte(0x0200 + i as u16, i);
+        }
+
+        bus.begin_instruction(cycle);
+        bus.write_byte(0x4014, 0x02);
+        bus.read_byte(0x0000);
+
+        for i in 0..=255u8 {
+            assert_eq!(bus.ppu.peek_oam(i), i);
+        }
+
+        bus.take_dma_cycles()
+    }
+
+    // the DMC halts the CPU at the start of `cycle` and reads on the next get cycle
+    fn dmc_dma_read(cycle: u32, addr: u16, bus: &mut Bus) -> (u32, u8) {
+        request_dmc_byte(bus);
+        bus.begin_instruction(cycle);
+        let val = bus.read_byte(addr);
+        (bus.take_dma_cycles(), val)
+    }
+
+    // https://www.nesdev.org/wiki/DMA#OAM_DMA
+    #[test]
+    fn oam_dma_aligns_on_a_get_cycle() {
+        // halted on a get cycle, an alignment cycle is needed before the first read
+        assert_eq!(oam_dma_cycles(10), 514);
+        // halted on a put cycle
+        assert_eq!(oam_dma_cycles(11), 513);
+    }
+
+    // https://www.nesdev.org/wiki/DMA#DMC_DMA
+    #[test]
+    fn dmc_dma_aligns_on_a_get_cycle() {
+        // halt, dummy, alignment and get cycles
+        assert_eq!(dmc_dma_read(10, 0x0000, &mut bus()).0, 4);
+        // halt, dummy and get cycles
+        assert_eq!(dmc_dma_read(11, 0x0000, &mut bus()).0, 3);
+    }
+
+    // the halted reads are consecutive so the controller is only clocked once by them,
+    // the read of the CPU clocks it again: a single bit is lost
+    #[test]
+    fn dmc_dma_deletes_one_controller_bit() {
+        for cycle in [10, 11] {
+            let mut bus = bus();
+            bus.joypad1.update(0b0000_0101);
+            bus.write_byte(0x4016, 1);
+            bus.write_byte(0x4016, 0);
+
+            let (_, val) = dmc_dma_read(cycle, 0x4016, &mut bus);
+            let bits = (0..3).map(|_| bus.read_byte(0x4016)).collect::<Vec<_>>();
+
+            assert_eq!(val, 0);
+            assert_eq!(bits, [1, 0, 0]);
+        }
+    }
+
+    // every halted read of PPUDATA increments the VRAM address
+    #[test]
+    fn dmc_dma_repeats_ppudata_reads() {
+        for cycle in [10, 11] {
+            let mut bus = bus();
+            bus.write_byte(0x2006, 0x20);
+            bus.write_byte(0x2006, 0x00);
+
+            let (dma_cycles, _) = dmc_dma_read(cycle, 0x2007, &mut bus);
+
+            // one halted read on every DMA cycle but the get cycle, then the read of the CPU
+            assert_eq!(bus.ppu.get_vram_addr(), 0x2000 + dma_cycles as u16);
+        }
+    }
+}
@@ -2,10 +2,23 @@ use self::controller::Joypad;
 use super::apu::APU;
 use super::ppu::PPU;
 use crate::{
-    cpu::{memory::Memory, rom::ROM},
+    cheats::Cheats,
+    cpu::{
+        memory::Memory,
+        opcodes::{AddressingMode, OPCODES},
+        rom::ROM,
+        CpuMode,
+    },
+    debugger::{AccessKind, AddressSpace, Debugger},
+    event_log::EventKind,
+    power_on::PowerOnConfig,
+    ram_search::RamSnapshot,
+    region::Region,
     savestate::{self, SaveStateError},
+    tracer::Tracer,
 };
 pub mod controller;
+mod dma;
 
 #[allow(clippy::upper_case_acronyms)]
 pub struct RAM([u8; 0x800]);
@@ -38,49 +51,401 @@ pub struct Bus {
     pub apu: APU,
     pub joypad1: Joypad,
     pub joypad2: Joypad,
-    pub dma_transfer: bool,
+    pub debugger: Option<Box<Debugger>>,
+    pub tracer: Option<Box<Tracer>>,
+    pub cheats: Cheats,
+    region: Region,
+    cpu_mode: CpuMode,
+    ppu_clock_remainder: u32,
+    // CPU cycles the PPU and the APU lag behind the CPU
+    pending_cycles: u32,
+    // CPU cycle the current instruction started at
+    instruction_start: u32,
+    // bus accesses made by the current instruction, each access takes one CPU cycle
+    instruction_accesses: u32,
+    // accesses of the current instruction already included in a catch-up
+    synced_accesses: u32,
+    // pending cycles after which the PPU or the APU must catch up
+    event_deadline: u32,
+    // page copied to OAM once the CPU is halted
+    oam_dma_page: Option<u8>,
+    // cycles stolen from the CPU by the DMA units since the last call to `take_dma_cycles`
+    dma_cycles: u32,
+    // set when the interrupt lines must not be polled at the end of the current instruction
+    skip_interrupt_poll: bool,
+    // NMI detected by the last poll, latched until the CPU services it
+    nmi_polled: bool,
+    // level of the IRQ line during the last poll
+    irq_polled: bool,
 }
 
 impl Bus {
     pub fn new(rom: ROM, sample_rate: f64) -> Bus {
+        let region = rom.cart.region;
+
         Bus {
             ram: RAM([0; 0x800]),
             ppu: PPU::new(rom),
-            apu: APU::new(sample_rate),
+            apu: APU::new(sample_rate, region),
             joypad1: Joypad::new(),
             joypad2: Joypad::new(),
-            dma_transfer: false,
+            debugger: None,
+            tracer: None,
+            cheats: Cheats::new(),
+            region,
+            cpu_mode: CpuMode::Instruction,
+            ppu_clock_remainder: 0,
+            pending_cycles: 0,
+            instruction_start: 0,
+            instruction_accesses: 0,
+            synced_accesses: 0,
+            event_deadline: 0,
+            oam_dma_page: None,
+            dma_cycles: 0,
+            skip_interrupt_poll: false,
+            nmi_polled: false,
+            irq_polled: false,
         }
     }
 
-    pub fn pull_interrupt(&mut self) -> Interrupt {
-        if self.ppu.is_asserting_nmi() {
+    // the reset line doesn't reach the cartridge, mappers keep their registers
+    pub fn soft_reset(&mut self) {
+        self.oam_dma_page = None;
+        self.nmi_polled = false;
+        self.ppu.soft_reset();
+        self.apu.soft_reset();
+        self.update_event_deadline();
+    }
+
+    pub fn fill_memory(&mut self, config: &PowerOnConfig) {
+        let mut filler = config.filler();
+        filler.fill_cpu_ram(&mut self.ram.0);
+
+        if !self.ppu.rom.cart.battery {
+            filler.fill(self.ppu.rom.mapper.get_prg_ram_mut());
+        }
+
+        self.ppu.fill_memory(&mut filler);
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_clock_remainder = 0;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        self.update_event_deadline();
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_cpu_mode(&mut self, mode: CpuMode) {
+        self.cpu_mode = mode;
+    }
+
+    pub fn get_cpu_mode(&self) -> CpuMode {
+        self.cpu_mode
+    }
+
+    /// interrupt detected by the poll made at the end of the last instruction,
+    /// IRQs are ignored when `irq_disabled` is set
+    pub fn pull_interrupt(&mut self, irq_disabled: bool) -> Interrupt {
+        if std::mem::take(&mut self.nmi_polled) {
             Interrupt::Nmi
+        } else if self.irq_polled && !irq_disabled {
+            Interrupt::Irq
         } else {
-            let is_mapper_irq = self.ppu.rom.mapper.is_asserting_irq();
-            let is_apu_irq = self.apu.is_asserting_irq();
+            Interrupt::None
+        }
+    }
 
-            if is_mapper_irq || is_apu_irq {
-                Interrupt::Irq
-            } else {
-                Interrupt::None
-            }
+    // the CPU polls the interrupt lines during the penultimate cycle of each instruction,
+    // the NMI input is edge sensitive while the IRQ input is level sensitive
+    // https://www.nesdev.org/wiki/CPU_interrupts#Detailed_interrupt_behavior
+    fn poll_interrupts(&mut self) {
+        if self.ppu.is_asserting_nmi() {
+            self.nmi_polled = true;
         }
+
+        self.irq_polled = self.is_asserting_irq();
     }
 
+    fn is_asserting_irq(&self) -> bool {
+        self.ppu.rom.mapper.is_asserting_irq() || self.apu.is_asserting_irq()
+    }
+
+    /// whether an NMI was detected since the start of the instruction, BRK and IRQ
+    /// fetch the NMI vector instead of theirs when an NMI occurs before they push the status
+    pub(crate) fn poll_nmi(&mut self) -> bool {
+        self.run_accesses(self.instruction_accesses);
+        self.ppu.is_asserting_nmi()
+    }
+
+    /// taken branches that don't cross a page poll the interrupt lines before their last cycle,
+    /// as if they were not taken
+    pub(crate) fn poll_interrupts_early(&mut self) {
+        self.run_accesses(self.instruction_accesses - 1);
+        self.poll_interrupts();
+        self.skip_interrupt_poll = true;
+    }
+
+    /// BRK doesn't poll the interrupt lines, like the interrupt sequences it shares its cycles with,
+    /// the first instruction of the handler always runs
+    pub(crate) fn skip_interrupt_poll(&mut self) {
+        self.skip_interrupt_poll = true;
+    }
+
+    /// `cycle` is the number of CPU cycles elapsed before the instruction
+    pub(crate) fn begin_instruction(&mut self, cycle: u32) {
+        self.instruction_start = cycle;
+        self.instruction_accesses = 0;
+        self.synced_accesses = 0;
+    }
+
+    /// cycles of the current instruction which don't access the bus
+    pub(crate) fn internal_cycles(&mut self, cycles: u32) {
+        self.instruction_accesses += cycles;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn instruction_accesses(&self) -> u32 {
+        self.instruction_accesses
+    }
+
+    /// called once an instruction is complete, the PPU and the APU only catch up
+    /// when they might interrupt or stall the CPU, see `sync`
     pub fn advance(&mut self, cpu_cycles: u32) {
-        let ppu_cycles = cpu_cycles * 3;
+        // the CPU instruction is complete when the bus catches up
+        if let Some(cdl) = &mut self.ppu.rom.cdl {
+            cdl.end_instruction();
+        }
+
+        self.pending_cycles += cpu_cycles.saturating_sub(self.synced_accesses);
+        self.instruction_accesses = 0;
+        self.synced_accesses = 0;
+
+        if !std::mem::take(&mut self.skip_interrupt_poll) {
+            // the interrupt lines can only change during the pending cycles once the deadline is reached,
+            // the last cycle of the instruction runs after the poll
+            if self.pending_cycles >= self.event_deadline {
+                let last_cycle = self.pending_cycles.min(1);
+                self.pending_cycles -= last_cycle;
+                self.run_pending();
+                self.pending_cycles += last_cycle;
+            }
 
-        for _ in 0..ppu_cycles {
-            self.ppu.step();
+            self.poll_interrupts();
         }
 
+        // debugging tools inspect the PPU after every instruction
+        if self.pending_cycles >= self.event_deadline
+            || self.debugger.is_some()
+            || self.tracer.is_some()
+        {
+            self.run_pending();
+        }
+    }
+
+    // the PPU and the APU don't change while they lag behind, so the number of cycles
+    // before one of them raises an interrupt, starts a new frame, outputs a sample
+    // or stalls the CPU only changes when they catch up or when their registers are accessed
+    fn update_event_deadline(&mut self) {
+        let (num, den) = self.region.ppu_clock_ratio();
+        let ppu_dots = self.ppu.dots_until_event();
+        let ppu_cycles = (ppu_dots * den).saturating_sub(self.ppu_clock_remainder) / num;
+
+        self.event_deadline = ppu_cycles.min(self.apu.cycles_until_event());
+    }
+
+    /// brings the PPU and the APU up to date with the CPU
+    pub fn sync(&mut self) {
+        self.run_pending();
+        self.ppu.catch_up();
+    }
+
+    fn run_pending(&mut self) {
+        let cycles = std::mem::take(&mut self.pending_cycles);
+        self.run(cycles);
+        self.update_event_deadline();
+    }
+
+    // the PPU and the APU catch up right before the CPU accesses their registers or the mapper,
+    // accounting for the cycles of the current instruction spent before the access
+    fn sync_access(&mut self) {
+        self.run_until_access();
+        self.ppu.catch_up();
+    }
+
+    // the PPU only needs to catch up when its registers are accessed
+    fn run_until_access(&mut self) {
+        // the access itself is the last cycle counted
+        self.run_accesses(self.instruction_accesses - 1);
+    }
+
+    // runs the cycles of the first `accesses` accesses of the current instruction
+    fn run_accesses(&mut self, accesses: u32) {
+        self.pending_cycles += accesses - self.synced_accesses;
+        self.synced_accesses = accesses;
+        self.run_pending();
+    }
+
+    fn run(&mut self, cpu_cycles: u32) {
+        // PAL consoles run 3.2 PPU dots per CPU cycle
+        let (num, den) = self.region.ppu_clock_ratio();
+        let ppu_clocks = cpu_cycles * num + self.ppu_clock_remainder;
+        let ppu_cycles = ppu_clocks / den;
+        self.ppu_clock_remainder = ppu_clocks % den;
+
+        self.ppu.run(ppu_cycles);
+
         for _ in 0..cpu_cycles {
             self.apu.step();
+        }
+    }
+}
+
+impl Bus {
+    /// reads a byte without side effects: reading PPUSTATUS doesn't clear the vblank flag,
+    /// PPUDATA returns the read buffer without incrementing the VRAM address
+    /// and the joypads don't shift
+    pub fn peek_byte(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.ram.read_byte(addr),
+            0x2000..=0x3fff => self.ppu.peek_register(0x2000 + (addr & 7)),
+            0x4016 => self.joypad1.peek(),
+            0x4017 => self.joypad2.peek(),
+            0x4000..=0x4015 => self.apu.peek(addr),
+            0x4018..=0x401f => 0,
+            0x4020..=0xffff => self.read_cart(addr),
+        }
+    }
+
+    fn read_cart(&mut self, addr: u16) -> u8 {
+        let val = self.ppu.rom.mapper.read(&mut self.ppu.rom.cart, addr);
+
+        if addr >= 0x8000 && self.cheats.has_rom_patches() {
+            self.cheats.patch_read(addr, val)
+        } else {
+            val
+        }
+    }
+
+    pub fn snapshot_ram(&mut self) -> RamSnapshot {
+        RamSnapshot::new(&self.ram.0, self.ppu.rom.mapper.get_prg_ram_mut())
+    }
+
+    /// writes the values of the enabled RAM freeze cheats
+    pub fn apply_ram_cheats(&mut self) {
+        for &(addr, val) in self.cheats.ram_freezes() {
+            match addr {
+                0x0000..=0x1fff => self.ram.write_byte(addr, val),
+                _ => {
+                    let prg_ram = self.ppu.rom.mapper.get_prg_ram_mut();
+
+                    if !prg_ram.is_empty() {
+                        let len = prg_ram.len();
+                        prg_ram[(addr as usize - 0x6000) % len] = val;
+                    }
+                }
+            }
+        }
+    }
+
+    /// writes a byte without side effects, PRG ROM bytes are patched until the next
+    /// power cycle and writes to memory mapped registers are ignored
+    pub fn poke_byte(&mut self, addr: u16, val: u8) {
+        let rom = &mut self.ppu.rom;
+
+        match addr {
+            0x0000..=0x1fff => self.ram.write_byte(addr, val),
+            0x6000..=0x7fff => {
+                let prg_ram = rom.mapper.get_prg_ram_mut();
+
+                if !prg_ram.is_empty() {
+                    let len = prg_ram.len();
+                    prg_ram[(addr as usize - 0x6000) % len] = val;
+                }
+            }
+            0x8000..=0xffff => {
+                if let Some(offset) = rom.mapper.prg_rom_offset(&rom.cart, addr) {
+                    let start = rom.cart.prg_rom_start;
+                    rom.cart.patch(start + offset, val);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// marks the bytes of the instruction at `pc` as code in the code/data log
+    pub(crate) fn log_instruction(&mut self, pc: u16) {
+        let opcode = self.peek_byte(pc);
+        let mode = OPCODES[opcode as usize].mode;
+        let len = mode.instruction_len();
+        let rom = &mut self.ppu.rom;
+
+        for i in 0..len {
+            let addr = pc.wrapping_add(i);
+
+            if let Some(flags) = rom.cdl.as_ref().map(|cdl| cdl.code_flags(addr)) {
+                rom.log_prg(addr, flags);
+            }
+        }
+
+        if let Some(cdl) = &mut rom.cdl {
+            let indirect = matches!(mode, AddressingMode::IndirectX | AddressingMode::IndirectY);
+            cdl.begin_instruction(pc, len, indirect, mode == AddressingMode::Indirect);
+        }
+    }
+
+    fn log_data_read(&mut self, addr: u16) {
+        let rom = &mut self.ppu.rom;
+
+        if let Some(flags) = rom.cdl.as_ref().and_then(|cdl| cdl.data_flags(addr)) {
+            rom.log_prg(addr, flags);
+        }
+    }
+
+    // writes to the PPU, APU and mapper registers shown in the event log
+    fn log_write_event(&mut self, addr: u16, val: u8) {
+        if let Some(kind) = EventKind::from_write(addr) {
+            let addr = match kind {
+                EventKind::PpuRegisterWrite => 0x2000 + (addr & 7),
+                _ => addr,
+            };
+
+            self.ppu.log_event(kind, addr, val);
+        }
+    }
+
+    // PPU address and value accessed through PPUDATA
+    fn ppu_data_access(&mut self, addr: u16, kind: AccessKind, val: u8) -> Option<(u16, u8)> {
+        if (0x2000..=0x3fff).contains(&addr) && addr & 7 == 7 {
+            let ppu_addr = self.ppu.get_vram_addr();
+            let val = if kind == AccessKind::READ {
+                self.ppu.peek_data(ppu_addr)
+            } else {
+                val
+            };
+
+            Some((ppu_addr, val))
+        } else {
+            None
+        }
+    }
 
-            if let Some(addr) = self.apu.pull_memory_read_request() {
-                let val = self.read_byte(addr);
-                self.apu.push_memory_read_response(val);
+    fn report_access(
+        &mut self,
+        addr: u16,
+        kind: AccessKind,
+        val: u8,
+        ppu_access: Option<(u16, u8)>,
+    ) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.on_access(AddressSpace::Cpu, addr, kind, val);
+
+            if let Some((ppu_addr, ppu_val)) = ppu_access {
+                debugger.on_access(AddressSpace::Ppu, ppu_addr, kind, ppu_val);
             }
         }
     }
@@ -89,6 +454,87 @@ impl Bus {
 // https://wiki.nesdev.com/w/index.php/CPU_memory_map
 impl Memory for Bus {
     fn read_byte(&mut self, addr: u16) -> u8 {
+        self.instruction_accesses += 1;
+
+        // in the cycle mode, the PPU and the APU catch up before every access
+        if self.cpu_mode == CpuMode::Cycle || matches!(addr, 0x2000..=0x3fff | 0x4015..=0x4017) {
+            self.sync_access();
+            self.halt_for_dma(addr);
+            let val = self.read(addr);
+            self.update_event_deadline();
+            return val;
+        }
+
+        self.halt_for_dma(addr);
+
+        self.read(addr)
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        self.instruction_accesses += 1;
+
+        // mapper writes can switch CHR banks or the mirroring mid-frame
+        if self.cpu_mode == CpuMode::Cycle
+            || matches!(addr, 0x2000..=0x4017 | 0x4020..=0x5fff | 0x8000..=0xffff)
+        {
+            self.sync_access();
+            self.write(addr, val);
+            self.update_event_deadline();
+            return;
+        }
+
+        self.write(addr, val);
+    }
+}
+
+impl Bus {
+    /// a CPU read whose value is discarded, it has the side effects of a read
+    /// but isn't reported to the debugger nor logged as data in the code/data log
+    pub(crate) fn dummy_read(&mut self, addr: u16) {
+        self.instruction_accesses += 1;
+        self.sync_access();
+        self.halt_for_dma(addr);
+        self.read_byte_inner(addr);
+        self.update_event_deadline();
+    }
+
+    // accesses made on behalf of the CPU and by the DMA units
+    fn read(&mut self, addr: u16) -> u8 {
+        if self.ppu.rom.cdl.is_some() && addr >= 0x8000 {
+            self.log_data_read(addr);
+        }
+
+        self.read_unlogged(addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if self.ppu.event_log.is_some() {
+            self.log_write_event(addr, val);
+        }
+
+        if self.debugger.is_some() {
+            let ppu_access = self.ppu_data_access(addr, AccessKind::WRITE, val);
+            self.report_access(addr, AccessKind::WRITE, val, ppu_access);
+        }
+
+        self.write_byte_inner(addr, val);
+    }
+
+    // reported to the debugger but not logged as data in the code/data log,
+    // for the DMC fetches which are logged as PCM data
+    fn read_unlogged(&mut self, addr: u16) -> u8 {
+        if self.debugger.is_some() {
+            // the VRAM address is incremented by PPUDATA accesses
+            let ppu_access = self.ppu_data_access(addr, AccessKind::READ, 0);
+            let val = self.read_byte_inner(addr);
+            self.report_access(addr, AccessKind::READ, val, ppu_access);
+            return val;
+        }
+
+        self.read_byte_inner(addr)
+    }
+
+    fn read_byte_inner(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1fff => self.ram.read_byte(addr),
             // 0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
@@ -97,32 +543,28 @@ impl Memory for Bus {
             0x2000..=0x2007 => self.ppu.read_register(addr),
             0x2008..=0x3fff => self.ppu.read_register(0x2000 + (addr & 7)),
             0x4016 => self.joypad1.read(),
-            0x4000..=0x4017 => self.apu.read(addr),
+            0x4017 => self.joypad2.read(),
+            0x4000..=0x4015 => self.apu.read(addr),
             0x4018..=0x401F => {
                 // APU and I/O functionality that is normally disabled.
                 0
             }
-            0x4020..=0xffff => self.ppu.rom.mapper.read(&mut self.ppu.rom.cart, addr),
+            0x4020..=0xffff => self.read_cart(addr),
         }
     }
 
-    fn write_byte(&mut self, addr: u16, val: u8) {
+    fn write_byte_inner(&mut self, addr: u16, val: u8) {
         match addr {
             0x0000..=0x1fff => self.ram.write_byte(addr, val),
             0x2000..=0x2007 => self.ppu.write_register(addr, val),
             0x2008..=0x3fff => self.ppu.write_register(0x2000 + (addr & 7), val),
-            0x4014 => {
-                let mut page = [0u8; 256];
-                let high_byte = (val as u16) << 8;
-
-                for low_byte in 0..256u16 {
-                    page[low_byte as usize] = self.read_byte(high_byte | low_byte);
-                }
-
-                self.ppu.write_oam_dma_reg(page);
-                self.dma_transfer = true;
+            // the transfer starts on the next read cycle
+            0x4014 => self.oam_dma_page = Some(val),
+            // the strobe is wired to both controllers
+            0x4016 => {
+                self.joypad1.write(val);
+                self.joypad2.write(val);
             }
-            0x4016 => self.joypad1.write(val),
             0x4000..=0x4017 => self.apu.write(addr, val),
             0x4018..=0x401F => (), // APU and I/O functionality that is normally disabled.
             0x4020..=0xffff => self.ppu.rom.mapper.write(&mut self.ppu.rom.cart, addr, val),
@@ -138,7 +580,15 @@ impl savestate::Save for Bus {
         let s = parent.create_child(BUS_SECTION_NAME);
 
         s.data.write_u8_slice(&self.ram.0);
-        s.data.write_bool(self.dma_transfer);
+        s.data.write_bool(self.oam_dma_page.is_some());
+
+        if let Some(page) = self.oam_dma_page {
+            s.data.write_u8(page);
+        }
+
+        s.data.write_u32(self.ppu_clock_remainder);
+        s.data.write_u32(self.pending_cycles);
+        s.data.write_bool(self.nmi_polled);
 
         self.ppu.save(s);
 
@@ -151,9 +601,19 @@ impl savestate::Save for Bus {
         let s = parent.get(BUS_SECTION_NAME)?;
 
         s.data.read_u8_slice(&mut self.ram.0)?;
-        self.dma_transfer = s.data.read_bool()?;
+        self.oam_dma_page = if s.data.read_bool()? {
+            Some(s.data.read_u8()?)
+        } else {
+            None
+        };
+        self.ppu_clock_remainder = s.data.read_u32()?;
+        self.pending_cycles = s.data.read_u32()?;
+        self.nmi_polled = s.data.read_bool()?;
 
         self.ppu.load(s)?;
+        // the IRQ line is a level, it is polled again from the restored state
+        self.irq_polled = self.is_asserting_irq();
+        self.update_event_deadline();
 
         let s = parent.get(JOYPADS_SECTION_NAME)?;
         self.joypad1.load(s)?;
@@ -162,3 +622,102 @@ impl savestate::Save for Bus {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Bus;
+    use crate::{
+        cpu::{memory::Memory, rom::ROM},
+        power_on::{PowerOnConfig, RamFill},
+        region::Region,
+        tests::{nestest, rom_bytes, NESTEST},
+    };
+
+    const BATTERY_FLAG: u8 = 0b10;
+
+    fn bus(region: Region) -> Bus {
+        let mut bus = Bus::new(nestest(), 44100.0);
+        bus.set_region(region);
+        bus
+    }
+
+    fn filled_prg_ram(battery: bool) -> Vec<u8> {
+        let mut bytes = NESTEST.to_vec();
+
+        if battery {
+            bytes[6] |= BATTERY_FLAG;
+        }
+
+        let mut bus = Bus::new(ROM::new(bytes).unwrap(), 44100.0);
+        bus.fill_memory(&PowerOnConfig {
+            ram_fill: RamFill::Ones,
+        });
+
+        assert!(bus.ram.0.iter().all(|&byte| byte == 0xFF));
+        bus.ppu.rom.mapper.get_prg_ram_mut().to_vec()
+    }
+
+    #[test]
+    fn battery_ram_is_not_filled() {
+        let prg_ram = filled_prg_ram(false);
+        assert!(!prg_ram.is_empty() && prg_ram.iter().all(|&byte| byte == 0xFF));
+        assert!(filled_prg_ram(true).iter().all(|&byte| byte == 0));
+    }
+
+    fn dots(region: Region, cpu_cycles: usize) -> Vec<u16> {
+        let mut bus = bus(region);
+        // the PPU starts at the end of a scanline, 5 CPU cycles leave no PAL remainder
+        bus.advance(5);
+        bus.sync();
+        let start = bus.ppu.cycle;
+
+        (0..cpu_cycles)
+            .map(|_| {
+                bus.advance(1);
+                bus.sync();
+                bus.ppu.cycle - start
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ppu_clock_ratio() {
+        assert_eq!(dots(Region::Ntsc, 5), [3, 6, 9, 12, 15]);
+        assert_eq!(dots(Region::Dendy, 5), [3, 6, 9, 12, 15]);
+        // PAL consoles run 16 dots every 5 CPU cycles
+        assert_eq!(dots(Region::Pal, 10), [3, 6, 9, 12, 16, 19, 22, 25, 28, 32]);
+    }
+
+    #[test]
+    fn soft_reset_keeps_the_mapper_banks() {
+        let mut unrom = Bus::new(ROM::new(rom_bytes(2, 4)).unwrap(), 44100.0);
+        unrom.write_byte(0x8000, 2);
+
+        let mut mmc1 = Bus::new(ROM::new(rom_bytes(1, 4)).unwrap(), 44100.0);
+        // the PRG bank register is loaded serially, bit 0 first
+        for bit in 0..5 {
+            mmc1.write_byte(0xE000, (2 >> bit) & 1);
+        }
+
+        for bus in [&mut unrom, &mut mmc1] {
+            assert_eq!(bus.read_byte(0x8000), 2);
+            bus.soft_reset();
+            assert_eq!(bus.read_byte(0x8000), 2);
+        }
+    }
+
+    #[test]
+    fn peek_reads_the_cartridge_like_the_cpu() {
+        // NROM, MMC1, UNROM and MMC3
+        for mapper in [0, 1, 2, 4] {
+            let mut bytes = NESTEST.to_vec();
+            bytes[6] = (bytes[6] & 0x0F) | (mapper << 4);
+            let mut bus = Bus::new(ROM::new(bytes).unwrap(), 44100.0);
+
+            for addr in 0x4020..=0xFFFF {
+                let val = bus.read_byte(addr);
+                assert_eq!(bus.peek_byte(addr), val, "mapper {mapper}, ${addr:04X}");
+            }
+        }
+    }
+}
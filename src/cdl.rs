@@ -0,0 +1,215 @@
+// Code/Data Logger, marks the PRG and CHR ROM bytes accessed while the game runs
+// the output follows the FCEUX .cdl format: one byte of flags per PRG ROM byte,
+// followed by one byte per CHR ROM byte
+// https://fceux.com/web/help/CodeDataLogger.html
+
+use bitflags::bitflags;
+
+bitflags! {
+    // xPdcAADC
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PrgFlags: u8 {
+        const CODE = 0b0000_0001;
+        const DATA = 0b0000_0010;
+        // AA: 8KB CPU window the byte was accessed through ($8000, $A000, $C000 or $E000)
+        const WINDOW = 0b0000_1100;
+        /// destination of an indirect jump
+        const INDIRECT_CODE = 0b0001_0000;
+        /// read through a pointer with (zp,X) or (zp),Y addressing
+        const INDIRECT_DATA = 0b0010_0000;
+        /// DPCM sample
+        const PCM_DATA = 0b0100_0000;
+    }
+}
+
+bitflags! {
+    // ------RD
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChrFlags: u8 {
+        /// fetched by the PPU while rendering
+        const DRAWN = 0b0000_0001;
+        /// read by the CPU through PPUDATA
+        const READ = 0b0000_0010;
+    }
+}
+
+#[derive(Debug)]
+pub enum CdlError {
+    SizeMismatch { expected: usize, actual: usize },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CdlStats {
+    pub code: usize,
+    pub data: usize,
+    pub unaccessed_prg: usize,
+    pub drawn: usize,
+    pub read: usize,
+    pub unaccessed_chr: usize,
+}
+
+// the instruction being executed, its operand fetches are code and not data
+#[derive(Clone, Copy, Default)]
+struct CurrentInstruction {
+    addr: u16,
+    len: u16,
+    indirect: bool,
+}
+
+pub struct CodeDataLogger {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    current: Option<CurrentInstruction>,
+    after_indirect_jump: bool,
+}
+
+impl CodeDataLogger {
+    /// sizes in bytes, CHR RAM isn't logged
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize) -> Self {
+        CodeDataLogger {
+            prg: vec![0; prg_rom_size],
+            chr: vec![0; chr_rom_size],
+            current: None,
+            after_indirect_jump: false,
+        }
+    }
+
+    /// resumes logging from an existing .cdl file
+    pub fn from_cdl(
+        data: &[u8],
+        prg_rom_size: usize,
+        chr_rom_size: usize,
+    ) -> Result<Self, CdlError> {
+        let expected = prg_rom_size + chr_rom_size;
+
+        if data.len() != expected {
+            return Err(CdlError::SizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let mut cdl = CodeDataLogger::new(prg_rom_size, chr_rom_size);
+        cdl.prg.copy_from_slice(&data[..prg_rom_size]);
+        cdl.chr.copy_from_slice(&data[prg_rom_size..]);
+
+        Ok(cdl)
+    }
+
+    pub fn to_cdl(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.prg.len() + self.chr.len());
+        data.extend_from_slice(&self.prg);
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    pub fn clear(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    pub fn get_prg_flags(&self, offset: usize) -> PrgFlags {
+        PrgFlags::from_bits_retain(self.prg.get(offset).copied().unwrap_or(0))
+    }
+
+    pub fn get_chr_flags(&self, offset: usize) -> ChrFlags {
+        ChrFlags::from_bits_retain(self.chr.get(offset).copied().unwrap_or(0))
+    }
+
+    pub fn get_stats(&self) -> CdlStats {
+        let mut stats = CdlStats::default();
+
+        for &flags in &self.prg {
+            let flags = PrgFlags::from_bits_retain(flags);
+
+            if flags.contains(PrgFlags::CODE) {
+                stats.code += 1;
+            }
+
+            if flags.contains(PrgFlags::DATA) {
+                stats.data += 1;
+            }
+
+            if !flags.intersects(PrgFlags::CODE | PrgFlags::DATA) {
+                stats.unaccessed_prg += 1;
+            }
+        }
+
+        for &flags in &self.chr {
+            let flags = ChrFlags::from_bits_retain(flags);
+
+            if flags.contains(ChrFlags::DRAWN) {
+                stats.drawn += 1;
+            }
+
+            if flags.contains(ChrFlags::READ) {
+                stats.read += 1;
+            }
+
+            if flags.is_empty() {
+                stats.unaccessed_chr += 1;
+            }
+        }
+
+        stats
+    }
+
+    /// flags of an opcode or operand byte
+    pub(crate) fn code_flags(&self, addr: u16) -> PrgFlags {
+        let mut flags = PrgFlags::CODE | window_flags(addr);
+
+        if self.after_indirect_jump {
+            flags |= PrgFlags::INDIRECT_CODE;
+        }
+
+        flags
+    }
+
+    pub(crate) fn begin_instruction(&mut self, addr: u16, len: u16, indirect: bool, jmp_ind: bool) {
+        self.current = Some(CurrentInstruction {
+            addr,
+            len,
+            indirect,
+        });
+
+        self.after_indirect_jump = jmp_ind;
+    }
+
+    pub(crate) fn end_instruction(&mut self) {
+        self.current = None;
+    }
+
+    /// flags of a data read, None for operand fetches of the current instruction
+    pub(crate) fn data_flags(&self, addr: u16) -> Option<PrgFlags> {
+        let mut flags = PrgFlags::DATA | window_flags(addr);
+
+        if let Some(inst) = self.current {
+            if addr.wrapping_sub(inst.addr) < inst.len {
+                return None;
+            }
+
+            if inst.indirect {
+                flags |= PrgFlags::INDIRECT_DATA;
+            }
+        }
+
+        Some(flags)
+    }
+
+    pub(crate) fn mark_prg(&mut self, offset: usize, flags: PrgFlags) {
+        if let Some(byte) = self.prg.get_mut(offset) {
+            // the window bits only hold the last window the byte was accessed through
+            *byte = (*byte & !PrgFlags::WINDOW.bits()) | flags.bits();
+        }
+    }
+
+    pub(crate) fn mark_chr(&mut self, offset: usize, flags: ChrFlags) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= flags.bits();
+        }
+    }
+}
+
+fn window_flags(addr: u16) -> PrgFlags {
+    PrgFlags::from_bits_retain((((addr >> 13) & 3) << 2) as u8)
+}
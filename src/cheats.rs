@@ -0,0 +1,389 @@
+// Game Genie codes patch the values read from PRG ROM,
+// Pro Action Replay codes (addr:value) freeze a RAM address every frame
+// https://www.nesdev.org/wiki/Game_Genie
+
+use crate::savestate::{self, SaveStateError};
+
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheatError {
+    InvalidLength(usize),
+    InvalidLetter(char),
+    InvalidNumber(String),
+    /// RAM freezes are limited to the internal RAM and the cartridge RAM
+    InvalidAddress(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatEffect {
+    /// replaces the value read at `addr` ($8000-$FFFF),
+    /// 8-letter codes only apply when the ROM holds `compare`
+    RomPatch {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// `value` is written at `addr` ($0000-$1FFF or $6000-$7FFF) every frame
+    RamFreeze { addr: u16, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub effect: CheatEffect,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// accepts 6 or 8 letter Game Genie codes and addr:value hex pairs
+    pub fn parse(code: &str) -> Result<Cheat, CheatError> {
+        let code = code.trim().to_ascii_uppercase();
+
+        let effect = match code.split_once(':') {
+            Some((addr, value)) => {
+                let addr = parse_hex(addr, 0xFFFF)? as u16;
+
+                if !matches!(addr, 0x0000..=0x1FFF | 0x6000..=0x7FFF) {
+                    return Err(CheatError::InvalidAddress(addr));
+                }
+
+                CheatEffect::RamFreeze {
+                    addr,
+                    value: parse_hex(value, 0xFF)? as u8,
+                }
+            }
+            None => decode_game_genie(&code)?,
+        };
+
+        Ok(Cheat {
+            code,
+            effect,
+            enabled: true,
+        })
+    }
+}
+
+fn parse_hex(text: &str, max: u32) -> Result<u32, CheatError> {
+    let digits = text.trim().trim_start_matches('$');
+    let invalid = || CheatError::InvalidNumber(text.to_owned());
+
+    if digits.is_empty() || digits.len() > 4 {
+        return Err(invalid());
+    }
+
+    match u32::from_str_radix(digits, 16) {
+        Ok(n) if n <= max => Ok(n),
+        _ => Err(invalid()),
+    }
+}
+
+fn decode_game_genie(code: &str) -> Result<CheatEffect, CheatError> {
+    let n = code
+        .chars()
+        .map(|c| {
+            GAME_GENIE_LETTERS
+                .iter()
+                .position(|&l| l as char == c)
+                .map(|pos| pos as u16)
+                .ok_or(CheatError::InvalidLetter(c))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if n.len() != 6 && n.len() != 8 {
+        return Err(CheatError::InvalidLength(n.len()));
+    }
+
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+    let effect = if n.len() == 6 {
+        CheatEffect::RomPatch {
+            addr,
+            value: (value | (n[5] & 8)) as u8,
+            compare: None,
+        }
+    } else {
+        let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+
+        CheatEffect::RomPatch {
+            addr,
+            value: (value | (n[7] & 8)) as u8,
+            compare: Some(compare as u8),
+        }
+    };
+
+    Ok(effect)
+}
+
+pub struct Cheats {
+    cheats: Vec<(usize, Cheat)>,
+    next_id: usize,
+    // enabled ROM patches: (addr, value, compare)
+    rom_patches: Vec<(u16, u8, Option<u8>)>,
+    // enabled RAM freezes: (addr, value)
+    ram_freezes: Vec<(u16, u8)>,
+    saved_in_states: bool,
+}
+
+impl Cheats {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Cheats {
+            cheats: Vec::new(),
+            next_id: 0,
+            rom_patches: Vec::new(),
+            ram_freezes: Vec::new(),
+            saved_in_states: true,
+        }
+    }
+
+    pub fn add(&mut self, cheat: Cheat) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.cheats.push((id, cheat));
+        self.update_effects();
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.cheats.len();
+        self.cheats.retain(|(cheat_id, _)| *cheat_id != id);
+        self.update_effects();
+        self.cheats.len() != len
+    }
+
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.cheats.iter_mut().find(|(cheat_id, _)| *cheat_id == id) {
+            Some((_, cheat)) => {
+                cheat.enabled = enabled;
+                self.update_effects();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Cheat> {
+        self.cheats
+            .iter()
+            .find(|(cheat_id, _)| *cheat_id == id)
+            .map(|(_, cheat)| cheat)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Cheat)> {
+        self.cheats.iter().map(|(id, cheat)| (*id, cheat))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+        self.rom_patches.clear();
+        self.ram_freezes.clear();
+    }
+
+    /// when disabled, savestates neither store nor restore the cheat list
+    pub fn set_saved_in_states(&mut self, saved: bool) {
+        self.saved_in_states = saved;
+    }
+
+    pub fn is_saved_in_states(&self) -> bool {
+        self.saved_in_states
+    }
+
+    // the effects of the enabled cheats are collected once instead of on every read or frame
+    fn update_effects(&mut self) {
+        self.rom_patches.clear();
+        self.ram_freezes.clear();
+
+        for (_, cheat) in self.cheats.iter().filter(|(_, cheat)| cheat.enabled) {
+            match cheat.effect {
+                CheatEffect::RomPatch {
+                    addr,
+                    value,
+                    compare,
+                } => self.rom_patches.push((addr, value, compare)),
+                CheatEffect::RamFreeze { addr, value } => self.ram_freezes.push((addr, value)),
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn has_rom_patches(&self) -> bool {
+        !self.rom_patches.is_empty()
+    }
+
+    /// value seen by the CPU when reading `val` from the PRG ROM at `addr`
+    pub(crate) fn patch_read(&self, addr: u16, val: u8) -> u8 {
+        for &(patch_addr, value, compare) in &self.rom_patches {
+            if patch_addr == addr && compare.is_none_or(|compare| compare == val) {
+                return value;
+            }
+        }
+
+        val
+    }
+
+    /// (addr, value) of the enabled RAM freezes
+    pub(crate) fn ram_freezes(&self) -> &[(u16, u8)] {
+        &self.ram_freezes
+    }
+}
+
+const CHEATS_SECTION_NAME: &str = "cheats";
+
+impl savestate::Save for Cheats {
+    fn save(&self, parent: &mut savestate::Section) {
+        let s = parent.create_child(CHEATS_SECTION_NAME);
+
+        s.data.write_u32(self.cheats.len() as u32);
+
+        for (id, cheat) in &self.cheats {
+            s.data.write_u32(*id as u32);
+            s.data.write_bool(cheat.enabled);
+            s.data.write_u8(cheat.code.len() as u8);
+            s.data.write_u8_slice(cheat.code.as_bytes());
+        }
+    }
+
+    fn load(&mut self, parent: &mut savestate::Section) -> Result<(), SaveStateError> {
+        let s = parent.get(CHEATS_SECTION_NAME)?;
+        let count = s.data.read_u32()?;
+
+        self.clear();
+
+        for _ in 0..count {
+            let id = s.data.read_u32()? as usize;
+            let enabled = s.data.read_bool()?;
+            let mut code = vec![0; s.data.read_u8()? as usize];
+            s.data.read_u8_slice(&mut code)?;
+
+            let code = String::from_utf8(code).map_err(|_| SaveStateError::InvalidData)?;
+            let mut cheat = Cheat::parse(&code).map_err(|_| SaveStateError::InvalidData)?;
+            cheat.enabled = enabled;
+            self.cheats.push((id, cheat));
+            self.next_id = self.next_id.max(id + 1);
+        }
+
+        self.update_effects();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(code: &str) -> Result<CheatEffect, CheatError> {
+        Cheat::parse(code).map(|cheat| cheat.effect)
+    }
+
+    #[test]
+    fn six_letter_game_genie_codes() {
+        // Super Mario Bros., infinite lives
+        assert_eq!(
+            effect("SXIOPO"),
+            Ok(CheatEffect::RomPatch {
+                addr: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            })
+        );
+        assert_eq!(effect(" sxiopo "), effect("SXIOPO"));
+    }
+
+    // https://www.nesdev.org/wiki/Game_Genie
+    #[test]
+    fn eight_letter_game_genie_codes() {
+        assert_eq!(
+            effect("ZEXPYGLA"),
+            Ok(CheatEffect::RomPatch {
+                addr: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_game_genie_codes() {
+        assert_eq!(effect("SXIOP"), Err(CheatError::InvalidLength(5)));
+        assert_eq!(effect("SXIOPOP"), Err(CheatError::InvalidLength(7)));
+        assert_eq!(effect("SXIOPB"), Err(CheatError::InvalidLetter('B')));
+    }
+
+    #[test]
+    fn ram_freezes() {
+        assert_eq!(
+            effect("075A:09"),
+            Ok(CheatEffect::RamFreeze {
+                addr: 0x075A,
+                value: 0x09,
+            })
+        );
+        assert_eq!(
+            effect("$7FFF:$ff"),
+            Ok(CheatEffect::RamFreeze {
+                addr: 0x7FFF,
+                value: 0xFF,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_ram_freezes() {
+        assert_eq!(effect("8000:EA"), Err(CheatError::InvalidAddress(0x8000)));
+        assert_eq!(effect("2000:80"), Err(CheatError::InvalidAddress(0x2000)));
+        assert_eq!(
+            effect("0300:1FF"),
+            Err(CheatError::InvalidNumber("1FF".to_owned()))
+        );
+        assert_eq!(
+            effect("10000:00"),
+            Err(CheatError::InvalidNumber("10000".to_owned()))
+        );
+        assert_eq!(
+            effect("0300:"),
+            Err(CheatError::InvalidNumber(String::new()))
+        );
+        assert_eq!(
+            effect("03G0:00"),
+            Err(CheatError::InvalidNumber("03G0".to_owned()))
+        );
+    }
+
+    #[test]
+    fn only_enabled_freezes_are_applied() {
+        let mut cheats = Cheats::new();
+        let first = cheats.add(Cheat::parse("0300:01").unwrap());
+        cheats.add(Cheat::parse("SXIOPO").unwrap());
+        cheats.add(Cheat::parse("6000:02").unwrap());
+
+        assert_eq!(cheats.ram_freezes(), [(0x0300, 0x01), (0x6000, 0x02)]);
+        assert!(cheats.has_rom_patches());
+
+        cheats.set_enabled(first, false);
+        assert_eq!(cheats.ram_freezes(), [(0x6000, 0x02)]);
+
+        cheats.remove(first);
+        cheats.clear();
+        assert!(cheats.ram_freezes().is_empty());
+        assert!(!cheats.has_rom_patches());
+    }
+}
@@ -0,0 +1,333 @@
+// 6502 disassembler, instructions are decoded through the mapper's current bank view
+// https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+
+mod symbols;
+
+use std::fmt;
+
+use super::opcodes::{AddressingMode, OPCODES};
+use crate::bus::Bus;
+pub use symbols::{Symbols, SymbolsError};
+
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// the opcode followed by its operand, only the first `len` bytes are used
+    pub bytes: [u8; 3],
+    pub len: u16,
+    pub official: bool,
+    /// offset in the PRG ROM of the instruction, None outside of $8000-$FFFF
+    pub prg_offset: Option<usize>,
+    /// offset in the PRG ROM of the operand address, if it points to $8000-$FFFF
+    target_prg_offset: Option<usize>,
+}
+
+impl Instruction {
+    /// raw operand value, 0 for implied and accumulator instructions
+    pub fn operand(&self) -> u16 {
+        match self.len {
+            2 => self.bytes[1] as u16,
+            3 => u16::from_le_bytes([self.bytes[1], self.bytes[2]]),
+            _ => 0,
+        }
+    }
+
+    /// address referenced by the operand, before indexing and indirection
+    pub fn target(&self) -> Option<u16> {
+        use AddressingMode::*;
+
+        match self.mode {
+            Implied | Accumulator | Immediate => None,
+            Relative => {
+                let offset = self.bytes[1] as i8 as i16 as u16;
+                Some(self.addr.wrapping_add(2).wrapping_add(offset))
+            }
+            _ => Some(self.operand()),
+        }
+    }
+
+    pub fn is_branch(&self) -> bool {
+        self.mode == AddressingMode::Relative
+    }
+
+    /// label of the instruction address
+    pub fn label<'a>(&self, symbols: &'a Symbols) -> Option<&'a str> {
+        symbols.lookup(self.addr, self.prg_offset)
+    }
+
+    /// formats the operand, replacing addresses with labels when available
+    pub fn format_operand(&self, symbols: Option<&Symbols>) -> String {
+        use AddressingMode::*;
+
+        let target = self.target().map(|addr| {
+            symbols
+                .and_then(|s| s.lookup(addr, self.target_prg_offset))
+                .map(str::to_owned)
+                .unwrap_or_else(|| match self.mode {
+                    ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY => {
+                        format!("${:02X}", addr)
+                    }
+                    _ => format!("${:04X}", addr),
+                })
+        });
+
+        let target = target.unwrap_or_default();
+
+        match self.mode {
+            Implied => String::new(),
+            Accumulator => "A".to_owned(),
+            Immediate => format!("#${:02X}", self.bytes[1]),
+            ZeroPage | Absolute | Relative => target,
+            ZeroPageX | AbsoluteX => format!("{},X", target),
+            ZeroPageY | AbsoluteY => format!("{},Y", target),
+            Indirect => format!("({})", target),
+            IndirectX => format!("({},X)", target),
+            IndirectY => format!("({}),Y", target),
+        }
+    }
+
+    pub fn format(&self, symbols: Option<&Symbols>) -> String {
+        let operand = self.format_operand(symbols);
+
+        if operand.is_empty() {
+            self.mnemonic.to_owned()
+        } else {
+            format!("{} {}", self.mnemonic, operand)
+        }
+    }
+
+    /// hex dump of the instruction bytes, e.g. "A9 10"
+    pub fn format_bytes(&self) -> String {
+        self.bytes[..self.len as usize]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(None))
+    }
+}
+
+/// decodes the instruction at `addr` without side effects
+pub fn disassemble(bus: &mut Bus, addr: u16) -> Instruction {
+    let opcode = bus.peek_byte(addr);
+    let len = OPCODES[opcode as usize].mode.instruction_len();
+    let mut bytes = [opcode, 0, 0];
+
+    for i in 1..len {
+        bytes[i as usize] = bus.peek_byte(addr.wrapping_add(i));
+    }
+
+    let mut inst = decode(addr, bytes);
+    inst.prg_offset = prg_rom_offset(bus, addr);
+    inst.target_prg_offset = inst.target().and_then(|addr| prg_rom_offset(bus, addr));
+
+    inst
+}
+
+/// decodes raw instruction bytes, ROM labels can't be resolved without the mapper state
+pub fn decode(addr: u16, bytes: [u8; 3]) -> Instruction {
+    let opcode = bytes[0];
+    let info = &OPCODES[opcode as usize];
+
+    Instruction {
+        addr,
+        opcode,
+        mnemonic: info.mnemonic,
+        mode: info.mode,
+        bytes,
+        len: info.mode.instruction_len(),
+        official: info.official,
+        prg_offset: None,
+        target_prg_offset: None,
+    }
+}
+
+/// decodes `count` consecutive instructions starting at `addr`
+pub fn disassemble_range(bus: &mut Bus, addr: u16, count: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut addr = addr;
+
+    for _ in 0..count {
+        let inst = disassemble(bus, addr);
+        addr = addr.wrapping_add(inst.len);
+        instructions.push(inst);
+    }
+
+    instructions
+}
+
+fn prg_rom_offset(bus: &Bus, addr: u16) -> Option<usize> {
+    let rom = &bus.ppu.rom;
+    rom.mapper.prg_rom_offset(&rom.cart, addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, disassemble_range, Symbols};
+    use crate::{bus::Bus, cpu::memory::Memory, tests::nestest};
+
+    fn bus_with(addr: u16, code: &[u8]) -> Bus {
+        let mut bus = Bus::new(nestest(), 44100.0);
+
+        for (i, &byte) in code.iter().enumerate() {
+            bus.write_byte(addr + i as u16, byte);
+        }
+
+        bus
+    }
+
+    fn format_all(code: &[u8]) -> Vec<String> {
+        let mut bus = bus_with(0x0300, code);
+        let mut formatted = Vec::new();
+        let mut addr = 0x0300;
+
+        while addr < 0x0300 + code.len() as u16 {
+            let inst = disassemble(&mut bus, addr);
+            addr += inst.len;
+            formatted.push(inst.format(None));
+        }
+
+        formatted
+    }
+
+    #[test]
+    fn decoding() {
+        #[rustfmt::skip]
+        let mut bus = bus_with(0x0300, &[
+            0xA9, 0x10,       // LDA #$10
+            0x8D, 0x00, 0x20, // STA $2000
+            0x0A,             // ASL A
+            0xEA,             // NOP
+            0x04, 0x12,       // NOP $12 (unofficial)
+        ]);
+
+        let instructions = disassemble_range(&mut bus, 0x0300, 5);
+        let summary = instructions
+            .iter()
+            .map(|inst| {
+                (
+                    inst.addr,
+                    inst.opcode,
+                    inst.mnemonic,
+                    inst.len,
+                    inst.official,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            summary,
+            [
+                (0x0300, 0xA9, "LDA", 2, true),
+                (0x0302, 0x8D, "STA", 3, true),
+                (0x0305, 0x0A, "ASL", 1, true),
+                (0x0306, 0xEA, "NOP", 1, true),
+                (0x0307, 0x04, "NOP", 2, false),
+            ]
+        );
+
+        assert_eq!(instructions[1].operand(), 0x2000);
+        assert_eq!(instructions[1].format_bytes(), "8D 00 20");
+        assert_eq!(instructions[2].operand(), 0);
+        assert_eq!(instructions[0].prg_offset, None);
+    }
+
+    #[test]
+    fn operand_formatting() {
+        #[rustfmt::skip]
+        let code = [
+            0xA9, 0x10,       // immediate
+            0xA5, 0x10,       // zero page
+            0xB5, 0x10,       // zero page,X
+            0xB6, 0x10,       // zero page,Y
+            0xAD, 0x34, 0x12, // absolute
+            0xBD, 0x34, 0x12, // absolute,X
+            0xB9, 0x34, 0x12, // absolute,Y
+            0x6C, 0x34, 0x12, // indirect
+            0xA1, 0x24,       // (indirect,X)
+            0xB1, 0x24,       // (indirect),Y
+            0x4A,             // accumulator
+            0x60,             // implied
+        ];
+
+        assert_eq!(
+            format_all(&code),
+            [
+                "LDA #$10",
+                "LDA $10",
+                "LDA $10,X",
+                "LDX $10,Y",
+                "LDA $1234",
+                "LDA $1234,X",
+                "LDA $1234,Y",
+                "JMP ($1234)",
+                "LDA ($24,X)",
+                "LDA ($24),Y",
+                "LSR A",
+                "RTS",
+            ]
+        );
+    }
+
+    #[test]
+    fn branch_targets() {
+        // BNE to itself, BEQ forward and BPL backward
+        let code = [0xD0, 0xFE, 0xF0, 0x7F, 0x10, 0x80];
+        assert_eq!(format_all(&code), ["BNE $0300", "BEQ $0383", "BPL $0286"]);
+    }
+
+    #[test]
+    fn operands_wrap_around_the_address_space() {
+        // BPL at $0000 back to $FFFE
+        let mut bus = bus_with(0x0000, &[0x10, 0xFC]);
+        let inst = disassemble(&mut bus, 0x0000);
+        assert_eq!(inst.target(), Some(0xFFFE));
+        assert_eq!(inst.format(None), "BPL $FFFE");
+
+        // the high byte of the IRQ vector of nestest is CMP zero page,
+        // its operand is read at $0000
+        let inst = disassemble(&mut bus, 0xFFFF);
+        assert_eq!(inst.bytes[..inst.len as usize], [0xC5, 0x10]);
+        assert_eq!(inst.format(None), "CMP $10");
+        assert_eq!(inst.prg_offset, Some(0x3FFF));
+    }
+
+    #[test]
+    fn labels() {
+        #[rustfmt::skip]
+        let mut bus = bus_with(0x0300, &[
+            0x8D, 0x00, 0x20, // STA $2000
+            0x4C, 0xF5, 0xC5, // JMP $C5F5
+            0xBD, 0x00, 0x04, // LDA $0400,X
+        ]);
+
+        let mut symbols = Symbols::new();
+        symbols.add(0x2000, None, "PPUCTRL");
+        symbols.add(0x0300, None, "start");
+        // nestest is mirrored at $8000 and $C000, $C5F5 is at $05F5 in the PRG ROM
+        symbols.add(0xC5F5, Some(0x05F5), "main");
+
+        let instructions = disassemble_range(&mut bus, 0x0300, 3);
+        let formatted = instructions
+            .iter()
+            .map(|inst| inst.format(Some(&symbols)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(formatted, ["STA PPUCTRL", "JMP main", "LDA $0400,X"]);
+        assert_eq!(instructions[0].label(&symbols), Some("start"));
+        assert_eq!(instructions[1].label(&symbols), None);
+
+        // a label of the same address in another bank doesn't match
+        let mut symbols = Symbols::new();
+        symbols.add(0xC5F5, Some(0x45F5), "other_bank");
+        assert_eq!(instructions[1].format(Some(&symbols)), "JMP $C5F5");
+    }
+}
@@ -0,0 +1,287 @@
+// labels loaded from FCEUX .nl files or ca65/ld65 .dbg files
+// https://fceux.com/web/help/NLFilesFormat.html
+// https://cc65.github.io/doc/ld65.html#s5
+
+use std::collections::HashMap;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const INES_HEADER_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum SymbolsError {
+    InvalidLine(usize),
+    InvalidNumber(String),
+}
+
+#[derive(Default)]
+pub struct Symbols {
+    /// labels in RAM and in unbanked memory
+    by_addr: HashMap<u16, String>,
+    /// labels in PRG ROM, by offset in the PRG ROM
+    by_prg_offset: HashMap<usize, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_addr.len() + self.by_prg_offset.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.by_addr.clear();
+        self.by_prg_offset.clear();
+    }
+
+    /// `prg_offset` is the offset in the PRG ROM for labels in $8000-$FFFF
+    pub fn add(&mut self, addr: u16, prg_offset: Option<usize>, name: &str) {
+        match prg_offset {
+            Some(offset) => self.by_prg_offset.insert(offset, name.to_owned()),
+            None => self.by_addr.insert(addr, name.to_owned()),
+        };
+    }
+
+    /// ROM labels are only matched in the bank they were defined in
+    pub fn lookup(&self, addr: u16, prg_offset: Option<usize>) -> Option<&str> {
+        prg_offset
+            .and_then(|offset| self.by_prg_offset.get(&offset))
+            .or_else(|| self.by_addr.get(&addr))
+            .map(String::as_str)
+    }
+
+    /// loads an FCEUX name list, `bank` is the 16KB PRG bank of a `game.nes.<bank>.nl` file
+    /// and None for `game.nes.ram.nl`
+    pub fn load_nl(&mut self, text: &str, bank: Option<usize>) -> Result<(), SymbolsError> {
+        // $C000#Reset#comment, arrays are written as $0300/10#buffer#
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            let Some(line) = line.strip_prefix('$') else {
+                continue;
+            };
+
+            let mut parts = line.splitn(3, '#');
+            let addr = parts.next().unwrap_or_default();
+            let name = parts.next().ok_or(SymbolsError::InvalidLine(index + 1))?;
+
+            if name.is_empty() {
+                continue;
+            }
+
+            let (addr, size) = match addr.split_once('/') {
+                Some((addr, size)) => (addr, parse_hex(size)? as u16),
+                None => (addr, 1),
+            };
+
+            let addr = parse_hex(addr)? as u16;
+
+            for i in 0..size.max(1) {
+                let addr = addr.wrapping_add(i);
+                let name = if i == 0 {
+                    name.to_owned()
+                } else {
+                    format!("{}+{}", name, i)
+                };
+
+                let prg_offset = match (bank, addr) {
+                    (Some(bank), 0x8000..=0xFFFF) => {
+                        Some(bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1)))
+                    }
+                    _ => None,
+                };
+
+                self.add(addr, prg_offset, &name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// loads the labels of a debug info file generated by `ld65 --dbgfile`
+    pub fn load_dbg(&mut self, text: &str) -> Result<(), SymbolsError> {
+        // segment id -> (start address, offset in the output file)
+        let mut segments = HashMap::new();
+        let mut labels = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let Some((kind, attrs)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+
+            let attrs = parse_attributes(attrs);
+            let get = |key: &str| {
+                attrs
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| *v)
+                    .ok_or(SymbolsError::InvalidLine(index + 1))
+            };
+
+            match kind {
+                "seg" => {
+                    let id = parse_number(get("id")?)?;
+                    let start = parse_number(get("start")?)?;
+                    let ooffs = get("ooffs").ok().map(parse_number).transpose()?;
+                    segments.insert(id, (start, ooffs));
+                }
+                "sym" if get("type").ok() == Some("lab") => {
+                    let name = get("name")?.trim_matches('"');
+                    let val = parse_number(get("val")?)?;
+                    let seg = get("seg").ok().map(parse_number).transpose()?;
+                    labels.push((name.to_owned(), val, seg));
+                }
+                _ => {}
+            }
+        }
+
+        for (name, val, seg) in labels {
+            let addr = val as u16;
+            let prg_offset = match seg.and_then(|id| segments.get(&id)) {
+                Some(&(start, Some(ooffs))) if addr >= 0x8000 => {
+                    (ooffs + val).checked_sub(start + INES_HEADER_SIZE)
+                }
+                _ => None,
+            };
+
+            self.add(addr, prg_offset, &name);
+        }
+
+        Ok(())
+    }
+}
+
+// key=value pairs separated by commas, values may be quoted strings
+fn parse_attributes(attrs: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = attrs.trim();
+
+    while !rest.is_empty() {
+        let Some((key, tail)) = rest.split_once('=') else {
+            break;
+        };
+
+        let end = if let Some(quoted) = tail.strip_prefix('"') {
+            quoted.find('"').map_or(tail.len(), |i| i + 2)
+        } else {
+            tail.find(',').unwrap_or(tail.len())
+        };
+
+        pairs.push((key.trim(), &tail[..end]));
+        rest = tail[end..].trim_start_matches(',');
+    }
+
+    pairs
+}
+
+fn parse_hex(text: &str) -> Result<usize, SymbolsError> {
+    usize::from_str_radix(text.trim(), 16).map_err(|_| SymbolsError::InvalidNumber(text.to_owned()))
+}
+
+fn parse_number(text: &str) -> Result<usize, SymbolsError> {
+    let res = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    res.map_err(|_| SymbolsError::InvalidNumber(text.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Symbols, SymbolsError};
+
+    #[test]
+    fn nl_files() {
+        let text = "\
+            $C000#Reset#entry point\n\
+            $C010#Loop#\n\
+            $0300/3#buffer#\n\
+            $0010##\n\
+            ; comments and other lines are ignored\n\
+            \n";
+
+        let mut symbols = Symbols::new();
+        symbols.load_nl(text, Some(1)).unwrap();
+
+        assert_eq!(symbols.len(), 5);
+        assert_eq!(symbols.lookup(0xC000, Some(0x4000)), Some("Reset"));
+        assert_eq!(symbols.lookup(0xC010, Some(0x4010)), Some("Loop"));
+        // the label is only defined in the second bank
+        assert_eq!(symbols.lookup(0xC000, Some(0x0000)), None);
+
+        assert_eq!(symbols.lookup(0x0300, None), Some("buffer"));
+        assert_eq!(symbols.lookup(0x0302, None), Some("buffer+2"));
+        assert_eq!(symbols.lookup(0x0303, None), None);
+        assert_eq!(symbols.lookup(0x0010, None), None);
+    }
+
+    #[test]
+    fn ram_nl_files() {
+        let mut symbols = Symbols::new();
+        symbols
+            .load_nl("$0000#temp#\n$8000#mapper_reg#\n", None)
+            .unwrap();
+
+        assert_eq!(symbols.lookup(0x0000, None), Some("temp"));
+        // unbanked labels match every bank
+        assert_eq!(symbols.lookup(0x8000, Some(0x1234)), Some("mapper_reg"));
+    }
+
+    #[test]
+    fn malformed_nl_lines() {
+        let mut symbols = Symbols::new();
+
+        assert!(matches!(
+            symbols.load_nl("$C000#Reset#\n$C010\n", Some(0)),
+            Err(SymbolsError::InvalidLine(2))
+        ));
+        assert!(matches!(
+            symbols.load_nl("$C0G0#Reset#\n", Some(0)),
+            Err(SymbolsError::InvalidNumber(number)) if number == "C0G0"
+        ));
+        assert!(matches!(
+            symbols.load_nl("$0300/X#buffer#\n", None),
+            Err(SymbolsError::InvalidNumber(number)) if number == "X"
+        ));
+    }
+
+    #[test]
+    fn dbg_files() {
+        let text = r#"version	major=2,minor=0
+seg	id=0,name="CODE",start=0x008000,size=0x0010,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=1,name="ZEROPAGE",start=0x000000,size=0x0002,addrsize=zeropage,type=rw
+sym	id=0,name="reset",addrsize=absolute,scope=0,def=1,ref=2,val=0x8004,seg=0,type=lab
+sym	id=1,name="counter",addrsize=zeropage,scope=0,def=3,val=0x1,seg=1,type=lab
+sym	id=2,name="SIZE",addrsize=zeropage,scope=0,def=4,val=0x10,type=equ
+"#;
+
+        let mut symbols = Symbols::new();
+        symbols.load_dbg(text).unwrap();
+
+        // constants are not labels
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.lookup(0x8004, Some(4)), Some("reset"));
+        assert_eq!(symbols.lookup(0x8004, Some(0x4004)), None);
+        assert_eq!(symbols.lookup(0x0001, None), Some("counter"));
+    }
+
+    #[test]
+    fn malformed_dbg_lines() {
+        let mut symbols = Symbols::new();
+
+        assert!(matches!(
+            symbols.load_dbg("seg\tid=0,name=\"CODE\",size=0x10\n"),
+            Err(SymbolsError::InvalidLine(1))
+        ));
+        assert!(matches!(
+            symbols.load_dbg("version\tmajor=2\nsym\tid=0,name=\"reset\",val=0x80G0,type=lab\n"),
+            Err(SymbolsError::InvalidNumber(number)) if number == "0x80G0"
+        ));
+    }
+}
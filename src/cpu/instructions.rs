@@ -1,7 +1,7 @@
 use super::memory::Memory;
-use super::opcodes::INST_CYCLES;
+use super::opcodes::{AddressingMode, INST_CYCLES, OPCODES};
 use super::{Status, CPU};
-use crate::bus::Interrupt;
+use crate::{bus::Interrupt, event_log::EventKind};
 
 const NMI_VECTOR: u16 = 0xfffa;
 const IRQ_VECTOR: u16 = 0xfffe;
@@ -167,59 +167,156 @@ impl CPU {
 
     pub fn step(&mut self) -> u32 {
         self.instr_cycles = 0;
+        self.bus.begin_instruction(self.total_cycles);
 
-        if self.bus.dma_transfer {
-            self.bus.dma_transfer = false;
-            self.stall += 513 + (self.total_cycles & 1);
-        }
-
-        if self.bus.apu.is_stalling_cpu() {
-            return 1;
-        }
-
+        // only the reset sequence stalls the CPU here,
+        // the DMA units halt it from the bus on a read cycle, see `Bus::halt_for_dma`
         if self.stall > 0 {
             self.stall -= 1;
             return 1;
         }
 
-        match self.bus.pull_interrupt() {
+        match self.bus.pull_interrupt(self.polled_interrupt_disable) {
             Interrupt::None => {}
-            Interrupt::Irq => {
-                if !self.status.contains(Status::INTERRUPT_DISABLE) {
-                    self.irq();
-                }
-            }
+            Interrupt::Irq => self.irq(),
             Interrupt::Nmi => self.nmi(),
         }
 
+        if self.bus.debugger.is_some() && self.debugger_break() {
+            self.total_cycles += self.instr_cycles + self.bus.take_dma_cycles();
+            return self.instr_cycles;
+        }
+
+        if self.bus.tracer.is_some() {
+            self.trace_instruction();
+        }
+
+        if self.bus.ppu.rom.cdl.is_some() {
+            self.bus.log_instruction(self.pc);
+        }
+
         let op_code = self.next_byte();
+
+        if self.is_cycle_accurate() {
+            self.implied_dummy_read(op_code);
+        }
+
+        let interrupt_disable = self.status.contains(Status::INTERRUPT_DISABLE);
+
         self.instructions[op_code as usize](self);
 
+        // CLI, SEI and PLP change the interrupt disable flag after the interrupt lines are polled
+        self.polled_interrupt_disable = match op_code {
+            0x28 | 0x58 | 0x78 => interrupt_disable,
+            _ => self.status.contains(Status::INTERRUPT_DISABLE),
+        };
+
         let instr_cycles = self.instr_cycles + INST_CYCLES[op_code as usize];
-        self.total_cycles += instr_cycles;
+        // the PPU and the APU already ran during the cycles stolen by the DMA units
+        self.total_cycles += instr_cycles + self.bus.take_dma_cycles();
 
         instr_cycles
     }
 
+    // single byte instructions read the byte following the opcode and discard it,
+    // unofficial opcodes are executed as single byte NOPs
+    fn implied_dummy_read(&mut self, op_code: u8) {
+        let opcode = &OPCODES[op_code as usize];
+
+        if !opcode.official
+            || matches!(
+                opcode.mode,
+                AddressingMode::Implied | AddressingMode::Accumulator
+            )
+        {
+            self.dummy_read(self.pc);
+        }
+    }
+
+    fn debugger_break(&mut self) -> bool {
+        match self.bus.debugger.take() {
+            Some(mut debugger) => {
+                let stop = debugger.before_instruction(self);
+                self.bus.debugger = Some(debugger);
+                stop
+            }
+            None => false,
+        }
+    }
+
+    fn trace_instruction(&mut self) {
+        if let Some(mut tracer) = self.bus.tracer.take() {
+            tracer.trace(self, self.total_cycles + self.instr_cycles);
+            self.bus.tracer = Some(tracer);
+        }
+    }
+
     // interrupts
     fn brk(&mut self) {
-        self.push_word(self.pc);
-        self.php();
-        self.sei();
-        self.pc = self.bus.read_word(IRQ_VECTOR);
+        // the byte following the opcode is skipped, it was read while the opcode was decoded
+        self.pc = self.pc.wrapping_add(1);
+
+        if self.interrupt(true) == NMI_VECTOR {
+            self.bus.ppu.log_event(EventKind::Nmi, self.pc, 0);
+        }
+
+        self.bus.skip_interrupt_poll();
+    }
+
+    // the opcode fetch is replaced by two reads of the interrupted instruction
+    fn interrupt_dummy_reads(&mut self) {
+        self.dummy_read(self.pc);
+        self.dummy_read(self.pc);
     }
 
     fn nmi(&mut self) {
+        self.interrupt_dummy_reads();
         self.push_word(self.pc);
-        self.php();
+        self.push_status(false);
         self.sei();
         self.pc = self.bus.read_word(NMI_VECTOR);
         self.instr_cycles += 7;
+        self.bus.ppu.log_event(EventKind::Nmi, self.pc, 0);
     }
 
     fn irq(&mut self) {
-        self.brk();
+        self.interrupt_dummy_reads();
+
+        let kind = match self.interrupt(false) {
+            NMI_VECTOR => EventKind::Nmi,
+            _ => EventKind::Irq,
+        };
+
         self.instr_cycles += 7;
+        self.bus.ppu.log_event(kind, self.pc, 0);
+    }
+
+    // an NMI occurring before the status is pushed hijacks BRK and IRQ: the NMI vector
+    // is fetched instead of the IRQ vector and the NMI is not serviced again, returns the vector used
+    // https://www.nesdev.org/wiki/CPU_interrupts#Interrupt_hijacking
+    fn interrupt(&mut self, brk: bool) -> u16 {
+        self.push_word(self.pc);
+
+        let vector = if self.bus.poll_nmi() {
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        };
+
+        self.push_status(brk);
+        self.sei();
+        self.pc = self.bus.read_word(vector);
+
+        vector
+    }
+
+    // the B flag is only set in the status pushed by BRK and PHP
+    // https://www.nesdev.org/wiki/Status_flags#The_B_flag
+    fn push_status(&mut self, break_flag: bool) {
+        let mut status = Status::from_bits_truncate(self.status.bits());
+        status.insert(Status::BREAK2);
+        status.set(Status::BREAK1, break_flag);
+        self.push(status.bits());
     }
 
     // NOP: No Operation
@@ -735,6 +832,7 @@ impl CPU {
 
     fn asl(&mut self, addr: u16) {
         let mut val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         self.status.set(Status::CARRY, val & 128 == 128);
         val <<= 1;
         self.bus.write_byte(addr, val);
@@ -773,6 +871,7 @@ impl CPU {
 
     fn lsr(&mut self, addr: u16) {
         let val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         self.status.set(Status::CARRY, val & 1 == 1);
         let val = val >> 1;
         self.bus.write_byte(addr, val);
@@ -811,6 +910,7 @@ impl CPU {
 
     fn inc(&mut self, addr: u16) {
         let val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         let val = val.wrapping_add(1);
         self.bus.write_byte(addr, val);
         self.toggle_nz(val);
@@ -858,6 +958,7 @@ impl CPU {
 
     fn dec(&mut self, addr: u16) {
         let val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         let val = if val == 0 { 0xff } else { val - 1 };
         self.bus.write_byte(addr, val);
         self.toggle_nz(val);
@@ -932,96 +1033,79 @@ impl CPU {
         self.jmp(addr);
     }
 
-    fn branch_rel(&mut self) {
+    // the offset is fetched even when the branch isn't taken
+    fn branch_rel(&mut self, condition: bool) {
         let rel: i8 = self.next_byte() as i8;
+
+        if !condition {
+            return;
+        }
+
         let jump_addr = self.pc.wrapping_add(rel as u16);
         let prev_pc = self.pc;
         self.pc = jump_addr;
         self.instr_cycles += 1;
 
+        if !self.page_crossed(prev_pc, jump_addr) {
+            // https://www.nesdev.org/wiki/CPU_interrupts#Branch_instructions_and_interrupts
+            self.bus.poll_interrupts_early();
+        }
+
+        // the next opcode is read while the offset is added
+        self.dummy_read(prev_pc);
+
         if self.page_crossed(prev_pc, jump_addr) {
             self.instr_cycles += 1;
+            self.dummy_read((prev_pc & 0xff00) | (jump_addr & 0x00ff));
         }
     }
 
     // BCS - Branch if Carry Clear
 
     fn bcc_rel(&mut self) {
-        if !self.status.contains(Status::CARRY) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(!self.status.contains(Status::CARRY));
     }
 
     // BCS - Branch if Carry Set
 
     fn bcs_rel(&mut self) {
-        if self.status.contains(Status::CARRY) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(self.status.contains(Status::CARRY));
     }
 
     // BEQ - Branch if Equal
 
     fn beq_rel(&mut self) {
-        if self.status.contains(Status::ZERO) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(self.status.contains(Status::ZERO));
     }
 
     // BNE - Branch if Not Equal
 
     fn bne_rel(&mut self) {
-        if !self.status.contains(Status::ZERO) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(!self.status.contains(Status::ZERO));
     }
 
     // BPL - Branch if Positive
 
     fn bpl_rel(&mut self) {
-        if !self.status.contains(Status::NEGATIVE) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(!self.status.contains(Status::NEGATIVE));
     }
 
     // BMI - Branch if Minus
 
     fn bmi_rel(&mut self) {
-        if self.status.contains(Status::NEGATIVE) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(self.status.contains(Status::NEGATIVE));
     }
 
     // BVC - Branch if Overflow Clear
 
     fn bvc_rel(&mut self) {
-        if !self.status.contains(Status::OVERFLOW) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(!self.status.contains(Status::OVERFLOW));
     }
 
     // BVS - Branch if Overflow Set
 
     fn bvs_rel(&mut self) {
-        if self.status.contains(Status::OVERFLOW) {
-            self.branch_rel();
-        } else {
-            self.pc += 1;
-        }
+        self.branch_rel(self.status.contains(Status::OVERFLOW));
     }
 
     // CLC - Clear Carry Flag
@@ -1172,6 +1256,7 @@ impl CPU {
     // PLA - Pull Accumulator
 
     fn pla(&mut self) {
+        self.dummy_stack_read();
         let a = self.pull();
         self.a = a;
         self.toggle_nz(a);
@@ -1180,13 +1265,12 @@ impl CPU {
     // PHP - Push Processor Status
 
     fn php(&mut self) {
-        // set the break flags
-        let status_flags = self.status.bits() | Status::BREAK1.bits() | Status::BREAK2.bits();
-        self.push(status_flags);
+        self.push_status(true);
     }
 
     // PLP - Pull Processor Status
     fn plp(&mut self) {
+        self.dummy_stack_read();
         let mut flags = self.pull();
         flags &= 0b11101111;
         flags |= 0b00100000;
@@ -1196,20 +1280,28 @@ impl CPU {
     // JSR - Jump to Subroutine
 
     fn jsr(&mut self) {
-        let ret_addr = self.pc + 1;
-        self.push_word(ret_addr);
-        let target_addr = self.absolute();
-        self.pc = target_addr;
+        // the high byte of the target is fetched after the return address is pushed,
+        // the return address points to it
+        let low = self.next_byte() as u16;
+        self.dummy_stack_read();
+        self.push_word(self.pc);
+        let high = self.bus.read_byte(self.pc) as u16;
+        self.pc = high << 8 | low;
     }
 
     // RTS - Return from Subroutine
 
     fn rts(&mut self) {
-        self.pc = self.pull_word() + 1;
+        self.dummy_stack_read();
+        let ret_addr = self.pull_word();
+        // the return address is read while it is incremented
+        self.dummy_read(ret_addr);
+        self.pc = ret_addr.wrapping_add(1);
     }
 
     // RTI - Return from Interrupt
 
+    // the dummy stack read is made by plp
     fn rti(&mut self) {
         self.plp();
         self.pc = self.pull_word();
@@ -1238,6 +1330,7 @@ impl CPU {
 
     fn rol(&mut self, addr: u16) {
         let mut val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         let next_carry = (val >> 7) == 1;
         val <<= 1;
         val |= if self.status.contains(Status::CARRY) {
@@ -1288,6 +1381,7 @@ impl CPU {
 
     fn ror(&mut self, addr: u16) {
         let mut val = self.bus.read_byte(addr);
+        self.dummy_write(addr, val);
         let old_carry = self.status.contains(Status::CARRY);
         self.status.set(Status::CARRY, val & 1 == 1);
 
@@ -1336,3 +1430,284 @@ impl CPU {
         self.ror(addr);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bus::Bus,
+        cpu::{rom::ROM, CpuMode},
+        tests::{nrom, rom_bytes},
+    };
+
+    const PROGRAM_ADDR: u16 = 0x0300;
+    // zero page pointer used by the indirect addressing modes
+    const POINTER_ADDR: u16 = 0x0010;
+
+    fn cycle_mode_cpu() -> CPU {
+        let mut bus = Bus::new(nrom(), 44100.0);
+        bus.set_cpu_mode(CpuMode::Cycle);
+        CPU::new(bus)
+    }
+
+    // returns the number of cycles of the instruction and the number of bus accesses it made
+    fn run_opcode(op_code: u8, index: u8, status: u8) -> (u32, u32) {
+        let mut cpu = cycle_mode_cpu();
+
+        // operand $0210, the pointer at $10 also points to $0210
+        for (i, byte) in [op_code, 0x10, 0x02].into_iter().enumerate() {
+            cpu.bus.write_byte(PROGRAM_ADDR + i as u16, byte);
+        }
+
+        for addr in [POINTER_ADDR, POINTER_ADDR.wrapping_add(index as u16) & 0xFF] {
+            cpu.bus.write_byte(addr, 0x10);
+            cpu.bus.write_byte(addr + 1, 0x02);
+        }
+
+        cpu.pc = PROGRAM_ADDR;
+        cpu.x = index;
+        cpu.y = index;
+        cpu.status.update(status);
+
+        let cycles = cpu.step();
+        (cycles, cpu.bus.instruction_accesses())
+    }
+
+    // the 6502 accesses the bus on every cycle
+    #[test]
+    fn cycle_mode_accesses_the_bus_on_every_cycle() {
+        for op_code in 0..=255u8 {
+            if !OPCODES[op_code as usize].official {
+                continue;
+            }
+
+            let expected = INST_CYCLES[op_code as usize];
+            let is_branch = OPCODES[op_code as usize].mode == AddressingMode::Relative;
+
+            for status in [0x00, 0xFF] {
+                let (cycles, accesses) = run_opcode(op_code, 0, status);
+                assert_eq!(
+                    accesses, cycles,
+                    "opcode {op_code:02X}, status {status:02X}"
+                );
+
+                if !is_branch {
+                    assert_eq!(accesses, expected, "opcode {op_code:02X}");
+                }
+
+                // indexed reads take an extra cycle when the index crosses a page
+                let (cycles, accesses) = run_opcode(op_code, 0xFF, status);
+                assert_eq!(accesses, cycles, "opcode {op_code:02X}, crossing a page");
+            }
+        }
+    }
+
+    const RESET_ADDR: u16 = 0xC000;
+    const CODE_ADDR: u16 = 0xC100;
+    const IRQ_ADDR: u16 = 0xC200;
+    const NMI_ADDR: u16 = 0xC300;
+
+    // the handlers store the return address at $10-$11 and the pushed status at $12,
+    // then loop forever
+    #[rustfmt::skip]
+    const HANDLER: [u8; 19] = [
+        0xBA,             // TSX
+        0xBD, 0x02, 0x01, // LDA $0102,X
+        0x85, 0x10,       // STA $10
+        0xBD, 0x03, 0x01, // LDA $0103,X
+        0x85, 0x11,       // STA $11
+        0xBD, 0x01, 0x01, // LDA $0101,X
+        0x85, 0x12,       // STA $12
+        0x4C, 0x00, 0x00, // JMP to itself, see `handler`
+    ];
+
+    // NROM cartridge filled with NOPs, the interrupt handlers record the interrupted code
+    fn interrupt_test_cpu(mode: CpuMode, reset: &[u8], code: &[u8], irq: &[u8]) -> CPU {
+        let mut bytes = rom_bytes(0, 1);
+        let prg = &mut bytes[16..16 + 0x4000];
+        prg.fill(0xEA);
+        let nmi = handler(NMI_ADDR);
+
+        for (addr, bytes) in [
+            (RESET_ADDR, reset),
+            (CODE_ADDR, code),
+            (IRQ_ADDR, irq),
+            (NMI_ADDR, &nmi[..]),
+        ] {
+            let offset = (addr - 0xC000) as usize;
+            prg[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        for (i, addr) in [NMI_ADDR, RESET_ADDR, IRQ_ADDR].into_iter().enumerate() {
+            prg[0x3FFA + i * 2..0x3FFC + i * 2].copy_from_slice(&addr.to_le_bytes());
+        }
+
+        let mut bus = Bus::new(ROM::new(bytes).unwrap(), 44100.0);
+        bus.set_cpu_mode(mode);
+        CPU::new(bus)
+    }
+
+    fn handler(addr: u16) -> [u8; 19] {
+        let mut handler = HANDLER;
+        handler[17..].copy_from_slice(&(addr + 16).to_le_bytes());
+        handler
+    }
+
+    fn run_instruction(cpu: &mut CPU) {
+        let cycles = cpu.step();
+        cpu.bus.advance(cycles);
+        cpu.bus.sync();
+    }
+
+    // (return address, pushed status) recorded by the handler
+    fn recorded_interrupt(cpu: &mut CPU) -> (u16, u8) {
+        let addr = u16::from_le_bytes([cpu.bus.peek_byte(0x10), cpu.bus.peek_byte(0x11)]);
+        (addr, cpu.bus.peek_byte(0x12))
+    }
+
+    // runs `code` at $C100 while the frame IRQ of the APU is asserted
+    fn irq_after(mode: CpuMode, code: &[u8]) -> (u16, u8) {
+        #[rustfmt::skip]
+        let reset = [
+            0x78,             // SEI
+            0xA2, 0xFF,       // LDX #$FF
+            0x9A,             // TXS
+            0xA9, 0x00,       // LDA #$00
+            0x8D, 0x17, 0x40, // STA $4017, frame IRQ enabled
+            0x4C, 0x09, 0xC0, // JMP $C009
+        ];
+        let mut cpu = interrupt_test_cpu(mode, &reset, code, &handler(IRQ_ADDR));
+
+        while !cpu.bus.apu.is_asserting_irq() {
+            run_instruction(&mut cpu);
+        }
+
+        run_instruction(&mut cpu);
+        cpu.pc = CODE_ADDR;
+
+        for _ in 0..40 {
+            run_instruction(&mut cpu);
+        }
+
+        recorded_interrupt(&mut cpu)
+    }
+
+    // runs `code` after `sled` cycles of NOPs, starting a few cycles before the vblank NMI:
+    // the longer the sled, the earlier the NMI lands in `code`, returns the return address
+    // relative to the start of `code` and the status recorded by the NMI handler
+    fn nmi_during(mode: CpuMode, sled: u16, code: &[u8]) -> (i32, u8) {
+        #[rustfmt::skip]
+        let reset = [
+            0x78,             // SEI
+            0xA2, 0xFF,       // LDX #$FF
+            0x9A,             // TXS
+            0xA9, 0x80,       // LDA #$80
+            0x8D, 0x00, 0x20, // STA $2000, NMI enabled
+            0x4C, 0x09, 0xC0, // JMP $C009
+        ];
+        // LDA $00 takes 3 cycles and NOP 2 cycles
+        let mut sled_code = vec![];
+        let mut cycles = sled as usize;
+
+        if cycles % 2 == 1 {
+            sled_code.extend([0xA5, 0x00]);
+            cycles -= 3;
+        }
+
+        sled_code.resize(sled_code.len() + cycles / 2, 0xEA);
+
+        let start = CODE_ADDR + sled_code.len() as u16;
+        sled_code.extend(code);
+
+        // the IRQ handler runs NOPs
+        let irq = [0xEA, 0xEA, 0x4C, 0x00, 0xC2];
+        let mut cpu = interrupt_test_cpu(mode, &reset, &sled_code, &irq);
+
+        while cpu.bus.ppu.get_frame_count() < 2
+            || cpu.bus.ppu.get_scanline() != 240
+            || cpu.bus.ppu.cycle < 300
+        {
+            run_instruction(&mut cpu);
+        }
+
+        cpu.pc = CODE_ADDR;
+
+        for _ in 0..40 {
+            run_instruction(&mut cpu);
+        }
+
+        let (addr, status) = recorded_interrupt(&mut cpu);
+        (addr as i32 - start as i32, status)
+    }
+
+    // https://www.nesdev.org/wiki/CPU_interrupts#Delayed_IRQ_response_after_CLI,_SEI,_and_PLP
+    #[test]
+    fn cli_sei_and_plp_change_the_interrupt_flag_after_the_poll() {
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            // the IRQ stays disabled
+            assert_eq!(irq_after(mode, &[0xEA, 0xEA, 0xEA]), (0, 0));
+            // CLI, the NOP runs before the IRQ
+            assert_eq!(irq_after(mode, &[0x58, 0xEA, 0xEA, 0xEA]), (0xC102, 0x22));
+            // CLI then SEI, the IRQ is taken after SEI and the interrupt flag is pushed set
+            assert_eq!(irq_after(mode, &[0x58, 0x78, 0xEA, 0xEA]), (0xC102, 0x26));
+            // LDA #$00, PHA, PLP, the NOP runs before the IRQ
+            assert_eq!(
+                irq_after(mode, &[0xA9, 0x00, 0x48, 0x28, 0xEA, 0xEA]),
+                (0xC105, 0x20)
+            );
+        }
+    }
+
+    // https://www.nesdev.org/wiki/CPU_interrupts#Branch_instructions_and_interrupts
+    #[test]
+    fn taken_branches_delay_interrupts() {
+        // CLC, BCC +0 or LDA $00, NOP, NOP, NOP, JMP $C400
+        let branch = [0x18, 0x90, 0x00, 0xEA, 0xEA, 0xEA, 0x4C, 0x00, 0xC4];
+        let mut reference = branch;
+        reference[1] = 0xA5;
+
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            let mut delayed = false;
+
+            for sled in 2..24 {
+                let (branch_ret, _) = nmi_during(mode, sled, &branch);
+                let (reference_ret, _) = nmi_during(mode, sled, &reference);
+
+                // the branch takes 3 cycles like LDA $00 but its last cycle doesn't poll,
+                // an NMI asserted on this cycle is taken after the following NOP
+                if reference_ret == 3 && branch_ret == 4 {
+                    delayed = true;
+                } else {
+                    assert_eq!(branch_ret, reference_ret, "{mode:?}, sled {sled}");
+                }
+            }
+
+            assert!(delayed, "{mode:?}");
+        }
+    }
+
+    // https://www.nesdev.org/wiki/CPU_interrupts#Interrupt_hijacking
+    #[test]
+    fn nmi_hijacks_brk() {
+        // BRK, padding, NOP, NOP, JMP $C400
+        let code = [0x00, 0x00, 0xEA, 0xEA, 0x4C, 0x00, 0xC4];
+
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            let mut hijacked = false;
+
+            for sled in 2..24 {
+                let (ret, status) = nmi_during(mode, sled, &code);
+
+                // the NMI handler runs with the return address and the B flag pushed by BRK
+                if status & 0x10 != 0 {
+                    assert_eq!(ret, 2, "{mode:?}, sled {sled}");
+                    hijacked = true;
+                } else {
+                    assert_ne!(ret, 2, "{mode:?}, sled {sled}");
+                }
+            }
+
+            assert!(hijacked, "{mode:?}");
+        }
+    }
+}
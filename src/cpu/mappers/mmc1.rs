@@ -42,35 +42,17 @@ impl Mapper for MMC1 {
                 if cart.chr_rom_size == 0 {
                     self.chr_ram[addr as usize]
                 } else {
-                    let offset = self.chr_rom_offset(cart, addr);
+                    let offset = self.chr_bank_offset(addr);
                     cart.bytes[cart.chr_rom_start + offset]
                 }
             }
             0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
-            0x8000..=0xBFFF => {
-                let bank = match self.prg_mode {
-                    0 | 1 => self.prg_bank & 0xFE,
-                    2 => 0,
-                    3 => self.prg_bank,
-                    _ => unreachable!(),
-                };
-
-                let offset = addr as usize - 0x8000;
-                let addr = cart.prg_rom_start + (bank as usize * 0x4000) + offset;
-                cart.bytes[addr]
-            }
-            0xC000..=0xFFFF => {
-                let bank = match self.prg_mode {
-                    0 | 1 => self.prg_bank | 1,
-                    2 => self.prg_bank,
-                    3 => cart.prg_rom_size - 1,
-                    _ => unreachable!(),
-                };
-
-                let offset = (addr as usize - 0x8000) & 0x3fff;
-                let addr = cart.prg_rom_start + (bank as usize * 0x4000) + offset;
-                cart.bytes[addr]
+            0x8000..=0xFFFF => {
+                let offset = self.prg_rom_offset(cart, addr).unwrap();
+                cart.bytes[cart.prg_rom_start + offset]
             }
+            // nothing is mapped in the expansion area
+            0x4020..=0x5FFF => 0,
             _ => {
                 panic!("Invalid MMC1 read address: {:04X}", addr);
             }
@@ -83,8 +65,8 @@ impl Mapper for MMC1 {
                 if cart.chr_rom_size == 0 {
                     self.chr_ram[addr as usize] = val;
                 } else {
-                    let offset = self.chr_rom_offset(cart, addr);
-                    cart.bytes[cart.chr_rom_start + offset] = val;
+                    let offset = self.chr_bank_offset(addr);
+                    cart.patch(cart.chr_rom_start + offset, val);
                 }
             }
             0x6000..=0x7FFF => {
@@ -123,10 +105,41 @@ impl Mapper for MMC1 {
             _ => panic!("Invalid MMC1 write address: {:04X}", addr),
         }
     }
+
+    fn prg_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xBFFF => match self.prg_mode {
+                0 | 1 => self.prg_bank & 0xFE,
+                2 => 0,
+                3 => self.prg_bank,
+                _ => unreachable!(),
+            },
+            0xC000..=0xFFFF => match self.prg_mode {
+                0 | 1 => self.prg_bank | 1,
+                2 => self.prg_bank,
+                3 => cart.prg_rom_size - 1,
+                _ => unreachable!(),
+            },
+            _ => return None,
+        };
+
+        Some(bank as usize * 0x4000 + (addr & 0x3FFF) as usize)
+    }
+
+    fn chr_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF if cart.chr_rom_size > 0 => Some(self.chr_bank_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
 }
 
 impl MMC1 {
-    fn chr_rom_offset(&self, _: &Cart, addr: u16) -> usize {
+    fn chr_bank_offset(&self, addr: u16) -> usize {
         if self.chr_mode == 0 {
             // switch 8 KB at a time
             (addr as usize & 0xFFF)
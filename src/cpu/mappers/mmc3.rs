@@ -59,6 +59,8 @@ impl Mapper for MMC3 {
                 let offset = self.prg_offsets[idx] as usize + (addr & 0x1FFF) as usize;
                 cart.bytes[cart.prg_rom_start + offset]
             }
+            // nothing is mapped in the expansion area
+            0x4020..=0x5FFF => 0,
             _ => {
                 panic!("Invalid MMC3 read address: {:04X}", addr);
             }
@@ -112,14 +114,12 @@ impl Mapper for MMC3 {
                     self.prg_offsets[3] = (prg_pages - 1) as u32 * 0x2000;
                 }
             }
-            0xA000..=0xBFFF => {
-                if addr & 1 == 0 {
-                    cart.mirroring = if val & 1 == 0 {
-                        Mirroring::Vertical
-                    } else {
-                        Mirroring::Horizontal
-                    };
-                }
+            0xA000..=0xBFFF if addr & 1 == 0 => {
+                cart.mirroring = if val & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
             }
             0xC000..=0xDFFF => {
                 if addr & 1 == 0 {
@@ -139,11 +139,36 @@ impl Mapper for MMC3 {
         }
     }
 
-    fn is_asserting_irq(&mut self) -> bool {
-        let result = self.irq_asserted;
-        self.irq_asserted = false;
+    fn prg_rom_offset(&self, _: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => {
+                let idx = ((addr - 0x8000) / 0x2000) as usize;
+                Some(self.prg_offsets[idx] as usize + (addr & 0x1FFF) as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn chr_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF if cart.chr_rom_size > 0 => {
+                let idx = (addr / 0x0400) as usize;
+                Some(self.chr_offsets[idx] as usize + (addr & 0x3FF) as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
 
-        result
+    fn is_asserting_irq(&self) -> bool {
+        self.irq_asserted
     }
 
     fn step_scanline(&mut self) {
@@ -13,7 +13,25 @@ pub trait Mapper: savestate::Save {
 
     fn step_scanline(&mut self) {}
 
-    fn is_asserting_irq(&mut self) -> bool {
+    /// offset in the PRG ROM of the byte currently mapped at `addr` ($8000-$FFFF)
+    fn prg_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize>;
+
+    /// size of the smallest PRG ROM bank the mapper switches
+    fn prg_bank_size(&self) -> usize {
+        0x4000
+    }
+
+    /// offset in the CHR ROM of the byte currently mapped at `addr` ($0000-$1FFF),
+    /// None when the cartridge uses CHR RAM
+    fn chr_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize>;
+
+    /// RAM mapped at $6000-$7FFF, empty if the cartridge has none
+    fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    /// the IRQ line is level triggered, it stays asserted until the mapper acknowledges it
+    fn is_asserting_irq(&self) -> bool {
         false
     }
 
@@ -11,6 +11,7 @@ pub struct NROM {
 }
 
 impl NROM {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         NROM { ram: [0; 2048] }
     }
@@ -48,13 +49,31 @@ impl Mapper for NROM {
                 // panic!("Attempted to write to CHR ROM on NROM mapper");
             }
             0x6000..=0x7FFF => {
-                self.ram[(addr - 0x6000) as usize] = val;
+                self.ram[((addr - 0x6000) & 0x7FF) as usize] = val;
             }
             _ => {
                 // panic!("Invalid NROM write address: {:04X}", addr)
             }
         }
     }
+
+    fn prg_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(mirrored_addr(cart, addr) - cart.prg_rom_start),
+            _ => None,
+        }
+    }
+
+    fn chr_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF if cart.chr_rom_size > 0 => Some(addr as usize),
+            _ => None,
+        }
+    }
+
+    fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
 const NROM_SECTION_NAME: &str = "NROM";
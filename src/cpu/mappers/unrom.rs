@@ -13,6 +13,7 @@ pub struct UNROM {
 }
 
 impl UNROM {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         UNROM {
             prg_ram: [0; 2048],
@@ -58,11 +59,11 @@ impl Mapper for UNROM {
                     self.chr_ram[addr as usize] = val;
                 } else {
                     let addr = cart.chr_rom_start + (addr & 0x1fff) as usize;
-                    cart.bytes[addr] = val;
+                    cart.patch(addr, val);
                 }
             }
             0x6000..=0x7FFF => {
-                self.prg_ram[(addr - 0x6000) as usize] = val;
+                self.prg_ram[((addr - 0x6000) & 0x7FF) as usize] = val;
             }
             0x8000..=0xFFFF => {
                 self.bank = val & 0b1111;
@@ -70,6 +71,27 @@ impl Mapper for UNROM {
             _ => {}
         }
     }
+
+    fn prg_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize,
+            0xC000..=0xFFFF => cart.prg_rom_size as usize - 1,
+            _ => return None,
+        };
+
+        Some(bank * 0x4000 + (addr & 0x3FFF) as usize)
+    }
+
+    fn chr_rom_offset(&self, cart: &Cart, addr: u16) -> Option<usize> {
+        match addr {
+            0x0000..=0x1FFF if cart.chr_rom_size > 0 => Some(addr as usize),
+            _ => None,
+        }
+    }
+
+    fn get_prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
 }
 
 const UNROM_SECTION_NAME: &str = "UNROM";
@@ -1,7 +1,8 @@
+pub mod disasm;
 mod instructions;
 pub mod mappers;
 pub mod memory;
-mod opcodes;
+pub mod opcodes;
 pub mod rom;
 
 use bitflags::bitflags;
@@ -15,6 +16,7 @@ use std::fmt;
 const RESET_VECTOR: u16 = 0xfffc;
 const STACK_START: u16 = 0x100;
 const STACK_TOP: u8 = 0xfd;
+const RESET_CYCLES: u32 = 7;
 const DEFAULT_STATUS_STATE: u8 = 0b0010_0100;
 
 // 7  bit  0
@@ -54,6 +56,27 @@ impl Status {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub p: u8,
+}
+
+/// the instruction mode performs the accesses of an instruction at once and only makes
+/// the ones whose value is used, the cycle mode accesses the bus on every cycle like the 6502:
+/// the dummy reads and the double writes of read-modify-write instructions are performed
+/// and the PPU and the APU advance by one cycle on each access
+// https://www.nesdev.org/6502_cpu.txt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuMode {
+    Instruction,
+    Cycle,
+}
+
 // Represents the state of a MOS 6502 CPU
 #[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
@@ -65,6 +88,9 @@ pub struct CPU {
     instr_cycles: u32,
     total_cycles: u32,
     status: Status,
+    // interrupt disable flag seen by the last interrupt poll, CLI, SEI and PLP
+    // change the flag after the poll so their effect is delayed by one instruction
+    polled_interrupt_disable: bool,
     pub bus: Bus,
     stall: u32,
     instructions: [fn(&mut CPU); 256],
@@ -83,19 +109,35 @@ impl CPU {
             instr_cycles: 0,
             total_cycles: 0,
             status: Status::new(),
+            polled_interrupt_disable: true,
             bus,
             stall: 0,
             instructions: CPU::instructions_lut(),
         }
     }
 
+    pub fn get_registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            pc: self.pc,
+            sp: self.sp,
+            p: self.status.bits(),
+        }
+    }
+
+    // https://www.nesdev.org/wiki/CPU_power_up_state#After_reset
     pub fn soft_reset(&mut self) {
+        self.bus.soft_reset();
+
+        // the reset sequence is an interrupt with the stack writes turned into reads
+        self.sp = self.sp.wrapping_sub(3);
+        self.status.insert(Status::INTERRUPT_DISABLE);
+        self.polled_interrupt_disable = true;
         self.pc = self.bus.read_word(RESET_VECTOR);
-        self.sp = STACK_TOP;
         self.instr_cycles = 0;
-        self.total_cycles = 0;
-        self.status = Status::new();
-        self.stall = 0;
+        self.stall = RESET_CYCLES;
     }
 
     // Stack utils
@@ -125,6 +167,34 @@ impl CPU {
         high << 8 | low
     }
 
+    // the stack pointer is incremented during this cycle
+    fn dummy_stack_read(&mut self) {
+        self.dummy_read(STACK_START + self.sp as u16);
+    }
+
+    // Cycle mode utils
+
+    fn is_cycle_accurate(&self) -> bool {
+        self.bus.get_cpu_mode() == CpuMode::Cycle
+    }
+
+    // the 6502 reads the bus on every cycle it doesn't write, even when the value is unused
+    fn dummy_read(&mut self, addr: u16) {
+        if self.is_cycle_accurate() {
+            self.bus.dummy_read(addr);
+        } else {
+            // the cycle still elapses before the next accesses
+            self.bus.internal_cycles(1);
+        }
+    }
+
+    // read-modify-write instructions write the unmodified value back while they compute the result
+    fn dummy_write(&mut self, addr: u16, val: u8) {
+        if self.is_cycle_accurate() {
+            self.bus.write_byte(addr, val);
+        }
+    }
+
     // Memory utils
 
     fn next_byte(&mut self) -> u8 {
@@ -153,7 +223,10 @@ impl CPU {
 
     fn zero_page_x(&mut self) -> u16 {
         // val = PEEK((arg + X) % 256)
-        self.next_byte().wrapping_add(self.x) as u16
+        let addr = self.next_byte();
+        // the unindexed address is read while X is added
+        self.dummy_read(addr as u16);
+        addr.wrapping_add(self.x) as u16
     }
 
     fn zero_page_x_val(&mut self) -> u8 {
@@ -162,7 +235,9 @@ impl CPU {
     }
 
     fn zero_page_y(&mut self) -> u16 {
-        self.next_byte().wrapping_add(self.y) as u16
+        let addr = self.next_byte();
+        self.dummy_read(addr as u16);
+        addr.wrapping_add(self.y) as u16
     }
 
     fn zero_page_y_val(&mut self) -> u8 {
@@ -189,6 +264,8 @@ impl CPU {
             self.instr_cycles += 1;
         }
 
+        self.indexed_dummy_read(addr, res, add_on_boundary_crossed);
+
         res
     }
 
@@ -201,6 +278,14 @@ impl CPU {
         prev & 0xff00 != next & 0xff00
     }
 
+    // the index is added to the low byte first, the address with the high byte not yet fixed
+    // is read when a page is crossed, writes and read-modify-writes always read it
+    fn indexed_dummy_read(&mut self, base: u16, addr: u16, read_instruction: bool) {
+        if !read_instruction || self.page_crossed(base, addr) {
+            self.dummy_read((base & 0xff00) | (addr & 0x00ff));
+        }
+    }
+
     fn absolute_y(&mut self, add_on_boundary_crossed: bool) -> u16 {
         let addr = self.next_word();
         let y = self.y as u16;
@@ -211,6 +296,8 @@ impl CPU {
             self.instr_cycles += 1;
         }
 
+        self.indexed_dummy_read(addr, res, add_on_boundary_crossed);
+
         res
     }
 
@@ -234,6 +321,8 @@ impl CPU {
             self.instr_cycles += 1;
         }
 
+        self.indexed_dummy_read(addr, final_addr, add_on_boundary_crossed);
+
         final_addr
     }
 
@@ -247,6 +336,7 @@ impl CPU {
     fn indirect_x(&mut self) -> u16 {
         // val = PEEK(PEEK((arg + X) % 256) + PEEK((arg + X + 1) % 256) * 256)
         let addr = self.next_byte();
+        self.dummy_read(addr as u16);
         let addr1 = addr.wrapping_add(self.x);
         let addr2 = addr1.wrapping_add(1);
         let val1 = self.bus.read_byte(addr1 as u16);
@@ -303,6 +393,7 @@ impl savestate::Save for CPU {
         s.data.write_u32(self.total_cycles);
         s.data.write_u8(self.status.bits());
         s.data.write_u32(self.stall);
+        s.data.write_bool(self.polled_interrupt_disable);
 
         self.bus.save(s);
     }
@@ -319,9 +410,59 @@ impl savestate::Save for CPU {
         self.total_cycles = s.data.read_u32()?;
         *self.status.0.bits_mut() = s.data.read_u8()?;
         self.stall = s.data.read_u32()?;
+        self.polled_interrupt_disable = s.data.read_bool()?;
 
         self.bus.load(s)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{memory::Memory, Status, CPU, RESET_VECTOR};
+    use crate::{bus::Bus, tests::nestest};
+
+    fn cpu() -> CPU {
+        CPU::new(Bus::new(nestest(), 44100.0))
+    }
+
+    #[test]
+    fn soft_reset() {
+        let mut cpu = cpu();
+        cpu.pc = 0xC123;
+        cpu.sp = 0x01;
+        cpu.status.remove(Status::INTERRUPT_DISABLE);
+        cpu.bus.write_byte(0x0300, 0x42);
+
+        cpu.soft_reset();
+
+        let reset_vector = cpu.bus.read_word(RESET_VECTOR);
+        assert_eq!(cpu.pc, reset_vector);
+        assert_eq!(cpu.sp, 0xFE);
+        assert!(cpu.status.contains(Status::INTERRUPT_DISABLE));
+        assert_eq!(cpu.bus.read_byte(0x0300), 0x42);
+
+        // the reset sequence takes 7 cycles before the first instruction
+        for _ in 0..7 {
+            assert_eq!(cpu.step(), 1);
+            assert_eq!(cpu.pc, reset_vector);
+        }
+
+        cpu.step();
+        assert_ne!(cpu.pc, reset_vector);
+    }
+
+    #[test]
+    fn soft_reset_silences_the_apu() {
+        let mut cpu = cpu();
+        // enable the first pulse channel and load its length counter
+        cpu.bus.write_byte(0x4015, 0b0000_0001);
+        cpu.bus.write_byte(0x4003, 0b0000_1000);
+        assert_eq!(cpu.bus.read_byte(0x4015) & 0b0000_0001, 1);
+
+        cpu.soft_reset();
+
+        assert_eq!(cpu.bus.read_byte(0x4015) & 0b0001_1111, 0);
+    }
+}
@@ -23,6 +23,7 @@ pub enum AddressingMode {
     IndirectY,
     Implied,
     Relative,
+    Accumulator,
 }
 
 impl From<u8> for AddressingMode {
@@ -41,6 +42,7 @@ impl From<u8> for AddressingMode {
             9 => IndirectY,
             10 => Implied,
             11 => Relative,
+            12 => Accumulator,
             _ => panic!("Invalid addressing mode: {}", mode),
         }
     }
@@ -63,6 +65,299 @@ impl From<AddressingMode> for u8 {
             IndirectY => 9,
             Implied => 10,
             Relative => 11,
+            Accumulator => 12,
         }
     }
 }
+
+impl AddressingMode {
+    /// length of an instruction in bytes, including the opcode
+    pub fn instruction_len(&self) -> u16 {
+        use AddressingMode::*;
+
+        match self {
+            Implied | Accumulator => 1,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 2,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// unofficial opcodes are executed as NOPs
+    pub official: bool,
+}
+
+impl Opcode {
+    const fn new(mnemonic: &'static str, mode: AddressingMode, official: bool) -> Self {
+        Opcode {
+            mnemonic,
+            mode,
+            official,
+        }
+    }
+}
+
+// https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+#[rustfmt::skip]
+pub static OPCODES: [Opcode; 256] = [
+    /* 00 */ Opcode::new("BRK", AddressingMode::Implied, true),
+    /* 01 */ Opcode::new("ORA", AddressingMode::IndirectX, true),
+    /* 02 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 03 */ Opcode::new("SLO", AddressingMode::IndirectX, false),
+    /* 04 */ Opcode::new("NOP", AddressingMode::ZeroPage, false),
+    /* 05 */ Opcode::new("ORA", AddressingMode::ZeroPage, true),
+    /* 06 */ Opcode::new("ASL", AddressingMode::ZeroPage, true),
+    /* 07 */ Opcode::new("SLO", AddressingMode::ZeroPage, false),
+    /* 08 */ Opcode::new("PHP", AddressingMode::Implied, true),
+    /* 09 */ Opcode::new("ORA", AddressingMode::Immediate, true),
+    /* 0A */ Opcode::new("ASL", AddressingMode::Accumulator, true),
+    /* 0B */ Opcode::new("ANC", AddressingMode::Immediate, false),
+    /* 0C */ Opcode::new("NOP", AddressingMode::Absolute, false),
+    /* 0D */ Opcode::new("ORA", AddressingMode::Absolute, true),
+    /* 0E */ Opcode::new("ASL", AddressingMode::Absolute, true),
+    /* 0F */ Opcode::new("SLO", AddressingMode::Absolute, false),
+    /* 10 */ Opcode::new("BPL", AddressingMode::Relative, true),
+    /* 11 */ Opcode::new("ORA", AddressingMode::IndirectY, true),
+    /* 12 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 13 */ Opcode::new("SLO", AddressingMode::IndirectY, false),
+    /* 14 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* 15 */ Opcode::new("ORA", AddressingMode::ZeroPageX, true),
+    /* 16 */ Opcode::new("ASL", AddressingMode::ZeroPageX, true),
+    /* 17 */ Opcode::new("SLO", AddressingMode::ZeroPageX, false),
+    /* 18 */ Opcode::new("CLC", AddressingMode::Implied, true),
+    /* 19 */ Opcode::new("ORA", AddressingMode::AbsoluteY, true),
+    /* 1A */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* 1B */ Opcode::new("SLO", AddressingMode::AbsoluteY, false),
+    /* 1C */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* 1D */ Opcode::new("ORA", AddressingMode::AbsoluteX, true),
+    /* 1E */ Opcode::new("ASL", AddressingMode::AbsoluteX, true),
+    /* 1F */ Opcode::new("SLO", AddressingMode::AbsoluteX, false),
+    /* 20 */ Opcode::new("JSR", AddressingMode::Absolute, true),
+    /* 21 */ Opcode::new("AND", AddressingMode::IndirectX, true),
+    /* 22 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 23 */ Opcode::new("RLA", AddressingMode::IndirectX, false),
+    /* 24 */ Opcode::new("BIT", AddressingMode::ZeroPage, true),
+    /* 25 */ Opcode::new("AND", AddressingMode::ZeroPage, true),
+    /* 26 */ Opcode::new("ROL", AddressingMode::ZeroPage, true),
+    /* 27 */ Opcode::new("RLA", AddressingMode::ZeroPage, false),
+    /* 28 */ Opcode::new("PLP", AddressingMode::Implied, true),
+    /* 29 */ Opcode::new("AND", AddressingMode::Immediate, true),
+    /* 2A */ Opcode::new("ROL", AddressingMode::Accumulator, true),
+    /* 2B */ Opcode::new("ANC", AddressingMode::Immediate, false),
+    /* 2C */ Opcode::new("BIT", AddressingMode::Absolute, true),
+    /* 2D */ Opcode::new("AND", AddressingMode::Absolute, true),
+    /* 2E */ Opcode::new("ROL", AddressingMode::Absolute, true),
+    /* 2F */ Opcode::new("RLA", AddressingMode::Absolute, false),
+    /* 30 */ Opcode::new("BMI", AddressingMode::Relative, true),
+    /* 31 */ Opcode::new("AND", AddressingMode::IndirectY, true),
+    /* 32 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 33 */ Opcode::new("RLA", AddressingMode::IndirectY, false),
+    /* 34 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* 35 */ Opcode::new("AND", AddressingMode::ZeroPageX, true),
+    /* 36 */ Opcode::new("ROL", AddressingMode::ZeroPageX, true),
+    /* 37 */ Opcode::new("RLA", AddressingMode::ZeroPageX, false),
+    /* 38 */ Opcode::new("SEC", AddressingMode::Implied, true),
+    /* 39 */ Opcode::new("AND", AddressingMode::AbsoluteY, true),
+    /* 3A */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* 3B */ Opcode::new("RLA", AddressingMode::AbsoluteY, false),
+    /* 3C */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* 3D */ Opcode::new("AND", AddressingMode::AbsoluteX, true),
+    /* 3E */ Opcode::new("ROL", AddressingMode::AbsoluteX, true),
+    /* 3F */ Opcode::new("RLA", AddressingMode::AbsoluteX, false),
+    /* 40 */ Opcode::new("RTI", AddressingMode::Implied, true),
+    /* 41 */ Opcode::new("EOR", AddressingMode::IndirectX, true),
+    /* 42 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 43 */ Opcode::new("SRE", AddressingMode::IndirectX, false),
+    /* 44 */ Opcode::new("NOP", AddressingMode::ZeroPage, false),
+    /* 45 */ Opcode::new("EOR", AddressingMode::ZeroPage, true),
+    /* 46 */ Opcode::new("LSR", AddressingMode::ZeroPage, true),
+    /* 47 */ Opcode::new("SRE", AddressingMode::ZeroPage, false),
+    /* 48 */ Opcode::new("PHA", AddressingMode::Implied, true),
+    /* 49 */ Opcode::new("EOR", AddressingMode::Immediate, true),
+    /* 4A */ Opcode::new("LSR", AddressingMode::Accumulator, true),
+    /* 4B */ Opcode::new("ALR", AddressingMode::Immediate, false),
+    /* 4C */ Opcode::new("JMP", AddressingMode::Absolute, true),
+    /* 4D */ Opcode::new("EOR", AddressingMode::Absolute, true),
+    /* 4E */ Opcode::new("LSR", AddressingMode::Absolute, true),
+    /* 4F */ Opcode::new("SRE", AddressingMode::Absolute, false),
+    /* 50 */ Opcode::new("BVC", AddressingMode::Relative, true),
+    /* 51 */ Opcode::new("EOR", AddressingMode::IndirectY, true),
+    /* 52 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 53 */ Opcode::new("SRE", AddressingMode::IndirectY, false),
+    /* 54 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* 55 */ Opcode::new("EOR", AddressingMode::ZeroPageX, true),
+    /* 56 */ Opcode::new("LSR", AddressingMode::ZeroPageX, true),
+    /* 57 */ Opcode::new("SRE", AddressingMode::ZeroPageX, false),
+    /* 58 */ Opcode::new("CLI", AddressingMode::Implied, true),
+    /* 59 */ Opcode::new("EOR", AddressingMode::AbsoluteY, true),
+    /* 5A */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* 5B */ Opcode::new("SRE", AddressingMode::AbsoluteY, false),
+    /* 5C */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* 5D */ Opcode::new("EOR", AddressingMode::AbsoluteX, true),
+    /* 5E */ Opcode::new("LSR", AddressingMode::AbsoluteX, true),
+    /* 5F */ Opcode::new("SRE", AddressingMode::AbsoluteX, false),
+    /* 60 */ Opcode::new("RTS", AddressingMode::Implied, true),
+    /* 61 */ Opcode::new("ADC", AddressingMode::IndirectX, true),
+    /* 62 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 63 */ Opcode::new("RRA", AddressingMode::IndirectX, false),
+    /* 64 */ Opcode::new("NOP", AddressingMode::ZeroPage, false),
+    /* 65 */ Opcode::new("ADC", AddressingMode::ZeroPage, true),
+    /* 66 */ Opcode::new("ROR", AddressingMode::ZeroPage, true),
+    /* 67 */ Opcode::new("RRA", AddressingMode::ZeroPage, false),
+    /* 68 */ Opcode::new("PLA", AddressingMode::Implied, true),
+    /* 69 */ Opcode::new("ADC", AddressingMode::Immediate, true),
+    /* 6A */ Opcode::new("ROR", AddressingMode::Accumulator, true),
+    /* 6B */ Opcode::new("ARR", AddressingMode::Immediate, false),
+    /* 6C */ Opcode::new("JMP", AddressingMode::Indirect, true),
+    /* 6D */ Opcode::new("ADC", AddressingMode::Absolute, true),
+    /* 6E */ Opcode::new("ROR", AddressingMode::Absolute, true),
+    /* 6F */ Opcode::new("RRA", AddressingMode::Absolute, false),
+    /* 70 */ Opcode::new("BVS", AddressingMode::Relative, true),
+    /* 71 */ Opcode::new("ADC", AddressingMode::IndirectY, true),
+    /* 72 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 73 */ Opcode::new("RRA", AddressingMode::IndirectY, false),
+    /* 74 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* 75 */ Opcode::new("ADC", AddressingMode::ZeroPageX, true),
+    /* 76 */ Opcode::new("ROR", AddressingMode::ZeroPageX, true),
+    /* 77 */ Opcode::new("RRA", AddressingMode::ZeroPageX, false),
+    /* 78 */ Opcode::new("SEI", AddressingMode::Implied, true),
+    /* 79 */ Opcode::new("ADC", AddressingMode::AbsoluteY, true),
+    /* 7A */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* 7B */ Opcode::new("RRA", AddressingMode::AbsoluteY, false),
+    /* 7C */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* 7D */ Opcode::new("ADC", AddressingMode::AbsoluteX, true),
+    /* 7E */ Opcode::new("ROR", AddressingMode::AbsoluteX, true),
+    /* 7F */ Opcode::new("RRA", AddressingMode::AbsoluteX, false),
+    /* 80 */ Opcode::new("NOP", AddressingMode::Immediate, false),
+    /* 81 */ Opcode::new("STA", AddressingMode::IndirectX, true),
+    /* 82 */ Opcode::new("NOP", AddressingMode::Immediate, false),
+    /* 83 */ Opcode::new("SAX", AddressingMode::IndirectX, false),
+    /* 84 */ Opcode::new("STY", AddressingMode::ZeroPage, true),
+    /* 85 */ Opcode::new("STA", AddressingMode::ZeroPage, true),
+    /* 86 */ Opcode::new("STX", AddressingMode::ZeroPage, true),
+    /* 87 */ Opcode::new("SAX", AddressingMode::ZeroPage, false),
+    /* 88 */ Opcode::new("DEY", AddressingMode::Implied, true),
+    /* 89 */ Opcode::new("NOP", AddressingMode::Immediate, false),
+    /* 8A */ Opcode::new("TXA", AddressingMode::Implied, true),
+    /* 8B */ Opcode::new("XAA", AddressingMode::Immediate, false),
+    /* 8C */ Opcode::new("STY", AddressingMode::Absolute, true),
+    /* 8D */ Opcode::new("STA", AddressingMode::Absolute, true),
+    /* 8E */ Opcode::new("STX", AddressingMode::Absolute, true),
+    /* 8F */ Opcode::new("SAX", AddressingMode::Absolute, false),
+    /* 90 */ Opcode::new("BCC", AddressingMode::Relative, true),
+    /* 91 */ Opcode::new("STA", AddressingMode::IndirectY, true),
+    /* 92 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* 93 */ Opcode::new("AHX", AddressingMode::IndirectY, false),
+    /* 94 */ Opcode::new("STY", AddressingMode::ZeroPageX, true),
+    /* 95 */ Opcode::new("STA", AddressingMode::ZeroPageX, true),
+    /* 96 */ Opcode::new("STX", AddressingMode::ZeroPageY, true),
+    /* 97 */ Opcode::new("SAX", AddressingMode::ZeroPageY, false),
+    /* 98 */ Opcode::new("TYA", AddressingMode::Implied, true),
+    /* 99 */ Opcode::new("STA", AddressingMode::AbsoluteY, true),
+    /* 9A */ Opcode::new("TXS", AddressingMode::Implied, true),
+    /* 9B */ Opcode::new("TAS", AddressingMode::AbsoluteY, false),
+    /* 9C */ Opcode::new("SHY", AddressingMode::AbsoluteX, false),
+    /* 9D */ Opcode::new("STA", AddressingMode::AbsoluteX, true),
+    /* 9E */ Opcode::new("SHX", AddressingMode::AbsoluteY, false),
+    /* 9F */ Opcode::new("AHX", AddressingMode::AbsoluteY, false),
+    /* A0 */ Opcode::new("LDY", AddressingMode::Immediate, true),
+    /* A1 */ Opcode::new("LDA", AddressingMode::IndirectX, true),
+    /* A2 */ Opcode::new("LDX", AddressingMode::Immediate, true),
+    /* A3 */ Opcode::new("LAX", AddressingMode::IndirectX, false),
+    /* A4 */ Opcode::new("LDY", AddressingMode::ZeroPage, true),
+    /* A5 */ Opcode::new("LDA", AddressingMode::ZeroPage, true),
+    /* A6 */ Opcode::new("LDX", AddressingMode::ZeroPage, true),
+    /* A7 */ Opcode::new("LAX", AddressingMode::ZeroPage, false),
+    /* A8 */ Opcode::new("TAY", AddressingMode::Implied, true),
+    /* A9 */ Opcode::new("LDA", AddressingMode::Immediate, true),
+    /* AA */ Opcode::new("TAX", AddressingMode::Implied, true),
+    /* AB */ Opcode::new("LAX", AddressingMode::Immediate, false),
+    /* AC */ Opcode::new("LDY", AddressingMode::Absolute, true),
+    /* AD */ Opcode::new("LDA", AddressingMode::Absolute, true),
+    /* AE */ Opcode::new("LDX", AddressingMode::Absolute, true),
+    /* AF */ Opcode::new("LAX", AddressingMode::Absolute, false),
+    /* B0 */ Opcode::new("BCS", AddressingMode::Relative, true),
+    /* B1 */ Opcode::new("LDA", AddressingMode::IndirectY, true),
+    /* B2 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* B3 */ Opcode::new("LAX", AddressingMode::IndirectY, false),
+    /* B4 */ Opcode::new("LDY", AddressingMode::ZeroPageX, true),
+    /* B5 */ Opcode::new("LDA", AddressingMode::ZeroPageX, true),
+    /* B6 */ Opcode::new("LDX", AddressingMode::ZeroPageY, true),
+    /* B7 */ Opcode::new("LAX", AddressingMode::ZeroPageY, false),
+    /* B8 */ Opcode::new("CLV", AddressingMode::Implied, true),
+    /* B9 */ Opcode::new("LDA", AddressingMode::AbsoluteY, true),
+    /* BA */ Opcode::new("TSX", AddressingMode::Implied, true),
+    /* BB */ Opcode::new("LAS", AddressingMode::AbsoluteY, false),
+    /* BC */ Opcode::new("LDY", AddressingMode::AbsoluteX, true),
+    /* BD */ Opcode::new("LDA", AddressingMode::AbsoluteX, true),
+    /* BE */ Opcode::new("LDX", AddressingMode::AbsoluteY, true),
+    /* BF */ Opcode::new("LAX", AddressingMode::AbsoluteY, false),
+    /* C0 */ Opcode::new("CPY", AddressingMode::Immediate, true),
+    /* C1 */ Opcode::new("CMP", AddressingMode::IndirectX, true),
+    /* C2 */ Opcode::new("NOP", AddressingMode::Immediate, false),
+    /* C3 */ Opcode::new("DCP", AddressingMode::IndirectX, false),
+    /* C4 */ Opcode::new("CPY", AddressingMode::ZeroPage, true),
+    /* C5 */ Opcode::new("CMP", AddressingMode::ZeroPage, true),
+    /* C6 */ Opcode::new("DEC", AddressingMode::ZeroPage, true),
+    /* C7 */ Opcode::new("DCP", AddressingMode::ZeroPage, false),
+    /* C8 */ Opcode::new("INY", AddressingMode::Implied, true),
+    /* C9 */ Opcode::new("CMP", AddressingMode::Immediate, true),
+    /* CA */ Opcode::new("DEX", AddressingMode::Implied, true),
+    /* CB */ Opcode::new("AXS", AddressingMode::Immediate, false),
+    /* CC */ Opcode::new("CPY", AddressingMode::Absolute, true),
+    /* CD */ Opcode::new("CMP", AddressingMode::Absolute, true),
+    /* CE */ Opcode::new("DEC", AddressingMode::Absolute, true),
+    /* CF */ Opcode::new("DCP", AddressingMode::Absolute, false),
+    /* D0 */ Opcode::new("BNE", AddressingMode::Relative, true),
+    /* D1 */ Opcode::new("CMP", AddressingMode::IndirectY, true),
+    /* D2 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* D3 */ Opcode::new("DCP", AddressingMode::IndirectY, false),
+    /* D4 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* D5 */ Opcode::new("CMP", AddressingMode::ZeroPageX, true),
+    /* D6 */ Opcode::new("DEC", AddressingMode::ZeroPageX, true),
+    /* D7 */ Opcode::new("DCP", AddressingMode::ZeroPageX, false),
+    /* D8 */ Opcode::new("CLD", AddressingMode::Implied, true),
+    /* D9 */ Opcode::new("CMP", AddressingMode::AbsoluteY, true),
+    /* DA */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* DB */ Opcode::new("DCP", AddressingMode::AbsoluteY, false),
+    /* DC */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* DD */ Opcode::new("CMP", AddressingMode::AbsoluteX, true),
+    /* DE */ Opcode::new("DEC", AddressingMode::AbsoluteX, true),
+    /* DF */ Opcode::new("DCP", AddressingMode::AbsoluteX, false),
+    /* E0 */ Opcode::new("CPX", AddressingMode::Immediate, true),
+    /* E1 */ Opcode::new("SBC", AddressingMode::IndirectX, true),
+    /* E2 */ Opcode::new("NOP", AddressingMode::Immediate, false),
+    /* E3 */ Opcode::new("ISC", AddressingMode::IndirectX, false),
+    /* E4 */ Opcode::new("CPX", AddressingMode::ZeroPage, true),
+    /* E5 */ Opcode::new("SBC", AddressingMode::ZeroPage, true),
+    /* E6 */ Opcode::new("INC", AddressingMode::ZeroPage, true),
+    /* E7 */ Opcode::new("ISC", AddressingMode::ZeroPage, false),
+    /* E8 */ Opcode::new("INX", AddressingMode::Implied, true),
+    /* E9 */ Opcode::new("SBC", AddressingMode::Immediate, true),
+    /* EA */ Opcode::new("NOP", AddressingMode::Implied, true),
+    /* EB */ Opcode::new("SBC", AddressingMode::Immediate, false),
+    /* EC */ Opcode::new("CPX", AddressingMode::Absolute, true),
+    /* ED */ Opcode::new("SBC", AddressingMode::Absolute, true),
+    /* EE */ Opcode::new("INC", AddressingMode::Absolute, true),
+    /* EF */ Opcode::new("ISC", AddressingMode::Absolute, false),
+    /* F0 */ Opcode::new("BEQ", AddressingMode::Relative, true),
+    /* F1 */ Opcode::new("SBC", AddressingMode::IndirectY, true),
+    /* F2 */ Opcode::new("STP", AddressingMode::Implied, false),
+    /* F3 */ Opcode::new("ISC", AddressingMode::IndirectY, false),
+    /* F4 */ Opcode::new("NOP", AddressingMode::ZeroPageX, false),
+    /* F5 */ Opcode::new("SBC", AddressingMode::ZeroPageX, true),
+    /* F6 */ Opcode::new("INC", AddressingMode::ZeroPageX, true),
+    /* F7 */ Opcode::new("ISC", AddressingMode::ZeroPageX, false),
+    /* F8 */ Opcode::new("SED", AddressingMode::Implied, true),
+    /* F9 */ Opcode::new("SBC", AddressingMode::AbsoluteY, true),
+    /* FA */ Opcode::new("NOP", AddressingMode::Implied, false),
+    /* FB */ Opcode::new("ISC", AddressingMode::AbsoluteY, false),
+    /* FC */ Opcode::new("NOP", AddressingMode::AbsoluteX, false),
+    /* FD */ Opcode::new("SBC", AddressingMode::AbsoluteX, true),
+    /* FE */ Opcode::new("INC", AddressingMode::AbsoluteX, true),
+    /* FF */ Opcode::new("ISC", AddressingMode::AbsoluteX, false),
+];
@@ -1,5 +1,10 @@
 use sha2::{Digest, Sha256};
 
+use crate::{
+    cdl::{ChrFlags, CodeDataLogger, PrgFlags},
+    region::Region,
+};
+
 use super::mappers::mmc1::MMC1;
 use super::mappers::mmc3::MMC3;
 use super::mappers::nrom::NROM;
@@ -19,12 +24,32 @@ pub struct Cart {
     pub trainer: bool,
     pub prg_rom_start: usize,
     pub chr_rom_start: usize,
+    pub region: Region,
+    // the bytes as loaded, copied before the first patch
+    original: Option<Vec<u8>>,
+}
+
+impl Cart {
+    /// changes a byte of the cartridge, power cycles and checksums still use the loaded bytes
+    pub fn patch(&mut self, index: usize, val: u8) {
+        if self.original.is_none() {
+            self.original = Some(self.bytes.clone());
+        }
+
+        self.bytes[index] = val;
+    }
+
+    /// the cartridge as it was loaded, without the patches
+    pub fn original_bytes(&self) -> &[u8] {
+        self.original.as_deref().unwrap_or(&self.bytes)
+    }
 }
 
 #[allow(clippy::upper_case_acronyms)]
 pub struct ROM {
     pub cart: Cart,
     pub mapper: Box<dyn Mapper + Send + Sync>,
+    pub cdl: Option<Box<CodeDataLogger>>,
 }
 
 #[derive(Debug)]
@@ -68,6 +93,7 @@ impl ROM {
         let chr_rom_size = bytes[5];
         let prg_rom_start = 16 + if trainer { 512 } else { 0 };
         let chr_rom_start = prg_rom_start + (prg_rom_size as usize) * PRG_ROM_PAGE_SIZE;
+        let region = ROM::get_region(&bytes);
         let cart = Cart {
             bytes,
             hash,
@@ -79,11 +105,58 @@ impl ROM {
             trainer,
             prg_rom_start,
             chr_rom_start,
+            region,
+            original: None,
         };
 
         let mapper = ROM::get_mapper(mapper_id, &cart)?;
 
-        Ok(ROM { mapper, cart })
+        Ok(ROM {
+            mapper,
+            cart,
+            cdl: None,
+        })
+    }
+
+    /// marks the PRG ROM byte currently mapped at `addr` in the code/data log
+    pub(crate) fn log_prg(&mut self, addr: u16, flags: PrgFlags) {
+        if let Some(cdl) = &mut self.cdl {
+            if let Some(offset) = self.mapper.prg_rom_offset(&self.cart, addr) {
+                cdl.mark_prg(offset, flags);
+            }
+        }
+    }
+
+    /// marks the CHR ROM byte currently mapped at `addr` in the code/data log
+    pub(crate) fn log_chr(&mut self, addr: u16, flags: ChrFlags) {
+        if let Some(cdl) = &mut self.cdl {
+            if let Some(offset) = self.mapper.chr_rom_offset(&self.cart, addr) {
+                cdl.mark_chr(offset, flags);
+            }
+        }
+    }
+
+    // https://www.nesdev.org/wiki/NES_2.0#CPU/PPU_Timing
+    fn get_region(bytes: &[u8]) -> Region {
+        let is_nes2 = bytes[7] & 0b1100 == 0b1000;
+
+        if is_nes2 {
+            match bytes[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc, // 2 = multi-region
+            }
+        } else {
+            // the TV system bit of iNES 1.0 headers is rarely set, only trust it
+            // when the unused padding bytes are clean
+            let clean_padding = bytes[12..16].iter().all(|&b| b == 0);
+
+            if clean_padding && bytes[9] & 1 != 0 {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            }
+        }
     }
 
     fn get_mapper(mapper_id: u8, cart: &Cart) -> Result<Box<dyn Mapper + Send + Sync>, RomError> {
@@ -96,3 +169,42 @@ impl ROM {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ROM;
+    use crate::region::Region;
+
+    fn header(flags7: u8, flags9: u8, flags12: u8, padding: u8) -> [u8; 16] {
+        [
+            b'N', b'E', b'S', 0x1A, 1, 1, 0, flags7, 0, flags9, 0, 0, flags12, 0, 0, padding,
+        ]
+    }
+
+    #[test]
+    fn nes2_timing_bits() {
+        let nes2 = 0b1000;
+
+        assert_eq!(ROM::get_region(&header(nes2, 0, 0, 0)), Region::Ntsc);
+        assert_eq!(ROM::get_region(&header(nes2, 0, 1, 0)), Region::Pal);
+        // multi-region
+        assert_eq!(ROM::get_region(&header(nes2, 0, 2, 0)), Region::Ntsc);
+        assert_eq!(ROM::get_region(&header(nes2, 0, 3, 0)), Region::Dendy);
+        // only the two low bits are used
+        assert_eq!(
+            ROM::get_region(&header(nes2, 0, 0b1111_1101, 0)),
+            Region::Pal
+        );
+        // the iNES 1.0 TV system bit is ignored
+        assert_eq!(ROM::get_region(&header(nes2, 1, 0, 0)), Region::Ntsc);
+    }
+
+    #[test]
+    fn ines_tv_system_bit() {
+        assert_eq!(ROM::get_region(&header(0, 0, 0, 0)), Region::Ntsc);
+        assert_eq!(ROM::get_region(&header(0, 1, 0, 0)), Region::Pal);
+        // headers with garbage in the padding, like "DiskDude!", are not trusted
+        assert_eq!(ROM::get_region(&header(0, 1, 0, b'!')), Region::Ntsc);
+        assert_eq!(ROM::get_region(&header(0, 1, 3, 0)), Region::Ntsc);
+    }
+}
@@ -0,0 +1,454 @@
+// Conditions attached to breakpoints and watchpoints, e.g. `A == $10 && [$0300] != 0`
+//
+// operands: numbers ($FF, 0xFF, %1010, 255), registers (A, X, Y, SP, PC, P),
+// flags (C, Z, I, D, V, N), SCANLINE, CYCLE, FRAME, VALUE and ADDR
+// (the value and address of the access that triggered a watchpoint)
+// and memory reads ([addr])
+//
+// operators, from lowest to highest precedence: ||, &&, |, ^, &, == !=, < <= > >=, + -
+// unary operators: ! -
+
+use crate::cpu::Registers;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConditionError {
+    UnexpectedEnd,
+    UnexpectedToken(String),
+    UnknownIdentifier(String),
+    InvalidNumber(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+    P,
+    Flag(u8),
+    Scanline,
+    Cycle,
+    Frame,
+    Value,
+    Addr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Add,
+    Sub,
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        use BinaryOp::*;
+
+        match self {
+            Or => 1,
+            And => 2,
+            BitOr => 3,
+            BitXor => 4,
+            BitAnd => 5,
+            Eq | NotEq => 6,
+            Less | LessEq | Greater | GreaterEq => 7,
+            Add | Sub => 8,
+        }
+    }
+
+    fn apply(&self, lhs: i64, rhs: i64) -> i64 {
+        use BinaryOp::*;
+
+        match self {
+            Or => ((lhs != 0) || (rhs != 0)) as i64,
+            And => ((lhs != 0) && (rhs != 0)) as i64,
+            BitOr => lhs | rhs,
+            BitXor => lhs ^ rhs,
+            BitAnd => lhs & rhs,
+            Eq => (lhs == rhs) as i64,
+            NotEq => (lhs != rhs) as i64,
+            Less => (lhs < rhs) as i64,
+            LessEq => (lhs <= rhs) as i64,
+            Greater => (lhs > rhs) as i64,
+            GreaterEq => (lhs >= rhs) as i64,
+            Add => lhs.wrapping_add(rhs),
+            Sub => lhs.wrapping_sub(rhs),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Operand(Operand),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(BinaryOp),
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+/// state of the console when a condition is evaluated
+pub struct EvalContext {
+    pub registers: Registers,
+    pub scanline: u16,
+    pub cycle: u16,
+    pub frame: u64,
+    /// value read or written by the access that triggered a watchpoint
+    pub value: Option<u8>,
+    /// address of the access that triggered a watchpoint
+    pub addr: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_expr(0)?;
+
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(ConditionError::UnexpectedToken(format!("{:?}", token)));
+        }
+
+        Ok(Condition {
+            source: source.to_owned(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// `peek` reads a byte from the CPU address space without side effects
+    pub fn is_true(&self, ctx: &EvalContext, peek: &mut dyn FnMut(u16) -> u8) -> bool {
+        eval(&self.expr, ctx, peek) != 0
+    }
+}
+
+fn eval(expr: &Expr, ctx: &EvalContext, peek: &mut dyn FnMut(u16) -> u8) -> i64 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Operand(operand) => {
+            let regs = &ctx.registers;
+
+            match operand {
+                Operand::A => regs.a as i64,
+                Operand::X => regs.x as i64,
+                Operand::Y => regs.y as i64,
+                Operand::SP => regs.sp as i64,
+                Operand::PC => regs.pc as i64,
+                Operand::P => regs.p as i64,
+                Operand::Flag(bit) => ((regs.p >> bit) & 1) as i64,
+                Operand::Scanline => ctx.scanline as i64,
+                Operand::Cycle => ctx.cycle as i64,
+                Operand::Frame => ctx.frame as i64,
+                Operand::Value => ctx.value.map_or(-1, |v| v as i64),
+                Operand::Addr => ctx.addr.map_or(-1, |a| a as i64),
+            }
+        }
+        Expr::Memory(addr) => {
+            let addr = eval(addr, ctx, peek) as u16;
+            peek(addr) as i64
+        }
+        Expr::Not(expr) => (eval(expr, ctx, peek) == 0) as i64,
+        Expr::Neg(expr) => eval(expr, ctx, peek).wrapping_neg(),
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, ctx, peek);
+            let rhs = eval(rhs, ctx, peek);
+            op.apply(lhs, rhs)
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ConditionError> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let (token, len) = match (c, next) {
+            (' ' | '\t', _) => {
+                i += 1;
+                continue;
+            }
+            ('|', Some('|')) => (Token::Op(BinaryOp::Or), 2),
+            ('&', Some('&')) => (Token::Op(BinaryOp::And), 2),
+            ('=', Some('=')) => (Token::Op(BinaryOp::Eq), 2),
+            ('!', Some('=')) => (Token::Op(BinaryOp::NotEq), 2),
+            ('<', Some('=')) => (Token::Op(BinaryOp::LessEq), 2),
+            ('>', Some('=')) => (Token::Op(BinaryOp::GreaterEq), 2),
+            ('|', _) => (Token::Op(BinaryOp::BitOr), 1),
+            ('&', _) => (Token::Op(BinaryOp::BitAnd), 1),
+            ('^', _) => (Token::Op(BinaryOp::BitXor), 1),
+            ('<', _) => (Token::Op(BinaryOp::Less), 1),
+            ('>', _) => (Token::Op(BinaryOp::Greater), 1),
+            ('+', _) => (Token::Op(BinaryOp::Add), 1),
+            ('-', _) => (Token::Op(BinaryOp::Sub), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            _ if c.is_ascii_alphanumeric() || c == '$' || c == '%' || c == '_' => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count()
+                    + 1;
+
+                let word = chars[i..i + len].iter().collect::<String>();
+                let token = if c.is_ascii_digit() || c == '$' || c == '%' {
+                    Token::Number(parse_number(&word)?)
+                } else {
+                    Token::Ident(word)
+                };
+
+                (token, len)
+            }
+            _ => return Err(ConditionError::UnexpectedToken(c.to_string())),
+        };
+
+        tokens.push(token);
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Result<i64, ConditionError> {
+    let res = if let Some(hex) = word.strip_prefix('$') {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = word.strip_prefix('%') {
+        i64::from_str_radix(bin, 2)
+    } else {
+        word.parse()
+    };
+
+    res.map_err(|_| ConditionError::InvalidNumber(word.to_owned()))
+}
+
+fn parse_operand(ident: &str) -> Result<Operand, ConditionError> {
+    let operand = match ident.to_ascii_uppercase().as_str() {
+        "A" => Operand::A,
+        "X" => Operand::X,
+        "Y" => Operand::Y,
+        "SP" | "S" => Operand::SP,
+        "PC" => Operand::PC,
+        "P" => Operand::P,
+        "C" => Operand::Flag(0),
+        "Z" => Operand::Flag(1),
+        "I" => Operand::Flag(2),
+        "D" => Operand::Flag(3),
+        "V" => Operand::Flag(6),
+        "N" => Operand::Flag(7),
+        "SCANLINE" => Operand::Scanline,
+        "CYCLE" => Operand::Cycle,
+        "FRAME" => Operand::Frame,
+        "VALUE" => Operand::Value,
+        "ADDR" => Operand::Addr,
+        _ => return Err(ConditionError::UnknownIdentifier(ident.to_owned())),
+    };
+
+    Ok(operand)
+}
+
+// precedence climbing
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<Token, ConditionError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(ConditionError::UnexpectedEnd)?;
+
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ConditionError> {
+        let token = self.next()?;
+
+        if token == expected {
+            Ok(())
+        } else {
+            Err(ConditionError::UnexpectedToken(format!("{:?}", token)))
+        }
+    }
+
+    fn parse_expr(&mut self, min_precedence: u8) -> Result<Expr, ConditionError> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos).cloned() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+
+            self.pos += 1;
+            let rhs = self.parse_expr(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ConditionError> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(ident) => Ok(Expr::Operand(parse_operand(&ident)?)),
+            Token::Not => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Token::Op(BinaryOp::Sub) => Ok(Expr::Neg(Box::new(self.parse_unary()?))),
+            Token::LParen => {
+                let expr = self.parse_expr(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Token::LBracket => {
+                let addr = self.parse_expr(0)?;
+                self.expect(Token::RBracket)?;
+                Ok(Expr::Memory(Box::new(addr)))
+            }
+            token => Err(ConditionError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> EvalContext {
+        EvalContext {
+            registers: Registers {
+                a: 1,
+                x: 2,
+                y: 4,
+                pc: 0xC000,
+                sp: 0xFD,
+                p: 0b1000_0011,
+            },
+            scanline: 241,
+            cycle: 1,
+            frame: 10,
+            value: None,
+            addr: None,
+        }
+    }
+
+    fn eval_source(source: &str) -> i64 {
+        let condition = Condition::parse(source).unwrap();
+        let mut peek = |addr: u16| (addr & 0xFF) as u8 ^ 0xFF;
+        eval(&condition.expr, &context(), &mut peek)
+    }
+
+    #[test]
+    fn logical_and_binds_tighter_than_or() {
+        // A == 1 || (X == 2 && Y == 3)
+        assert_eq!(eval_source("A == 1 || X == 2 && Y == 3"), 1);
+        assert_eq!(eval_source("(A == 1 || X == 2) && Y == 3"), 0);
+        assert_eq!(eval_source("A == 0 || X == 2 && Y == 4"), 1);
+    }
+
+    #[test]
+    fn operator_precedence() {
+        assert_eq!(eval_source("1 + 2 == 3"), 1);
+        assert_eq!(eval_source("X | Y & 6"), 6);
+        assert_eq!(eval_source("X ^ Y | 1"), 7);
+        assert_eq!(eval_source("A < X == 1"), 1);
+        assert_eq!(eval_source("10 - 3 - 2"), 5);
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(eval_source("-A"), -1);
+        assert_eq!(eval_source("X - -A"), 3);
+        assert_eq!(eval_source("-(X + Y) == -6"), 1);
+        assert_eq!(eval_source("!A"), 0);
+        assert_eq!(eval_source("!!X"), 1);
+        assert_eq!(eval_source("!Z && C && N"), 0);
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(eval_source("$FF + 0x10 + %101 + 7"), 0xFF + 0x10 + 5 + 7);
+        assert_eq!(eval_source("PC == $C000 && SP == $FD && S == SP"), 1);
+        assert_eq!(eval_source("P"), 0b1000_0011);
+        assert_eq!(
+            eval_source("scanline == 241 && CYCLE == 1 && FRAME == 10"),
+            1
+        );
+        // no watchpoint access
+        assert_eq!(eval_source("VALUE"), -1);
+        assert_eq!(eval_source("ADDR"), -1);
+    }
+
+    #[test]
+    fn memory_reads() {
+        let condition = Condition::parse("[$0300] == $FF && [X + $10] == $ED").unwrap();
+        let mut reads = Vec::new();
+        let mut peek = |addr: u16| {
+            reads.push(addr);
+            (addr & 0xFF) as u8 ^ 0xFF
+        };
+
+        assert!(condition.is_true(&context(), &mut peek));
+        assert_eq!(reads, [0x0300, 0x0012]);
+        assert_eq!(eval_source("[[$0001]]"), 0x01);
+    }
+
+    #[test]
+    fn parse_errors() {
+        use ConditionError::*;
+
+        let error = |source| Condition::parse(source).unwrap_err();
+
+        assert_eq!(error(""), UnexpectedEnd);
+        assert_eq!(error("A =="), UnexpectedEnd);
+        assert_eq!(error("(A == 1"), UnexpectedEnd);
+        assert_eq!(error("[$10"), UnexpectedEnd);
+        assert_eq!(error("A == 1)"), UnexpectedToken("RParen".to_owned()));
+        assert_eq!(error("A B"), UnexpectedToken("Ident(\"B\")".to_owned()));
+        assert_eq!(error("A # 1"), UnexpectedToken("#".to_owned()));
+        assert_eq!(error("Q == 1"), UnknownIdentifier("Q".to_owned()));
+        assert_eq!(error("$FG"), InvalidNumber("$FG".to_owned()));
+        assert_eq!(error("%102"), InvalidNumber("%102".to_owned()));
+    }
+}
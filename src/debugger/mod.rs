@@ -0,0 +1,454 @@
+mod condition;
+
+use bitflags::bitflags;
+
+use crate::cpu::CPU;
+
+pub use self::condition::{Condition, ConditionError, EvalContext};
+
+const JSR_OPCODE: u8 = 0x20;
+const RTS_OPCODE: u8 = 0x60;
+const RTI_OPCODE: u8 = 0x40;
+const JSR_LEN: u16 = 3; // bytes
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AccessKind: u8 {
+        const READ = 0b001;
+        const WRITE = 0b010;
+        const EXECUTE = 0b100;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    /// only accesses made by the CPU through PPUDATA ($2007) are reported
+    Ppu,
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+}
+
+impl Breakpoint {
+    pub fn new(addr: u16) -> Self {
+        Breakpoint {
+            addr,
+            condition: None,
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub space: AddressSpace,
+    /// inclusive
+    pub start: u16,
+    /// inclusive
+    pub end: u16,
+    pub kind: AccessKind,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+}
+
+impl Watchpoint {
+    pub fn new(space: AddressSpace, start: u16, end: u16, kind: AccessKind) -> Self {
+        Watchpoint {
+            space,
+            start,
+            end,
+            kind,
+            condition: None,
+            enabled: true,
+        }
+    }
+
+    fn matches(&self, space: AddressSpace, addr: u16, kind: AccessKind) -> bool {
+        self.enabled
+            && self.space == space
+            && self.kind.intersects(kind)
+            && (self.start..=self.end).contains(&addr)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// stopped before executing the instruction at `pc`
+    Breakpoint {
+        id: usize,
+        pc: u16,
+    },
+    Watchpoint {
+        id: usize,
+        space: AddressSpace,
+        addr: u16,
+        kind: AccessKind,
+        value: u8,
+    },
+    /// a step into, over or out completed
+    Step,
+    Scanline(u16),
+    Frame(u64),
+    Paused,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StepMode {
+    Run,
+    Into,
+    // resolved when the next instruction is about to be executed
+    OverRequested,
+    Over { return_pc: u16, sp: u8 },
+    OutRequested,
+    Out { sp: u8 },
+    Scanline(u16),
+    Frame(u64),
+}
+
+#[derive(Clone, Copy)]
+struct WatchHit {
+    id: usize,
+    space: AddressSpace,
+    addr: u16,
+    kind: AccessKind,
+    value: u8,
+}
+
+// Breakpoints are checked by the CPU right before an opcode is fetched,
+// watchpoints are reported by the bus and evaluated once the instruction completes.
+// Once stopped, Nes::step doesn't emulate anything until the debugger is resumed.
+pub struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    mode: StepMode,
+    stopped: Option<StopReason>,
+    // pc of the instruction we stopped before, which must not break again when resuming
+    break_pc: Option<u16>,
+    ignored_pc: Option<u16>,
+    executed_opcode: Option<u8>,
+    prev_scanline: u16,
+    hits: Vec<WatchHit>,
+}
+
+impl Debugger {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 0,
+            mode: StepMode::Run,
+            stopped: None,
+            break_pc: None,
+            ignored_pc: None,
+            executed_opcode: None,
+            prev_scanline: 0,
+            hits: Vec::new(),
+        }
+    }
+
+    /// returns the id of the breakpoint
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id();
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|(bp_id, _)| *bp_id != id);
+        self.breakpoints.len() != len
+    }
+
+    pub fn get_breakpoint_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints
+            .iter_mut()
+            .find(|(bp_id, _)| *bp_id == id)
+            .map(|(_, bp)| bp)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, bp)| (*id, bp))
+    }
+
+    /// returns the id of the watchpoint
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_id();
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|(wp_id, _)| *wp_id != id);
+        self.watchpoints.len() != len
+    }
+
+    pub fn get_watchpoint_mut(&mut self, id: usize) -> Option<&mut Watchpoint> {
+        self.watchpoints
+            .iter_mut()
+            .find(|(wp_id, _)| *wp_id == id)
+            .map(|(_, wp)| wp)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints.iter().map(|(id, wp)| (*id, wp))
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn get_stop_reason(&self) -> Option<&StopReason> {
+        self.stopped.as_ref()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.is_some()
+    }
+
+    /// stops before the next instruction
+    pub fn pause(&mut self) {
+        if self.stopped.is_none() {
+            self.stopped = Some(StopReason::Paused);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.continue_with(StepMode::Run);
+    }
+
+    /// executes a single instruction
+    pub fn step_into(&mut self) {
+        self.continue_with(StepMode::Into);
+    }
+
+    /// executes a single instruction, or a whole subroutine for JSR
+    pub fn step_over(&mut self) {
+        self.continue_with(StepMode::OverRequested);
+    }
+
+    /// runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) {
+        self.continue_with(StepMode::OutRequested);
+    }
+
+    /// runs until the PPU enters the given scanline
+    pub fn run_to_scanline(&mut self, scanline: u16) {
+        self.continue_with(StepMode::Scanline(scanline));
+    }
+
+    /// runs until the PPU frame counter reaches `frame`
+    pub fn run_to_frame(&mut self, frame: u64) {
+        self.continue_with(StepMode::Frame(frame));
+    }
+
+    fn continue_with(&mut self, mode: StepMode) {
+        self.mode = mode;
+        self.stopped = None;
+        self.ignored_pc = self.break_pc.take();
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.mode = StepMode::Run;
+        self.stopped = Some(reason);
+    }
+
+    fn eval_context(cpu: &CPU, value: Option<u8>, addr: Option<u16>) -> EvalContext {
+        EvalContext {
+            registers: cpu.get_registers(),
+            scanline: cpu.bus.ppu.get_scanline(),
+            cycle: cpu.bus.ppu.cycle,
+            frame: cpu.bus.ppu.get_frame_count(),
+            value,
+            addr,
+        }
+    }
+
+    fn condition_holds(condition: &Option<Condition>, cpu: &mut CPU, ctx: &EvalContext) -> bool {
+        match condition {
+            Some(condition) => condition.is_true(ctx, &mut |addr| cpu.bus.peek_byte(addr)),
+            None => true,
+        }
+    }
+
+    /// called by the bus for every CPU access, and every PPUDATA access
+    #[inline]
+    pub(crate) fn on_access(
+        &mut self,
+        space: AddressSpace,
+        addr: u16,
+        kind: AccessKind,
+        value: u8,
+    ) {
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.matches(space, addr, kind) {
+                self.hits.push(WatchHit {
+                    id: *id,
+                    space,
+                    addr,
+                    kind,
+                    value,
+                });
+            }
+        }
+    }
+
+    /// returns true when the instruction at the program counter must not be executed
+    pub(crate) fn before_instruction(&mut self, cpu: &mut CPU) -> bool {
+        let pc = cpu.pc;
+        let ignored = self.ignored_pc.take() == Some(pc);
+
+        if self.stopped.is_some() {
+            return true;
+        }
+
+        let opcode = cpu.bus.peek_byte(pc);
+
+        if !ignored {
+            if let Some(reason) = self.check_breakpoints(cpu, opcode) {
+                self.break_pc = Some(pc);
+                self.stop(reason);
+                return true;
+            }
+        }
+
+        let sp = cpu.get_registers().sp;
+
+        self.mode = match self.mode {
+            StepMode::OverRequested if opcode == JSR_OPCODE => StepMode::Over {
+                return_pc: pc.wrapping_add(JSR_LEN),
+                sp,
+            },
+            StepMode::OverRequested => StepMode::Into,
+            StepMode::OutRequested => StepMode::Out { sp },
+            mode => mode,
+        };
+
+        self.executed_opcode = Some(opcode);
+
+        false
+    }
+
+    fn check_breakpoints(&self, cpu: &mut CPU, opcode: u8) -> Option<StopReason> {
+        let pc = cpu.pc;
+        let ctx = Debugger::eval_context(cpu, None, Some(pc));
+
+        for (id, breakpoint) in &self.breakpoints {
+            if breakpoint.enabled
+                && breakpoint.addr == pc
+                && Debugger::condition_holds(&breakpoint.condition, cpu, &ctx)
+            {
+                return Some(StopReason::Breakpoint { id: *id, pc });
+            }
+        }
+
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.matches(AddressSpace::Cpu, pc, AccessKind::EXECUTE)
+                && Debugger::condition_holds(&watchpoint.condition, cpu, &ctx)
+            {
+                return Some(StopReason::Watchpoint {
+                    id: *id,
+                    space: AddressSpace::Cpu,
+                    addr: pc,
+                    kind: AccessKind::EXECUTE,
+                    value: opcode,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// called after each Nes::step
+    pub(crate) fn after_step(&mut self, cpu: &mut CPU) -> Option<StopReason> {
+        if self.stopped.is_some() {
+            self.hits.clear();
+            return self.stopped.clone();
+        }
+
+        let hits = std::mem::take(&mut self.hits);
+
+        for hit in &hits {
+            let ctx = Debugger::eval_context(cpu, Some(hit.value), Some(hit.addr));
+            let condition = self
+                .watchpoints
+                .iter()
+                .find(|(id, _)| *id == hit.id)
+                .and_then(|(_, wp)| wp.condition.as_ref());
+
+            let holds = match condition {
+                Some(condition) => condition.is_true(&ctx, &mut |addr| cpu.bus.peek_byte(addr)),
+                None => true,
+            };
+
+            if holds {
+                self.stop(StopReason::Watchpoint {
+                    id: hit.id,
+                    space: hit.space,
+                    addr: hit.addr,
+                    kind: hit.kind,
+                    value: hit.value,
+                });
+                break;
+            }
+        }
+
+        // reuse the allocation
+        self.hits = hits;
+        self.hits.clear();
+
+        if self.stopped.is_some() {
+            return self.stopped.clone();
+        }
+
+        let regs = cpu.get_registers();
+        let scanline = cpu.bus.ppu.get_scanline();
+        let prev_scanline = self.prev_scanline;
+        self.prev_scanline = scanline;
+
+        // steps only complete once an instruction was executed, the CPU may have been stalled
+        let opcode = self.executed_opcode.take();
+        let executed = opcode.is_some();
+
+        let reason = match self.mode {
+            StepMode::Run | StepMode::OverRequested | StepMode::OutRequested => None,
+            StepMode::Into if executed => Some(StopReason::Step),
+            StepMode::Into => None,
+            StepMode::Over { return_pc, sp }
+                if executed && regs.pc == return_pc && regs.sp == sp =>
+            {
+                Some(StopReason::Step)
+            }
+            StepMode::Over { .. } => None,
+            StepMode::Out { sp }
+                if matches!(opcode, Some(RTS_OPCODE | RTI_OPCODE)) && regs.sp > sp =>
+            {
+                Some(StopReason::Step)
+            }
+            StepMode::Out { .. } => None,
+            StepMode::Scanline(target) if scanline == target && prev_scanline != target => {
+                Some(StopReason::Scanline(target))
+            }
+            StepMode::Scanline(_) => None,
+            StepMode::Frame(target) if cpu.bus.ppu.get_frame_count() >= target => {
+                Some(StopReason::Frame(target))
+            }
+            StepMode::Frame(_) => None,
+        };
+
+        if let Some(reason) = reason {
+            self.stop(reason);
+        }
+
+        self.stopped.clone()
+    }
+}
@@ -0,0 +1,88 @@
+// Event log, records the register writes, interrupts and sprite 0 hits of each frame
+// with the scanline and dot they happened at, like Mesen's event viewer
+//
+// a frame ends when VBlank starts, so a frame's log holds the NMI handler
+// of the previous VBlank followed by the writes made while the picture was drawn
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// $2000-$2007, mirrors are reported at their base address
+    PpuRegisterWrite,
+    /// $4000-$4017, including OAM DMA ($4014) and the controller strobe ($4016)
+    ApuRegisterWrite,
+    /// $4020-$5FFF and $8000-$FFFF
+    MapperRegisterWrite,
+    Nmi,
+    Irq,
+    SpriteZeroHit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub scanline: u16,
+    pub dot: u16,
+    /// the register written, or the handler address for interrupts
+    pub addr: u16,
+    /// the value written, 0 for other events
+    pub value: u8,
+}
+
+impl EventKind {
+    /// kind of a CPU write, None for RAM and PRG RAM
+    pub fn from_write(addr: u16) -> Option<EventKind> {
+        match addr {
+            0x2000..=0x3FFF => Some(EventKind::PpuRegisterWrite),
+            0x4000..=0x4017 => Some(EventKind::ApuRegisterWrite),
+            0x4020..=0x5FFF | 0x8000..=0xFFFF => Some(EventKind::MapperRegisterWrite),
+            _ => None,
+        }
+    }
+}
+
+pub struct EventLog {
+    current: Vec<Event>,
+    previous: Vec<Event>,
+    frame_count: u64,
+}
+
+impl EventLog {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        EventLog {
+            current: Vec::new(),
+            previous: Vec::new(),
+            frame_count: 0,
+        }
+    }
+
+    /// events of the last complete frame, in the order they happened
+    pub fn get_frame_events(&self) -> &[Event] {
+        &self.previous
+    }
+
+    /// events recorded since the last frame ended
+    pub fn get_current_events(&self) -> &[Event] {
+        &self.current
+    }
+
+    /// number of frames completed since logging started
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+    }
+
+    pub(crate) fn push(&mut self, event: Event) {
+        self.current.push(event);
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+        self.frame_count += 1;
+    }
+}
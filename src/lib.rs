@@ -1,12 +1,26 @@
 pub mod apu;
 pub mod bus;
+pub mod cdl;
+pub mod cheats;
 pub mod cpu;
+pub mod debugger;
+pub mod event_log;
+pub mod movie;
 pub mod nes;
+pub mod power_on;
 pub mod ppu;
+pub mod ram_search;
+pub mod region;
+pub mod rewind;
 pub mod savestate;
+pub mod tracer;
+
+#[cfg(test)]
+mod tests;
 
 pub use bus::controller;
 pub use nes::Nes;
+pub use region::Region;
 
 pub const SCREEN_WIDTH: usize = 256; // px
 pub const SCREEN_HEIGHT: usize = 240; // px
@@ -0,0 +1,596 @@
+// FCEUX movie format
+// https://fceux.com/web/help/fm2.html
+
+use std::fmt::Write;
+
+use crate::{
+    bus::controller::JoypadStatus,
+    cpu::rom::Cart,
+    power_on::{PowerOnConfig, RamFill},
+    region::Region,
+    savestate,
+};
+
+use super::{Movie, MovieCommands, MovieError, MovieFrame, MovieStart};
+
+const FM2_VERSION: &str = "3";
+const EMU_VERSION: &str = "22020";
+const BASE64_PREFIX: &str = "base64:";
+// not part of the FM2 format, ignored by FCEUX
+const RAM_FILL_KEY: &str = "nessyRamFill";
+// palFlag only tells NTSC and PAL apart, Dendy movies would replay as NTSC without it
+const REGION_KEY: &str = "nessyRegion";
+// input characters from the most significant bit of JoypadStatus to the least significant one
+const GAMEPAD_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+pub fn decode(text: &str) -> Result<Movie, MovieError> {
+    let mut movie = Movie {
+        start: MovieStart::PowerOn,
+        frames: Vec::new(),
+        region: Region::Ntsc,
+        // movies recorded by FCEUX expect its RAM pattern
+        power_on: PowerOnConfig {
+            ram_fill: RamFill::Fceux,
+        },
+        rom_filename: String::new(),
+        rom_checksum: [0; 16],
+        guid: String::new(),
+        rerecord_count: 0,
+        comments: Vec::new(),
+    };
+
+    let mut has_version = false;
+    let mut region = None;
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+
+        if line.starts_with('|') {
+            let frame =
+                decode_input_line(line).ok_or(MovieError::InvalidInputLine(line_idx + 1))?;
+            movie.frames.push(frame);
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let invalid_value = || MovieError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+
+        match key {
+            "version" => {
+                if value != FM2_VERSION {
+                    return Err(MovieError::Unsupported(format!("FM2 version {}", value)));
+                }
+
+                has_version = true;
+            }
+            "binary" if value != "0" => {
+                return Err(MovieError::Unsupported("binary input log".to_owned()));
+            }
+            "fourscore" if value != "0" => {
+                return Err(MovieError::Unsupported("four score".to_owned()));
+            }
+            "port0" | "port1" if value != "0" && value != "1" => {
+                return Err(MovieError::Unsupported(format!("{} device {}", key, value)));
+            }
+            "palFlag" => {
+                movie.region = if value == "1" {
+                    Region::Pal
+                } else {
+                    Region::Ntsc
+                };
+            }
+            "romFilename" => movie.rom_filename = value.to_owned(),
+            "romChecksum" => {
+                let checksum = decode_base64_value(value).ok_or_else(invalid_value)?;
+
+                if checksum.len() != movie.rom_checksum.len() {
+                    return Err(invalid_value());
+                }
+
+                movie.rom_checksum.copy_from_slice(&checksum);
+            }
+            "guid" => movie.guid = value.to_owned(),
+            "rerecordCount" => {
+                movie.rerecord_count = value.parse().map_err(|_| invalid_value())?;
+            }
+            "comment" => movie.comments.push(value.to_owned()),
+            RAM_FILL_KEY => {
+                movie.power_on.ram_fill = decode_ram_fill(value).ok_or_else(invalid_value)?;
+            }
+            REGION_KEY => region = Some(decode_region(value).ok_or_else(invalid_value)?),
+            "savestate" => {
+                let state = decode_base64_value(value).ok_or_else(invalid_value)?;
+
+                // movies recorded by FCEUX embed a state in its own format
+                if !savestate::is_save_state(&state) {
+                    return Err(MovieError::ForeignSaveState);
+                }
+
+                movie.start = MovieStart::SaveState(state);
+            }
+            _ => {}
+        }
+    }
+
+    if !has_version {
+        return Err(MovieError::MissingKey("version"));
+    }
+
+    // takes precedence over palFlag wherever it appears
+    if let Some(region) = region {
+        movie.region = region;
+    }
+
+    Ok(movie)
+}
+
+pub fn encode(movie: &Movie) -> String {
+    let mut out = String::new();
+    let pal_flag = u8::from(movie.region == Region::Pal);
+
+    // writing to a String cannot fail
+    let _ = writeln!(out, "version {}", FM2_VERSION);
+    let _ = writeln!(out, "emuVersion {}", EMU_VERSION);
+    let _ = writeln!(out, "rerecordCount {}", movie.rerecord_count);
+    let _ = writeln!(out, "palFlag {}", pal_flag);
+    let _ = writeln!(out, "romFilename {}", movie.rom_filename);
+    let _ = writeln!(
+        out,
+        "romChecksum {}{}",
+        BASE64_PREFIX,
+        encode_base64(&movie.rom_checksum)
+    );
+    let _ = writeln!(out, "guid {}", movie.guid);
+    let _ = writeln!(out, "fourscore 0");
+    let _ = writeln!(out, "microphone 0");
+    let _ = writeln!(out, "port0 1");
+    let _ = writeln!(out, "port1 1");
+    let _ = writeln!(out, "port2 0");
+    let _ = writeln!(out, "FDS 0");
+    let _ = writeln!(out, "NewPPU 0");
+    let _ = writeln!(
+        out,
+        "{} {}",
+        RAM_FILL_KEY,
+        encode_ram_fill(movie.power_on.ram_fill)
+    );
+    let _ = writeln!(out, "{} {}", REGION_KEY, encode_region(movie.region));
+
+    for comment in &movie.comments {
+        let _ = writeln!(out, "comment {}", comment);
+    }
+
+    if let MovieStart::SaveState(state) = &movie.start {
+        let _ = writeln!(out, "savestate {}{}", BASE64_PREFIX, encode_base64(state));
+    }
+
+    for frame in &movie.frames {
+        let _ = writeln!(
+            out,
+            "|{}|{}|{}||",
+            frame.commands.bits(),
+            encode_gamepad(frame.joypad1),
+            encode_gamepad(frame.joypad2),
+        );
+    }
+
+    out
+}
+
+// |commands|port0|port1|port2|
+fn decode_input_line(line: &str) -> Option<MovieFrame> {
+    let mut fields = line.split('|').skip(1);
+    let commands = fields.next()?.trim().parse::<u32>().ok()?;
+    let joypad1 = decode_gamepad(fields.next().unwrap_or(""))?;
+    let joypad2 = decode_gamepad(fields.next().unwrap_or(""))?;
+
+    Some(MovieFrame {
+        joypad1,
+        joypad2,
+        // other commands (FDS, VS System...) are not supported
+        commands: MovieCommands::from_bits_truncate(commands as u8),
+    })
+}
+
+fn decode_gamepad(field: &str) -> Option<JoypadStatus> {
+    let field = field.as_bytes();
+
+    if field.is_empty() {
+        return Some(JoypadStatus::empty());
+    }
+
+    if field.len() != GAMEPAD_BUTTONS.len() {
+        return None;
+    }
+
+    let mut bits = 0u8;
+
+    for (i, c) in field.iter().enumerate() {
+        if *c != b'.' && *c != b' ' {
+            bits |= 1 << (7 - i);
+        }
+    }
+
+    Some(JoypadStatus::from_bits_truncate(bits))
+}
+
+fn encode_gamepad(status: JoypadStatus) -> String {
+    GAMEPAD_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            if status.bits() & (1 << (7 - i)) != 0 {
+                c as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// zeros | ones | fceux | random:<seed>
+fn decode_ram_fill(value: &str) -> Option<RamFill> {
+    match value {
+        "zeros" => Some(RamFill::Zeros),
+        "ones" => Some(RamFill::Ones),
+        "fceux" => Some(RamFill::Fceux),
+        _ => {
+            let seed = value.strip_prefix("random:")?.parse().ok()?;
+            Some(RamFill::Random(seed))
+        }
+    }
+}
+
+fn encode_ram_fill(fill: RamFill) -> String {
+    match fill {
+        RamFill::Zeros => "zeros".to_owned(),
+        RamFill::Ones => "ones".to_owned(),
+        RamFill::Fceux => "fceux".to_owned(),
+        RamFill::Random(seed) => format!("random:{}", seed),
+    }
+}
+
+// ntsc | pal | dendy
+fn decode_region(value: &str) -> Option<Region> {
+    match value {
+        "ntsc" => Some(Region::Ntsc),
+        "pal" => Some(Region::Pal),
+        "dendy" => Some(Region::Dendy),
+        _ => None,
+    }
+}
+
+fn encode_region(region: Region) -> &'static str {
+    match region {
+        Region::Ntsc => "ntsc",
+        Region::Pal => "pal",
+        Region::Dendy => "dendy",
+    }
+}
+
+/// MD5 of the PRG and CHR ROM, as stored in the romChecksum field by FCEUX
+pub fn rom_checksum(cart: &Cart) -> [u8; 16] {
+    md5(&cart.original_bytes()[cart.prg_rom_start..])
+}
+
+pub fn guid_from_hash(hash: &[u8; 32]) -> String {
+    let hex = hash[..16]
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<String>();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn decode_base64_value(value: &str) -> Option<Vec<u8>> {
+    decode_base64(value.strip_prefix(BASE64_PREFIX)?)
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim().trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in text {
+        let val = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+        acc = (acc << 6) | val;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+// https://www.ietf.org/rfc/rfc1321.txt
+
+#[rustfmt::skip]
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+#[rustfmt::skip]
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 16];
+
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = u32::from_le_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g]);
+
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i]));
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0u8; 16];
+
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // test suite of the RFC, appendix A.5
+    #[test]
+    fn md5_test_vectors() {
+        let vectors: [(&str, &str); 7] = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+
+        for (input, digest) in vectors {
+            assert_eq!(hex(&md5(input.as_bytes())), digest, "md5 of {:?}", input);
+        }
+    }
+
+    // padding spilling over into an extra block
+    #[test]
+    fn md5_block_boundaries() {
+        assert_eq!(hex(&md5(&[b'a'; 55])), "ef1772b6dff9a122358552954ad0df65");
+        assert_eq!(hex(&md5(&[b'a'; 56])), "3b0c8ac703f828b04c6c197006d17218");
+        assert_eq!(hex(&md5(&[b'a'; 64])), "014842d480b571495a4a0363793f7367");
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc4648#section-10
+    #[test]
+    fn base64_test_vectors() {
+        let vectors: [(&str, &str); 7] = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (data, text) in vectors {
+            assert_eq!(encode_base64(data.as_bytes()), text);
+            assert_eq!(decode_base64(text).as_deref(), Some(data.as_bytes()));
+        }
+
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        assert_eq!(decode_base64(&encode_base64(&bytes)), Some(bytes));
+        assert_eq!(decode_base64("Zm9v!"), None);
+    }
+
+    #[test]
+    fn parse_write_round_trip() {
+        let movie = Movie {
+            start: MovieStart::SaveState(b"NESSY\x01".to_vec()),
+            frames: vec![
+                MovieFrame::default(),
+                MovieFrame {
+                    joypad1: JoypadStatus::from_bits_truncate(0b1000_0001),
+                    joypad2: JoypadStatus::from_bits_truncate(0b0101_1010),
+                    commands: MovieCommands::empty(),
+                },
+                MovieFrame {
+                    joypad1: JoypadStatus::from_bits_truncate(0xFF),
+                    joypad2: JoypadStatus::empty(),
+                    commands: MovieCommands::SOFT_RESET,
+                },
+                MovieFrame {
+                    commands: MovieCommands::HARD_RESET,
+                    ..MovieFrame::default()
+                },
+            ],
+            region: Region::Pal,
+            power_on: PowerOnConfig {
+                ram_fill: RamFill::Random(1234),
+            },
+            rom_filename: "nestest".to_owned(),
+            rom_checksum: md5(b"nestest"),
+            guid: guid_from_hash(&[0xAB; 32]),
+            rerecord_count: 42,
+            comments: vec!["author nessy".to_owned(), "second comment".to_owned()],
+        };
+
+        let text = encode(&movie);
+        let decoded = decode(&text).unwrap();
+
+        assert!(matches!(
+            &decoded.start,
+            MovieStart::SaveState(state) if state == b"NESSY\x01"
+        ));
+        assert_eq!(decoded.frames, movie.frames);
+        assert_eq!(decoded.region, movie.region);
+        assert_eq!(decoded.power_on.ram_fill, movie.power_on.ram_fill);
+        assert_eq!(decoded.rom_filename, movie.rom_filename);
+        assert_eq!(decoded.rom_checksum, movie.rom_checksum);
+        assert_eq!(decoded.guid, "ABABABAB-ABAB-ABAB-ABAB-ABABABABABAB");
+        assert_eq!(decoded.rerecord_count, movie.rerecord_count);
+        assert_eq!(decoded.comments, movie.comments);
+        assert_eq!(encode(&decoded), text);
+    }
+
+    #[test]
+    fn dendy_round_trip() {
+        let movie = Movie {
+            start: MovieStart::PowerOn,
+            frames: vec![MovieFrame::default()],
+            region: Region::Dendy,
+            power_on: PowerOnConfig::default(),
+            rom_filename: "nestest".to_owned(),
+            rom_checksum: md5(b"nestest"),
+            guid: guid_from_hash(&[0xAB; 32]),
+            rerecord_count: 0,
+            comments: Vec::new(),
+        };
+
+        let text = encode(&movie);
+        // FCEUX has no Dendy flag, it sees an NTSC movie
+        assert!(text.contains("palFlag 0\n"));
+        assert_eq!(decode(&text).unwrap().region, Region::Dendy);
+
+        // movies of other emulators only have palFlag
+        let text = "version 3\npalFlag 1\n|0|........|||\n";
+        assert_eq!(decode(text).unwrap().region, Region::Pal);
+        // the region key wins over palFlag wherever it appears
+        let text = format!("version 3\n{REGION_KEY} dendy\npalFlag 0\n");
+        assert_eq!(decode(&text).unwrap().region, Region::Dendy);
+        let text = format!("version 3\n{REGION_KEY} secam\n");
+        assert!(matches!(
+            decode(&text),
+            Err(MovieError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn input_lines_use_fceux_button_order() {
+        let frame = decode_input_line("|1|R......A|.L....B.||").unwrap();
+
+        assert_eq!(frame.commands, MovieCommands::SOFT_RESET);
+        assert_eq!(frame.joypad1.bits(), 0b1000_0001);
+        assert_eq!(frame.joypad2.bits(), 0b0100_0010);
+        assert!(decode_input_line("|0|RLDU|........||").is_none());
+    }
+
+    #[test]
+    fn fceux_save_states_are_rejected() {
+        let fceux_state = encode_base64(b"FCSX\x00\x00\x00\x00");
+        let text = format!("version 3\nsavestate base64:{fceux_state}\n|0|........|||\n");
+
+        assert!(matches!(decode(&text), Err(MovieError::ForeignSaveState)));
+    }
+}
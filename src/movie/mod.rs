@@ -0,0 +1,165 @@
+mod fm2;
+
+use bitflags::bitflags;
+
+use crate::{
+    bus::controller::JoypadStatus, cpu::rom::Cart, power_on::PowerOnConfig, region::Region,
+};
+
+pub use self::fm2::rom_checksum;
+
+bitflags! {
+    // same values as the FM2 command field
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct MovieCommands: u8 {
+        const SOFT_RESET = 0b0000_0001;
+        const HARD_RESET = 0b0000_0010;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub joypad1: JoypadStatus,
+    pub joypad2: JoypadStatus,
+    /// applied at the start of the frame, before the inputs are latched
+    pub commands: MovieCommands,
+}
+
+impl Default for MovieFrame {
+    fn default() -> Self {
+        MovieFrame {
+            joypad1: JoypadStatus::empty(),
+            joypad2: JoypadStatus::empty(),
+            commands: MovieCommands::empty(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MovieStart {
+    PowerOn,
+    /// encoded save state
+    SaveState(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieAnchor {
+    /// power cycles the console before recording the first frame,
+    /// battery-backed RAM is cleared so that the movie doesn't depend on it
+    PowerOn,
+    /// embeds a save state of the current state in the movie
+    CurrentState,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    MissingKey(&'static str),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+    InvalidInputLine(usize),
+    Unsupported(String),
+    /// the movie starts from a save state made by another emulator, only movies
+    /// starting at power on or recorded by nessy can be played
+    ForeignSaveState,
+}
+
+#[derive(Clone, Debug)]
+pub struct Movie {
+    pub start: MovieStart,
+    pub frames: Vec<MovieFrame>,
+    pub region: Region,
+    /// used when the movie starts from power on or contains hard resets
+    pub power_on: PowerOnConfig,
+    pub rom_filename: String,
+    pub rom_checksum: [u8; 16],
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+}
+
+impl Movie {
+    pub fn new(cart: &Cart, start: MovieStart, region: Region, power_on: PowerOnConfig) -> Self {
+        Movie {
+            start,
+            frames: Vec::new(),
+            region,
+            power_on,
+            rom_filename: String::new(),
+            rom_checksum: rom_checksum(cart),
+            guid: fm2::guid_from_hash(&cart.hash),
+            rerecord_count: 0,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Movie, MovieError> {
+        fm2::decode(text)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        fm2::encode(self)
+    }
+
+    /// in frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieStatus {
+    Idle,
+    Recording { frame: usize },
+    Playing { frame: usize, length: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MovieMode {
+    Recording,
+    Playing,
+}
+
+pub(crate) struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    /// index of the current frame
+    pub frame: usize,
+    /// inputs of the frame being recorded
+    pub current: MovieFrame,
+    /// whether the current frame started executing,
+    /// inputs are sampled (or replayed) when the first instruction of a frame is executed
+    /// so that the frontend can update the joypads between two frames
+    pub frame_started: bool,
+}
+
+impl MovieSession {
+    pub fn new(movie: Movie, mode: MovieMode) -> Self {
+        MovieSession {
+            frame: if mode == MovieMode::Recording {
+                movie.len()
+            } else {
+                0
+            },
+            movie,
+            mode,
+            current: MovieFrame::default(),
+            frame_started: false,
+        }
+    }
+
+    pub fn status(&self) -> MovieStatus {
+        match self.mode {
+            MovieMode::Recording => MovieStatus::Recording { frame: self.frame },
+            MovieMode::Playing => MovieStatus::Playing {
+                frame: self.frame,
+                length: self.movie.len(),
+            },
+        }
+    }
+}
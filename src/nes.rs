@@ -1,80 +1,418 @@
 use crate::{
+    apu::{
+        mixer::{AudioChannel, AudioOutput, ChannelSettings},
+        rate_control::{AudioBufferHealth, RateControlConfig},
+        ring::AudioConsumer,
+    },
     bus::{controller::Joypad, Bus},
-    cpu::{rom::ROM, CPU},
+    cdl::{CdlError, CodeDataLogger},
+    cheats::{Cheat, CheatError, Cheats},
+    cpu::{
+        disasm::{self, Instruction},
+        rom::ROM,
+        CpuMode, CPU,
+    },
+    debugger::{Debugger, StopReason},
+    event_log::EventLog,
+    movie::{
+        Movie, MovieAnchor, MovieCommands, MovieFrame, MovieMode, MovieSession, MovieStart,
+        MovieStatus,
+    },
+    power_on::PowerOnConfig,
+    ppu::{
+        viewer::{self, Sprite},
+        RenderMode,
+    },
+    ram_search::RamSnapshot,
+    region::Region,
+    rewind::{Rewind, RewindConfig},
     savestate::{self, Save, SaveState, SaveStateError},
+    tracer::Tracer,
 };
 
 pub struct Nes {
     cpu: CPU,
+    power_on: PowerOnConfig,
+    rewind: Option<Rewind>,
+    // sections of the last rewind snapshot, reused by the next one
+    snapshot: Option<SaveState>,
+    state_buffer: Vec<u8>,
+    movie: Option<MovieSession>,
 }
 
 impl Nes {
     pub fn new(rom: ROM, sample_rate: f64) -> Self {
-        let bus = Bus::new(rom, sample_rate);
-        Nes { cpu: CPU::new(bus) }
+        Nes::with_power_on_config(rom, sample_rate, PowerOnConfig::default())
     }
 
-    pub fn step(&mut self) {
+    pub fn with_power_on_config(rom: ROM, sample_rate: f64, power_on: PowerOnConfig) -> Self {
+        let mut bus = Bus::new(rom, sample_rate);
+        bus.fill_memory(&power_on);
+
+        Nes {
+            cpu: CPU::new(bus),
+            power_on,
+            rewind: None,
+            snapshot: None,
+            state_buffer: Vec::new(),
+            movie: None,
+        }
+    }
+
+    /// overrides the region detected from the ROM header
+    pub fn set_region(&mut self, region: Region) {
+        self.cpu.bus.set_region(region);
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.cpu.bus.get_region()
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.cpu.bus.ppu.set_render_mode(mode);
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.cpu.bus.ppu.get_render_mode()
+    }
+
+    pub fn set_cpu_mode(&mut self, mode: CpuMode) {
+        self.cpu.bus.set_cpu_mode(mode);
+    }
+
+    pub fn get_cpu_mode(&self) -> CpuMode {
+        self.cpu.bus.get_cpu_mode()
+    }
+
+    /// returns why the debugger stopped, nothing is emulated while it is stopped
+    pub fn step(&mut self) -> Option<StopReason> {
+        let reason = self.run_instruction();
+        self.cpu.bus.sync();
+        reason
+    }
+
+    // the PPU and the APU may lag behind the CPU, callers outside of the emulation loops
+    // must see them up to date
+    fn run_instruction(&mut self) -> Option<StopReason> {
+        if let Some(debugger) = &self.cpu.bus.debugger {
+            if let Some(reason) = debugger.get_stop_reason() {
+                return Some(reason.clone());
+            }
+        }
+
+        if let Some(MovieSession {
+            frame_started: false,
+            ..
+        }) = self.movie
+        {
+            self.start_movie_frame();
+        }
+
         let cpu_cycles = self.cpu.step();
         self.cpu.bus.advance(cpu_cycles);
+
+        match self.cpu.bus.debugger.take() {
+            Some(mut debugger) => {
+                let reason = debugger.after_step(&mut self.cpu);
+                self.cpu.bus.debugger = Some(debugger);
+                reason
+            }
+            None => None,
+        }
+    }
+
+    pub fn enable_debugger(&mut self) {
+        if self.cpu.bus.debugger.is_none() {
+            self.cpu.bus.debugger = Some(Box::new(Debugger::new()));
+        }
+    }
+
+    pub fn disable_debugger(&mut self) {
+        self.cpu.bus.debugger = None;
+    }
+
+    pub fn get_debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.cpu.bus.debugger.as_deref_mut()
+    }
+
+    /// logs every executed instruction until `stop_trace` is called
+    pub fn start_trace(&mut self, tracer: Tracer) {
+        self.cpu.bus.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn stop_trace(&mut self) -> Option<Tracer> {
+        let mut tracer = self.cpu.bus.tracer.take()?;
+        let _ = tracer.flush();
+        Some(*tracer)
+    }
+
+    pub fn get_tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.cpu.bus.tracer.as_deref_mut()
+    }
+
+    /// marks the ROM bytes accessed as code or data until `disable_cdl` is called
+    pub fn enable_cdl(&mut self) {
+        if self.cpu.bus.ppu.rom.cdl.is_none() {
+            let (prg_size, chr_size) = self.cdl_sizes();
+            let cdl = CodeDataLogger::new(prg_size, chr_size);
+            self.cpu.bus.ppu.rom.cdl = Some(Box::new(cdl));
+        }
+    }
+
+    /// resumes logging from the content of a .cdl file
+    pub fn load_cdl(&mut self, data: &[u8]) -> Result<(), CdlError> {
+        let (prg_size, chr_size) = self.cdl_sizes();
+        let cdl = CodeDataLogger::from_cdl(data, prg_size, chr_size)?;
+        self.cpu.bus.ppu.rom.cdl = Some(Box::new(cdl));
+
+        Ok(())
+    }
+
+    pub fn disable_cdl(&mut self) -> Option<CodeDataLogger> {
+        self.cpu.bus.ppu.rom.cdl.take().map(|cdl| *cdl)
+    }
+
+    pub fn get_cdl(&self) -> Option<&CodeDataLogger> {
+        self.cpu.bus.ppu.rom.cdl.as_deref()
+    }
+
+    /// records the register writes, interrupts and sprite 0 hits of each frame
+    pub fn enable_event_log(&mut self) {
+        if self.cpu.bus.ppu.event_log.is_none() {
+            self.cpu.bus.ppu.event_log = Some(Box::new(EventLog::new()));
+        }
+    }
+
+    pub fn disable_event_log(&mut self) -> Option<EventLog> {
+        self.cpu
+            .bus
+            .ppu
+            .event_log
+            .take()
+            .map(|event_log| *event_log)
+    }
+
+    pub fn get_event_log(&self) -> Option<&EventLog> {
+        self.cpu.bus.ppu.event_log.as_deref()
+    }
+
+    fn cdl_sizes(&self) -> (usize, usize) {
+        let cart = &self.cpu.bus.ppu.rom.cart;
+        (
+            cart.prg_rom_size as usize * 0x4000,
+            cart.chr_rom_size as usize * 0x2000,
+        )
+    }
+
+    /// reads the CPU address space without side effects
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        self.cpu.bus.sync();
+        self.cpu.bus.peek_byte(addr)
+    }
+
+    /// writes to RAM, PRG RAM or patches the PRG ROM until the next power cycle,
+    /// registers are left untouched
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.cpu.bus.poke_byte(addr, val);
+    }
+
+    /// reads the PPU address space: pattern tables, nametables and palettes
+    pub fn peek_ppu(&mut self, addr: u16) -> u8 {
+        self.cpu.bus.ppu.peek_data(addr)
+    }
+
+    pub fn poke_ppu(&mut self, addr: u16, val: u8) {
+        self.cpu.bus.ppu.poke_data(addr, val);
+    }
+
+    /// copy of the internal RAM and PRG RAM, used by `RamSearch`
+    pub fn snapshot_ram(&mut self) -> RamSnapshot {
+        self.cpu.bus.snapshot_ram()
+    }
+
+    pub fn peek_oam(&self, index: u8) -> u8 {
+        self.cpu.bus.ppu.peek_oam(index)
+    }
+
+    pub fn poke_oam(&mut self, index: u8, val: u8) {
+        self.cpu.bus.ppu.poke_oam(index, val);
+    }
+
+    /// the four nametables (512x480 RGB) with the visible area outlined
+    pub fn view_nametables(&mut self) -> Vec<u8> {
+        viewer::nametables(&mut self.cpu.bus.ppu, true)
+    }
+
+    /// both pattern tables (256x128 RGB) drawn with one of the 8 palettes
+    pub fn view_pattern_tables(&mut self, palette_idx: u8) -> Vec<u8> {
+        viewer::pattern_tables(&mut self.cpu.bus.ppu, palette_idx)
+    }
+
+    /// the 64 OAM sprites (64x128 RGB)
+    pub fn view_oam(&mut self) -> Vec<u8> {
+        viewer::oam(&mut self.cpu.bus.ppu)
+    }
+
+    pub fn get_sprites(&self) -> Vec<Sprite> {
+        viewer::oam_sprites(&self.cpu.bus.ppu)
+    }
+
+    /// the palette RAM as 32 RGB triples
+    pub fn view_palette(&self) -> Vec<u8> {
+        viewer::palette(&self.cpu.bus.ppu)
+    }
+
+    /// adds a Game Genie (6 or 8 letters) or a RAM freeze (addr:value) code
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, CheatError> {
+        let cheat = Cheat::parse(code)?;
+        Ok(self.cpu.bus.cheats.add(cheat))
+    }
+
+    pub fn remove_cheat(&mut self, id: usize) -> bool {
+        self.cpu.bus.cheats.remove(id)
+    }
+
+    pub fn set_cheat_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        self.cpu.bus.cheats.set_enabled(id, enabled)
+    }
+
+    pub fn get_cheats(&self) -> &Cheats {
+        &self.cpu.bus.cheats
+    }
+
+    pub fn get_cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cpu.bus.cheats
+    }
+
+    pub fn get_cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// decodes `count` instructions starting at `addr` in the current bank configuration
+    pub fn disassemble(&mut self, addr: u16, count: usize) -> Vec<Instruction> {
+        disasm::disassemble_range(&mut self.cpu.bus, addr, count)
     }
 
     #[inline]
     fn on_frame_complete(&mut self) {
+        // movies, cheats and snapshots must see the end of the frame
+        self.cpu.bus.sync();
         self.cpu.bus.ppu.frame_complete = false;
+        self.end_movie_frame();
+        self.cpu.bus.apply_ram_cheats();
+
+        let take_snapshot = match &mut self.rewind {
+            Some(rewind) => rewind.on_frame_complete(),
+            None => false,
+        };
+
+        if take_snapshot {
+            self.encode_snapshot();
+
+            if let Some(rewind) = &mut self.rewind {
+                rewind.push(&self.state_buffer);
+            }
+        }
     }
 
-    pub fn next_frame(&mut self) {
+    // saves the console in the sections of the previous snapshot and encodes them
+    // in the state buffer, so that taking a snapshot every frame doesn't allocate
+    fn encode_snapshot(&mut self) {
+        let mut snapshot = match self.snapshot.take() {
+            Some(mut snapshot) => {
+                snapshot.clear();
+                snapshot
+            }
+            None => SaveState::new(&self.cpu.bus.ppu.rom.cart.hash),
+        };
+
+        self.save_console(snapshot.get_root_mut());
+        snapshot.encode_into(&mut self.state_buffer);
+        self.snapshot = Some(snapshot);
+    }
+
+    // cheats are left out of rewind snapshots so that rewinding doesn't undo
+    // the changes to the cheat list
+    fn save_console(&self, s: &mut savestate::Section) {
+        s.data.write_u8(self.get_region().into());
+        self.cpu.save(s);
+    }
+
+    /// returns early when the debugger stops
+    pub fn next_frame(&mut self) -> Option<StopReason> {
         while !self.cpu.bus.ppu.frame_complete {
-            self.step();
+            if let Some(reason) = self.run_instruction() {
+                self.cpu.bus.sync();
+                return Some(reason);
+            }
         }
 
         self.on_frame_complete();
+
+        None
     }
 
     /// emulates enough cycles to fill the audio buffer,
+    /// with the stems output, the samples of a frame are interleaved
     pub fn next_samples(&mut self, audio_buffer: &mut [f32]) -> bool {
         let mut count = 0;
         let mut new_frame = false;
 
         while count < audio_buffer.len() {
             loop {
-                match self.cpu.bus.apu.pull_sample() {
-                    Some(sample) => {
-                        audio_buffer[count] = sample;
-                        count += 1;
+                match self.cpu.bus.apu.pull_frame() {
+                    Some(frame) => {
+                        let len = frame.len().min(audio_buffer.len() - count);
+                        audio_buffer[count..count + len].copy_from_slice(&frame[..len]);
+                        count += len;
                         if self.cpu.bus.ppu.frame_complete {
                             self.on_frame_complete();
                             new_frame = true;
                         }
                         break;
                     }
-                    None => self.step(),
+                    None => {
+                        if self.run_instruction().is_some() {
+                            // the debugger stopped, output silence
+                            audio_buffer[count..].fill(0.0);
+                            self.cpu.bus.sync();
+                            return new_frame;
+                        }
+                    }
                 }
             }
         }
 
+        self.cpu.bus.sync();
         new_frame
     }
 
+    /// `count` is in samples, a frame holds one sample per output channel
     pub fn wait_for_samples(&mut self, count: usize) {
         let mut i = 0;
 
         while i < count {
             loop {
-                match self.cpu.bus.apu.pull_sample() {
-                    Some(_) => {
-                        i += 1;
+                match self.cpu.bus.apu.pull_frame() {
+                    Some(frame) => {
+                        i += frame.len();
                         break;
                     }
-                    None => self.step(),
+                    None => {
+                        if self.run_instruction().is_some() {
+                            self.cpu.bus.sync();
+                            return;
+                        }
+                    }
                 }
             }
         }
+
+        self.cpu.bus.sync();
     }
 
     pub fn fill_audio_buffer(&mut self, buffer: &mut [f32], avoid_underruns: bool) {
-        let remaining_samples_in_bufffer = self.cpu.bus.apu.remaining_samples() as usize;
+        let remaining_samples_in_bufffer = self.cpu.bus.apu.remaining_samples();
 
         if avoid_underruns {
             // ensure that the buffer is filled with enough samples
@@ -91,8 +429,236 @@ impl Nes {
         self.cpu.bus.apu.clear_buffer();
     }
 
+    pub fn get_audio_channel(&self, channel: AudioChannel) -> ChannelSettings {
+        self.cpu.bus.apu.get_channel_settings(channel)
+    }
+
+    pub fn set_audio_channel(&mut self, channel: AudioChannel, settings: ChannelSettings) {
+        self.cpu.bus.apu.set_channel_settings(channel, settings);
+    }
+
+    pub fn get_audio_output(&self) -> AudioOutput {
+        self.cpu.bus.apu.get_output()
+    }
+
+    /// the audio buffer is cleared
+    pub fn set_audio_output(&mut self, output: AudioOutput) {
+        self.cpu.bus.apu.set_output(output);
+    }
+
+    /// adjusts the sample rate to keep the buffer read by `fill_audio_buffer` around
+    /// its target, for frontends whose emulation is paced by the display
+    pub fn enable_audio_rate_control(&mut self, config: RateControlConfig) {
+        self.cpu.bus.apu.set_rate_control(Some(config));
+    }
+
+    pub fn disable_audio_rate_control(&mut self) {
+        self.cpu.bus.apu.set_rate_control(None);
+    }
+
+    pub fn get_audio_rate_control(&self) -> Option<RateControlConfig> {
+        self.cpu.bus.apu.get_rate_control()
+    }
+
+    pub fn get_audio_buffer_health(&self) -> AudioBufferHealth {
+        self.cpu.bus.apu.get_buffer_health()
+    }
+
+    /// the reading end of the audio buffer, it can be moved to the audio thread while the
+    /// emulation runs on another one, `fill_audio_buffer` doesn't output anything afterwards,
+    /// returns None when it was already taken
+    pub fn take_audio_consumer(&mut self) -> Option<AudioConsumer> {
+        self.cpu.bus.apu.take_consumer()
+    }
+
+    /// presses the reset button, the cartridge isn't wired to the reset line so the mapper
+    /// keeps its banks and PRG RAM
     pub fn soft_reset(&mut self) {
         self.cpu.soft_reset();
+
+        if let Some(session) = &mut self.movie {
+            if session.mode == MovieMode::Recording {
+                session.current.commands.insert(MovieCommands::SOFT_RESET);
+            }
+        }
+    }
+
+    /// used the next time the console is powered on
+    pub fn set_power_on_config(&mut self, config: PowerOnConfig) {
+        self.power_on = config;
+    }
+
+    pub fn get_power_on_config(&self) -> PowerOnConfig {
+        self.power_on
+    }
+
+    /// reinitializes the whole console as if it was turned off and on again,
+    /// the ROM and the battery-backed RAM are kept
+    pub fn power_cycle(&mut self) {
+        self.hard_reset(self.power_on, true);
+
+        if let Some(session) = &mut self.movie {
+            if session.mode == MovieMode::Recording {
+                session.current.commands.insert(MovieCommands::HARD_RESET);
+            }
+        }
+    }
+
+    // rebuilds the console from the cartridge
+    fn hard_reset(&mut self, power_on: PowerOnConfig, keep_battery_ram: bool) {
+        let battery_ram = if keep_battery_ram && self.cpu.bus.ppu.rom.cart.battery {
+            Some(self.cpu.bus.ppu.rom.mapper.get_prg_ram_mut().to_vec())
+        } else {
+            None
+        };
+
+        let bytes = self.cpu.bus.ppu.rom.cart.original_bytes().to_vec();
+        let rom = ROM::new(bytes).expect("the ROM was already loaded successfully");
+        let sample_rate = self.cpu.bus.apu.get_sample_rate();
+        let region = self.get_region();
+        let render_mode = self.get_render_mode();
+        let cpu_mode = self.get_cpu_mode();
+        let joypad1 = self.cpu.bus.joypad1.status;
+        let joypad2 = self.cpu.bus.joypad2.status;
+
+        let mut bus = Bus::new(rom, sample_rate);
+        bus.fill_memory(&power_on);
+        // debugging tools survive power cycles
+        bus.debugger = self.cpu.bus.debugger.take();
+        bus.tracer = self.cpu.bus.tracer.take();
+        bus.ppu.rom.cdl = self.cpu.bus.ppu.rom.cdl.take();
+        bus.ppu.event_log = self.cpu.bus.ppu.event_log.take();
+        std::mem::swap(&mut bus.cheats, &mut self.cpu.bus.cheats);
+        bus.apu.take_audio_from(&mut self.cpu.bus.apu);
+        bus.ppu.set_render_mode(render_mode);
+        bus.set_cpu_mode(cpu_mode);
+
+        if let Some(battery_ram) = battery_ram {
+            bus.ppu
+                .rom
+                .mapper
+                .get_prg_ram_mut()
+                .copy_from_slice(&battery_ram);
+        }
+
+        self.cpu = CPU::new(bus);
+        self.set_region(region);
+        self.cpu.bus.joypad1.status = joypad1;
+        self.cpu.bus.joypad2.status = joypad2;
+    }
+
+    /// inputs are sampled once per frame while recording,
+    /// the first frame starts right after this call
+    pub fn start_recording(&mut self, anchor: MovieAnchor) {
+        self.stop_movie();
+
+        let start = match anchor {
+            MovieAnchor::PowerOn => {
+                self.hard_reset(self.power_on, false);
+                MovieStart::PowerOn
+            }
+            MovieAnchor::CurrentState => MovieStart::SaveState(self.save_state().encode()),
+        };
+
+        let movie = Movie::new(
+            &self.cpu.bus.ppu.rom.cart,
+            start,
+            self.get_region(),
+            self.power_on,
+        );
+        self.movie = Some(MovieSession::new(movie, MovieMode::Recording));
+    }
+
+    /// resets the console to the start of the movie,
+    /// the inputs of the movie then replace the joypad inputs until it ends
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), SaveStateError> {
+        self.stop_movie();
+        self.set_region(movie.region);
+
+        match &movie.start {
+            MovieStart::PowerOn => self.hard_reset(movie.power_on, false),
+            MovieStart::SaveState(state) => self.load_state(state)?,
+        }
+
+        if !movie.is_empty() {
+            self.movie = Some(MovieSession::new(movie, MovieMode::Playing));
+        }
+
+        Ok(())
+    }
+
+    /// returns the recorded or played movie,
+    /// the frame being recorded is not included since it is not complete
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let session = self.movie.take()?;
+        self.cpu.bus.joypad1.unlatch();
+        self.cpu.bus.joypad2.unlatch();
+
+        Some(session.movie)
+    }
+
+    pub fn get_movie_status(&self) -> MovieStatus {
+        match &self.movie {
+            Some(session) => session.status(),
+            None => MovieStatus::Idle,
+        }
+    }
+
+    fn start_movie_frame(&mut self) {
+        let Some(session) = &mut self.movie else {
+            return;
+        };
+
+        session.frame_started = true;
+
+        // commands were already applied when recording
+        let frame = match session.mode {
+            MovieMode::Recording => {
+                session.current.joypad1 = self.cpu.bus.joypad1.status;
+                session.current.joypad2 = self.cpu.bus.joypad2.status;
+                session.current
+            }
+            MovieMode::Playing => {
+                let frame = session.movie.frames[session.frame];
+
+                if frame.commands.contains(MovieCommands::HARD_RESET) {
+                    let power_on = session.movie.power_on;
+                    self.hard_reset(power_on, true);
+                } else if frame.commands.contains(MovieCommands::SOFT_RESET) {
+                    self.cpu.soft_reset();
+                }
+
+                frame
+            }
+        };
+
+        self.cpu.bus.joypad1.latch(frame.joypad1);
+        self.cpu.bus.joypad2.latch(frame.joypad2);
+    }
+
+    fn end_movie_frame(&mut self) {
+        let Some(session) = &mut self.movie else {
+            return;
+        };
+
+        if !session.frame_started {
+            return;
+        }
+
+        session.frame_started = false;
+        session.frame += 1;
+
+        match session.mode {
+            MovieMode::Recording => {
+                session.movie.frames.push(session.current);
+                session.current = MovieFrame::default();
+            }
+            MovieMode::Playing => {
+                if session.frame >= session.movie.len() {
+                    self.stop_movie();
+                }
+            }
+        }
     }
 
     pub fn get_joypad1_mut(&mut self) -> &mut Joypad {
@@ -113,6 +679,41 @@ impl Nes {
         state
     }
 
+    /// records a snapshot every `config.interval` frames
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        self.rewind = Some(Rewind::new(config));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+        self.snapshot = None;
+    }
+
+    pub fn get_rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// restores the most recent snapshot older than the current frame and drops it
+    /// from the rewind buffer, the frame buffer is updated on the next frame
+    pub fn rewind_step(&mut self) -> Result<bool, SaveStateError> {
+        let mut state = std::mem::take(&mut self.state_buffer);
+
+        let restored = match &mut self.rewind {
+            Some(rewind) => rewind.pop(&mut state),
+            None => false,
+        };
+
+        let res = if restored {
+            self.load_state(&state).map(|_| true)
+        } else {
+            Ok(false)
+        };
+
+        self.state_buffer = state;
+
+        res
+    }
+
     pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
         let mut state = SaveState::decode(data)?;
 
@@ -128,18 +729,194 @@ impl Nes {
 
         self.load(state.get_root_mut())?;
 
+        if let Some(rewind) = &mut self.rewind {
+            rewind.on_state_loaded();
+        }
+
         Ok(())
     }
 }
 
 impl savestate::Save for Nes {
     fn save(&self, s: &mut savestate::Section) {
-        self.cpu.save(s);
+        self.save_console(s);
+
+        if self.cpu.bus.cheats.is_saved_in_states() {
+            self.cpu.bus.cheats.save(s);
+        }
     }
 
     fn load(&mut self, s: &mut savestate::Section) -> Result<(), SaveStateError> {
+        // the timings of the state only make sense in its region
+        let save_state_region =
+            Region::try_from(s.data.read_u8()?).map_err(|_| SaveStateError::InvalidData)?;
+        let region = self.get_region();
+
+        if save_state_region != region {
+            return Err(SaveStateError::IncoherentRegion {
+                save_state_region,
+                region,
+            });
+        }
+
         self.cpu.load(s)?;
 
+        if self.cpu.bus.cheats.is_saved_in_states() {
+            // states saved without cheats keep the current list
+            match self.cpu.bus.cheats.load(s) {
+                Err(SaveStateError::MissingSection(_)) => {}
+                res => res?,
+            }
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Nes;
+    use crate::{
+        apu::rate_control::RateControlConfig,
+        cpu::{memory::Memory, rom::ROM},
+        movie::{rom_checksum, MovieAnchor, MovieCommands},
+        power_on::{PowerOnConfig, RamFill},
+        tests::{nestest, NESTEST},
+    };
+
+    const BATTERY_FLAG: u8 = 0b10;
+
+    fn battery_backed_nes() -> Nes {
+        let mut bytes = NESTEST.to_vec();
+        bytes[6] |= BATTERY_FLAG;
+
+        Nes::new(ROM::new(bytes).unwrap(), 44100.0)
+    }
+
+    #[test]
+    fn resets_keep_the_battery_ram() {
+        let mut nes = battery_backed_nes();
+        nes.set_power_on_config(PowerOnConfig {
+            ram_fill: RamFill::Ones,
+        });
+        nes.cpu.bus.write_byte(0x0300, 0x12);
+        nes.cpu.bus.write_byte(0x6000, 0x34);
+
+        nes.soft_reset();
+        assert_eq!(nes.cpu.bus.read_byte(0x0300), 0x12);
+        assert_eq!(nes.cpu.bus.read_byte(0x6000), 0x34);
+
+        nes.power_cycle();
+        assert_eq!(nes.cpu.bus.read_byte(0x0300), 0xFF);
+        assert_eq!(nes.cpu.bus.read_byte(0x6000), 0x34);
+    }
+
+    #[test]
+    fn power_cycles_drop_the_rom_patches() {
+        let mut nes = Nes::new(nestest(), 44100.0);
+        let checksum = rom_checksum(&nes.cpu.bus.ppu.rom.cart);
+        let prg = nes.peek(0xC000);
+        let chr = nes.peek_ppu(0x0000);
+
+        nes.poke(0xC000, !prg);
+        nes.poke_ppu(0x0000, !chr);
+        assert_eq!(nes.peek(0xC000), !prg);
+        assert_eq!(nes.peek_ppu(0x0000), !chr);
+        assert_eq!(rom_checksum(&nes.cpu.bus.ppu.rom.cart), checksum);
+
+        nes.power_cycle();
+        assert_eq!(nes.peek(0xC000), prg);
+        assert_eq!(nes.peek_ppu(0x0000), chr);
+    }
+
+    #[test]
+    fn resets_are_recorded_in_movies() {
+        let mut nes = battery_backed_nes();
+        nes.start_recording(MovieAnchor::CurrentState);
+
+        nes.next_frame();
+        nes.power_cycle();
+        nes.next_frame();
+        nes.soft_reset();
+        nes.next_frame();
+
+        let movie = nes.stop_movie().unwrap();
+        let commands = movie
+            .frames
+            .iter()
+            .map(|frame| frame.commands)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            commands,
+            [
+                MovieCommands::empty(),
+                MovieCommands::HARD_RESET,
+                MovieCommands::SOFT_RESET
+            ]
+        );
+    }
+
+    #[test]
+    fn audio_buffer_health() {
+        let mut nes = Nes::new(nestest(), 44100.0);
+
+        let health = nes.get_audio_buffer_health();
+        assert_eq!(health.buffered_frames, 0);
+        assert_eq!(health.target_frames, None);
+        assert_eq!(health.rate_ratio, 1.0);
+
+        // 44100 / 60.1 samples per frame, the first frame ends at the first vblank
+        nes.next_frame();
+        nes.clear_audio_buffer();
+        nes.next_frame();
+        let buffered = nes.get_audio_buffer_health().buffered_frames;
+        assert!((730..=740).contains(&buffered), "{buffered}");
+
+        // reading more samples than buffered is an underrun
+        let mut buffer = vec![0.0; buffered + 1];
+        nes.fill_audio_buffer(&mut buffer, false);
+        let health = nes.get_audio_buffer_health();
+        assert_eq!((health.buffered_frames, health.underruns), (0, 1));
+
+        // the oldest samples are dropped once the buffer is full
+        while nes.get_audio_buffer_health().overruns == 0 {
+            nes.next_frame();
+        }
+        let health = nes.get_audio_buffer_health();
+        assert_eq!(health.buffered_frames, health.capacity_frames);
+
+        nes.clear_audio_buffer();
+        let health = nes.get_audio_buffer_health();
+        assert_eq!((health.underruns, health.overruns), (0, 0));
+    }
+
+    #[test]
+    fn audio_rate_control() {
+        let mut nes = Nes::new(nestest(), 44100.0);
+        let config = RateControlConfig {
+            target_frames: 2048,
+            ..Default::default()
+        };
+        nes.enable_audio_rate_control(config);
+
+        // the buffer fills faster while it is below its target
+        nes.next_frame();
+        nes.next_frame();
+        let health = nes.get_audio_buffer_health();
+        assert_eq!(health.target_frames, Some(2048));
+        assert!(health.rate_ratio > 1.0 && health.rate_ratio <= 1.0 + config.max_delta);
+
+        for _ in 0..5 {
+            nes.next_frame();
+        }
+        let health = nes.get_audio_buffer_health();
+        assert!(health.buffered_frames > 2048);
+        assert!(health.rate_ratio < 1.0 && health.rate_ratio >= 1.0 - config.max_delta);
+
+        nes.disable_audio_rate_control();
+        let health = nes.get_audio_buffer_health();
+        assert_eq!(health.target_frames, None);
+        assert_eq!(health.rate_ratio, 1.0);
+    }
+}
@@ -0,0 +1,139 @@
+// https://www.nesdev.org/wiki/CPU_power_up_state
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RamFill {
+    /// $00 everywhere
+    Zeros,
+    /// $FF everywhere
+    Ones,
+    /// $00 for 4 bytes then $FF for 4 bytes in the CPU RAM and $00 in the other memories,
+    /// as done by FCEUX
+    Fceux,
+    /// pseudo-random bytes, the same seed always gives the same contents
+    Random(u64),
+}
+
+/// contents of the memories when the console is powered on,
+/// covers the CPU RAM, the PRG RAM, the VRAM and the OAM,
+/// battery-backed PRG RAM keeps the data saved by the game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerOnConfig {
+    pub ram_fill: RamFill,
+}
+
+impl Default for PowerOnConfig {
+    fn default() -> Self {
+        PowerOnConfig {
+            ram_fill: RamFill::Zeros,
+        }
+    }
+}
+
+impl PowerOnConfig {
+    pub fn filler(&self) -> RamFiller {
+        let seed = match self.ram_fill {
+            RamFill::Random(seed) => seed,
+            _ => 0,
+        };
+
+        RamFiller {
+            fill: self.ram_fill,
+            rng: SplitMix64(seed),
+        }
+    }
+}
+
+// Fills the memories one after the other,
+// the random generator is shared so that every memory gets different contents
+pub struct RamFiller {
+    fill: RamFill,
+    rng: SplitMix64,
+}
+
+impl RamFiller {
+    pub fn fill_cpu_ram(&mut self, ram: &mut [u8]) {
+        match self.fill {
+            RamFill::Fceux => {
+                for (addr, byte) in ram.iter_mut().enumerate() {
+                    *byte = if addr & 4 != 0 { 0xFF } else { 0 };
+                }
+            }
+            _ => self.fill(ram),
+        }
+    }
+
+    /// fills the PRG RAM, the VRAM or the OAM
+    pub fn fill(&mut self, memory: &mut [u8]) {
+        match self.fill {
+            RamFill::Zeros | RamFill::Fceux => memory.fill(0),
+            RamFill::Ones => memory.fill(0xFF),
+            RamFill::Random(_) => {
+                for chunk in memory.chunks_mut(8) {
+                    let bytes = self.rng.next_u64().to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+// https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PowerOnConfig, RamFill};
+
+    // fills a CPU RAM and another memory
+    fn fill(ram_fill: RamFill) -> ([u8; 16], [u8; 16]) {
+        let mut filler = PowerOnConfig { ram_fill }.filler();
+        let mut ram = [0x55; 16];
+        let mut memory = [0x55; 16];
+        filler.fill_cpu_ram(&mut ram);
+        filler.fill(&mut memory);
+
+        (ram, memory)
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(fill(RamFill::Zeros), ([0; 16], [0; 16]));
+        assert_eq!(fill(RamFill::Ones), ([0xFF; 16], [0xFF; 16]));
+
+        let fceux = [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+        let (ram, memory) = fill(RamFill::Fceux);
+        assert_eq!(ram, [fceux, fceux].concat().as_slice());
+        assert_eq!(memory, [0; 16]);
+    }
+
+    #[test]
+    fn random_fill_only_depends_on_the_seed() {
+        let (ram, memory) = fill(RamFill::Random(42));
+
+        assert_eq!(fill(RamFill::Random(42)), (ram, memory));
+        assert_ne!(fill(RamFill::Random(43)).0, ram);
+        // the memories don't get the same contents
+        assert_ne!(ram, memory);
+        // odd sizes consume whole words
+        let mut filler = PowerOnConfig {
+            ram_fill: RamFill::Random(42),
+        }
+        .filler();
+        let mut odd = [0; 3];
+        filler.fill_cpu_ram(&mut odd);
+        assert_eq!(odd, ram[..3]);
+        let mut rest = [0; 16];
+        filler.fill(&mut rest);
+        assert_eq!(rest[..8], ram[8..]);
+    }
+}
@@ -1,8 +1,13 @@
 mod registers;
+pub mod viewer;
 
 use self::registers::{Ctrl, Registers, SpriteSize, Status};
 use crate::{
+    cdl::ChrFlags,
     cpu::rom::{Mirroring, ROM},
+    event_log::{Event, EventKind, EventLog},
+    power_on::RamFiller,
+    region::Region,
     savestate::{self, SaveStateError},
 };
 
@@ -28,6 +33,18 @@ pub static COLOR_PALETTE: [(u8, u8, u8); 64] = [
    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
 ];
 
+/// the scanline renderer draws each visible scanline in one go and falls back to
+/// the dot renderer for the lines where the CPU accesses the PPU or the mapper,
+/// both produce the same output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    Dot,
+    Scanline,
+}
+
+// color, behind the background, OAM index
+type SpritePixel = ((u8, u8, u8), bool, u8);
+
 #[derive(Clone, Copy)]
 struct SpriteData {
     x: u16,
@@ -40,6 +57,7 @@ struct SpriteData {
 #[allow(clippy::upper_case_acronyms)]
 pub struct PPU {
     pub rom: ROM,
+    region: Region,
     regs: Registers,
     open_bus: u8,
     vram: [u8; 2 * 1024],
@@ -52,6 +70,8 @@ pub struct PPU {
     nmi_triggered: bool,
     nmi_edge_detector: bool,
     should_trigger_nmi: bool,
+    // set by a $2002 read made one dot before the vblank flag is set
+    vblank_suppressed: bool,
     pub frame_complete: bool,
     tile_data: u64,
     nametable_byte: u8,
@@ -62,11 +82,16 @@ pub struct PPU {
     visible_sprites_count: u8,
     frame_buffer: [u8; WIDTH * HEIGHT * 3],
     frame_buffer_complete: Box<[u8; WIDTH * HEIGHT * 3]>, // avoid stack overflow in WASM
+    pub event_log: Option<Box<EventLog>>,
+    render_mode: RenderMode,
+    // last dot of the current scanline drawn by the scanline renderer
+    rendered_dot: u16,
 }
 
 impl PPU {
     pub fn new(rom: ROM) -> Self {
         let mut ppu = PPU {
+            region: rom.cart.region,
             rom,
             regs: Registers::new(),
             open_bus: 0,
@@ -80,6 +105,7 @@ impl PPU {
             nmi_triggered: false,
             should_trigger_nmi: false,
             nmi_edge_detector: false,
+            vblank_suppressed: false,
             frame_complete: false,
             // background data
             tile_data: 0,
@@ -97,6 +123,9 @@ impl PPU {
             visible_sprites_count: 0,
             frame_buffer: [0; WIDTH * HEIGHT * 3],
             frame_buffer_complete: Box::new([0; WIDTH * HEIGHT * 3]),
+            event_log: None,
+            render_mode: RenderMode::Dot,
+            rendered_dot: 0,
         };
 
         ppu.reset();
@@ -104,8 +133,8 @@ impl PPU {
     }
 
     fn tick(&mut self) {
-        // TODO: handle NMI delay
-
+        // the NMI is asserted one dot after the vblank flag is set,
+        // a $2002 read in between clears the flag and suppresses the NMI
         if self.should_trigger_nmi
             && self.regs.ctrl.contains(Ctrl::GENERATE_NMI)
             && self.regs.status.contains(Status::VBLANK_STARTED)
@@ -114,7 +143,13 @@ impl PPU {
             self.should_trigger_nmi = false;
         }
 
-        if self.regs.rendering_enabled() && self.regs.f && self.scanline == 261 && self.cycle == 339
+        let pre_render_scanline = self.region.pre_render_scanline();
+
+        if self.regs.rendering_enabled()
+            && self.regs.f
+            && self.region.skips_odd_frame_dot()
+            && self.scanline == pre_render_scanline
+            && self.cycle == 339
         {
             // skip cycle 339 of pre-render scanline
             self.cycle = 0;
@@ -130,7 +165,7 @@ impl PPU {
             self.cycle = 0;
             self.scanline += 1;
 
-            if self.scanline > 261 {
+            if self.scanline > pre_render_scanline {
                 self.scanline = 0;
                 self.regs.f = !self.regs.f;
                 self.frame += 1;
@@ -145,31 +180,34 @@ impl PPU {
     pub fn step(&mut self) {
         self.tick();
 
-        let preline = self.scanline == 261;
+        let preline = self.scanline == self.region.pre_render_scanline();
         let visible_line = self.scanline < 240;
         let render_line = preline || visible_line;
         let pre_fetch_cycle = self.cycle >= 321 && self.cycle <= 336;
         let visible_cycle = self.cycle >= 1 && self.cycle <= 256;
         let fetch_cycle = pre_fetch_cycle || visible_cycle;
 
-        // background logic
-        if self.regs.show_background() {
-            if visible_line && visible_cycle {
-                self.render_pixel();
+        if visible_line && visible_cycle {
+            match self.render_mode {
+                RenderMode::Dot => self.render_dot(),
+                RenderMode::Scanline => {
+                    if self.cycle == 1 {
+                        self.rendered_dot = 0;
+                    }
+
+                    if self.cycle == 256 {
+                        self.catch_up();
+                    }
+                }
             }
 
+            return;
+        }
+
+        // background logic
+        if self.regs.show_background() {
             if render_line && fetch_cycle {
-                self.tile_data <<= 4;
-
-                match self.cycle & 7 {
-                    1 => self.fetch_nametable_byte(),
-                    3 => self.fetch_attribute_table_byte(),
-                    // 5 => self.fetch_pattern_table_low_byte(),
-                    // 7 => self.fetch_pattern_table_high_byte(),
-                    7 => self.fetch_pattern_table_bytes(),
-                    0 => self.store_background_tile_data(),
-                    _ => {}
-                }
+                self.fetch_background();
             }
 
             if preline && self.cycle >= 280 && self.cycle <= 304 {
@@ -177,10 +215,6 @@ impl PPU {
             }
 
             if render_line {
-                if fetch_cycle && self.cycle & 7 == 0 {
-                    self.regs.increment_x();
-                }
-
                 if self.cycle == 256 {
                     self.regs.increment_y();
                 }
@@ -201,10 +235,18 @@ impl PPU {
         }
 
         // VBlank
-        if self.scanline == 241 && self.cycle == 1 {
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
             self.frame_complete = true;
-            self.regs.status.insert(Status::VBLANK_STARTED);
-            self.detect_nmi_edge();
+
+            if let Some(event_log) = &mut self.event_log {
+                event_log.end_frame();
+            }
+
+            if !std::mem::take(&mut self.vblank_suppressed) {
+                self.regs.status.insert(Status::VBLANK_STARTED);
+                self.detect_nmi_edge();
+            }
+
             self.transfer_frame_buffer();
         }
 
@@ -216,6 +258,250 @@ impl PPU {
         }
     }
 
+    /// advances the PPU by `dots` dots
+    pub fn run(&mut self, dots: u32) {
+        let mut dots = dots;
+
+        while dots > 0 {
+            // nothing happens on the dots the scanline renderer draws at the end of the line
+            if self.render_mode == RenderMode::Scanline
+                && self.scanline < 240
+                && self.cycle >= 1
+                && self.cycle < 255
+            {
+                let skipped = (255 - self.cycle as u32).min(dots);
+                self.cycle += skipped as u16;
+                dots -= skipped;
+            } else {
+                self.step();
+                dots -= 1;
+            }
+        }
+    }
+
+    /// dots until the PPU may change state visible to the CPU without a register access:
+    /// the start of VBlank, a pending NMI or the mapper scanline counter
+    pub fn dots_until_event(&self) -> u32 {
+        if self.should_trigger_nmi || self.nmi_triggered {
+            return 0;
+        }
+
+        let mut dots = self.dots_until(self.region.vblank_scanline(), 1);
+
+        if self.regs.rendering_enabled() {
+            let line = match (self.scanline, self.cycle) {
+                (scanline, cycle) if scanline < 240 && cycle < 260 => scanline,
+                (scanline, _) if scanline < 239 => scanline + 1,
+                _ => 0,
+            };
+
+            dots = dots.min(self.dots_until(line, 260));
+        }
+
+        // the dot skipped on odd frames brings the events one dot closer
+        dots.saturating_sub(1)
+    }
+
+    fn dots_until(&self, scanline: u16, cycle: u16) -> u32 {
+        let frame_len = (self.region.pre_render_scanline() as u32 + 1) * 341;
+        let pos = self.scanline as u32 * 341 + self.cycle as u32;
+        let target = scanline as u32 * 341 + cycle as u32;
+
+        match (target + frame_len - pos) % frame_len {
+            0 => frame_len,
+            dots => dots,
+        }
+    }
+
+    // dots 1-256 of a visible scanline
+    fn render_dot(&mut self) {
+        if self.regs.show_background() {
+            self.render_pixel();
+            self.fetch_background();
+
+            if self.cycle == 256 {
+                self.regs.increment_y();
+            }
+        }
+    }
+
+    fn fetch_background(&mut self) {
+        self.tile_data <<= 4;
+
+        match self.cycle & 7 {
+            1 => self.fetch_nametable_byte(),
+            3 => self.fetch_attribute_table_byte(),
+            // 5 => self.fetch_pattern_table_low_byte(),
+            // 7 => self.fetch_pattern_table_high_byte(),
+            7 => self.fetch_pattern_table_bytes(),
+            0 => self.store_background_tile_data(),
+            _ => {}
+        }
+
+        if self.cycle & 7 == 0 {
+            self.regs.increment_x();
+        }
+    }
+
+    /// draws the dots of the current scanline the scanline renderer has skipped so far,
+    /// called before the CPU accesses the PPU or the mapper so that mid-line writes
+    /// only apply to the following dots
+    pub fn catch_up(&mut self) {
+        if self.render_mode != RenderMode::Scanline || self.scanline >= 240 {
+            return;
+        }
+
+        let target = self.cycle.min(256);
+
+        if self.rendered_dot >= target {
+            return;
+        }
+
+        let cycle = self.cycle;
+
+        if self.rendered_dot == 0 && target == 256 {
+            self.render_scanline();
+        } else {
+            // fall back to the dot renderer when the line is interrupted
+            for dot in self.rendered_dot + 1..=target {
+                self.cycle = dot;
+                self.render_dot();
+            }
+        }
+
+        self.cycle = cycle;
+        self.rendered_dot = target;
+    }
+
+    // renders dots 1-256 in one go, the result is the same as calling render_dot for each dot
+    // as long as the PPU registers and the mapper don't change during the line
+    fn render_scanline(&mut self) {
+        if !self.regs.show_background() {
+            return;
+        }
+
+        let sprites = self.sprite_line();
+        let fine_x = self.regs.x as u64;
+        let show_leftmost_background = self.regs.show_leftmost_background();
+        let show_leftmost_sprites = self.regs.show_leftmost_sprites();
+        let backdrop = COLOR_PALETTE[(self.palette[0] & 63) as usize];
+        let y = self.scanline as usize;
+
+        for dot in 1..=256u16 {
+            self.cycle = dot;
+            let x = dot as usize - 1;
+
+            let color_idx = (((self.tile_data >> 32) >> ((7 - fine_x) * 4)) & 0xF) as usize;
+            let mut bg = (color_idx & 3 != 0)
+                .then(|| COLOR_PALETTE[(self.palette[color_idx] & 63) as usize]);
+            let mut sprite = sprites[x];
+
+            if x < 8 {
+                if !show_leftmost_background {
+                    bg = None;
+                }
+
+                if !show_leftmost_sprites {
+                    sprite = None;
+                }
+            }
+
+            let color = match (bg, sprite) {
+                (None, None) => backdrop,
+                (None, Some((sp, _, _))) => sp,
+                (Some(bg), None) => bg,
+                (Some(bg), Some((sp, behind, _))) => {
+                    if behind {
+                        bg
+                    } else {
+                        sp
+                    }
+                }
+            };
+
+            if let Some((_, _, 0)) = sprite {
+                if x < 255 && bg.is_some() && !self.regs.status.contains(Status::SPRITE_ZERO_HIT) {
+                    self.regs.status.insert(Status::SPRITE_ZERO_HIT);
+                    self.log_event(EventKind::SpriteZeroHit, 0, 0);
+                }
+            }
+
+            self.set_pixel(x, y, color);
+            self.fetch_background();
+        }
+
+        self.regs.increment_y();
+    }
+
+    // the sprite pixel of each dot of the current scanline, see get_sprite_pixel
+    fn sprite_line(&self) -> [Option<SpritePixel>; WIDTH] {
+        let mut line = [None; WIDTH];
+
+        if !self.regs.show_sprites() {
+            return line;
+        }
+
+        for sprite in &self.scanline_sprites[..self.visible_sprites_count as usize] {
+            for (i, &idx) in sprite.chr.iter().enumerate() {
+                let x = sprite.x as usize + i;
+
+                if x < WIDTH && line[x].is_none() {
+                    if let Some(color) = self.sprite_color(sprite.palette_idx, idx) {
+                        line[x] = Some((color, sprite.behind_background, sprite.idx));
+                    }
+                }
+            }
+        }
+
+        line
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.catch_up();
+        self.render_mode = mode;
+        self.sync_rendered_dot();
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    // the dot renderer is always up to date
+    fn sync_rendered_dot(&mut self) {
+        self.rendered_dot = if self.scanline < 240 {
+            self.cycle.min(256)
+        } else {
+            0
+        };
+    }
+
+    pub fn get_scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// number of frames since power on
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    /// current VRAM address (the v register), used by PPUDATA accesses
+    pub fn get_vram_addr(&self) -> u16 {
+        self.regs.v & 0x3FFF
+    }
+
+    pub fn fill_memory(&mut self, filler: &mut RamFiller) {
+        filler.fill(&mut self.vram);
+        filler.fill(&mut self.attributes);
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+
+        if self.scanline > region.pre_render_scanline() {
+            self.scanline = region.pre_render_scanline();
+        }
+    }
+
     fn transfer_frame_buffer(&mut self) {
         self.frame_buffer_complete
             .copy_from_slice(&self.frame_buffer);
@@ -252,8 +538,8 @@ impl PPU {
         let fine_y = self.regs.fine_y() as u16;
         let offset = table + tile * 16 + fine_y;
 
-        self.pattern_table_low_byte = self.read_chr(offset);
-        self.pattern_table_high_byte = self.read_chr(offset + 8);
+        self.pattern_table_low_byte = self.fetch_chr(offset);
+        self.pattern_table_high_byte = self.fetch_chr(offset + 8);
     }
 
     fn reset(&mut self) {
@@ -264,6 +550,18 @@ impl PPU {
         self.regs.write_mask(0);
     }
 
+    // https://www.nesdev.org/wiki/PPU_power_up_state
+    // VRAM, OAM, the palette and the status register are unchanged
+    pub fn soft_reset(&mut self) {
+        self.regs.write_ctrl(0);
+        self.regs.write_mask(0);
+        self.regs.t = 0;
+        self.regs.x = 0;
+        self.regs.w = false;
+        self.regs.f = false;
+        self.data_buffer = 0;
+    }
+
     fn store_background_tile_data(&mut self) {
         let mut data: u32 = 0;
         let attr = self.attribute_table_byte << 2;
@@ -295,7 +593,7 @@ impl PPU {
         .map(|idx| COLOR_PALETTE[(self.palette[idx] & 63) as usize])
     }
 
-    fn get_sprite_pixel(&mut self) -> Option<((u8, u8, u8), bool, u8)> {
+    fn get_sprite_pixel(&mut self) -> Option<SpritePixel> {
         if self.regs.show_sprites() {
             let x = self.cycle - 1;
 
@@ -355,8 +653,8 @@ impl PPU {
                 let tile_offset = chr_bank + tile_idx * 16 + row;
 
                 if count < 8 {
-                    let chr_low = self.read_chr(tile_offset);
-                    let chr_high = self.read_chr(tile_offset + 8);
+                    let chr_low = self.fetch_chr(tile_offset);
+                    let chr_high = self.fetch_chr(tile_offset + 8);
                     let mut chr = [0u8; 8];
 
                     #[allow(clippy::needless_range_loop)]
@@ -425,6 +723,7 @@ impl PPU {
 
             if sprite_zero_hit {
                 self.regs.status.insert(Status::SPRITE_ZERO_HIT);
+                self.log_event(EventKind::SpriteZeroHit, 0, 0);
             }
         }
 
@@ -453,6 +752,24 @@ impl PPU {
         }
     }
 
+    // reading $2002 one dot before the vblank flag is set reads it clear and the flag is not set
+    // for this frame, reading it on the same dot or one dot later suppresses the NMI
+    // https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+    fn vblank_race(&mut self) {
+        if self.scanline != self.region.vblank_scanline() {
+            return;
+        }
+
+        match self.cycle {
+            0 => self.vblank_suppressed = true,
+            1 | 2 => {
+                self.should_trigger_nmi = false;
+                self.nmi_triggered = false;
+            }
+            _ => {}
+        }
+    }
+
     pub fn is_asserting_nmi(&mut self) -> bool {
         let triggered = self.nmi_triggered;
         self.nmi_triggered = false;
@@ -504,11 +821,58 @@ impl PPU {
         self.rom.mapper.read(&mut self.rom.cart, addr)
     }
 
+    // pattern fetches made while rendering
+    fn fetch_chr(&mut self, addr: u16) -> u8 {
+        if self.rom.cdl.is_some() {
+            self.rom.log_chr(addr, ChrFlags::DRAWN);
+        }
+
+        self.read_chr(addr)
+    }
+
     fn read_nametable(&self, addr: u16) -> u8 {
         let addr = self.nametable_mirrored_addr(addr);
         self.vram[addr as usize]
     }
 
+    /// reads the PPU address space without going through the read buffer
+    pub fn peek_data(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+
+        match addr {
+            0x0000..=0x1fff => self.read_chr(addr),
+            0x2000..=0x3eff => self.read_nametable(addr),
+            _ => self.palette[palette_index(addr)],
+        }
+    }
+
+    /// writes to the PPU address space, CHR ROM bytes are patched until the next power cycle
+    pub fn poke_data(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0x3FFF;
+
+        match addr {
+            0x0000..=0x1fff => match self.rom.mapper.chr_rom_offset(&self.rom.cart, addr) {
+                Some(offset) => {
+                    let start = self.rom.cart.chr_rom_start;
+                    self.rom.cart.patch(start + offset, val);
+                }
+                None => self.rom.mapper.write(&mut self.rom.cart, addr, val),
+            },
+            0x2000..=0x3eff => {
+                self.vram[self.nametable_mirrored_addr(addr) as usize] = val;
+            }
+            _ => self.palette[palette_index(addr)] = val,
+        }
+    }
+
+    pub fn peek_oam(&self, index: u8) -> u8 {
+        self.attributes[index as usize]
+    }
+
+    pub fn poke_oam(&mut self, index: u8, val: u8) {
+        self.attributes[index as usize] = val;
+    }
+
     pub fn read_data_reg(&mut self) -> u8 {
         let addr = self.regs.v;
 
@@ -516,6 +880,7 @@ impl PPU {
             0x0000..=0x1fff => {
                 let res = self.data_buffer;
                 self.data_buffer = self.read_chr(addr);
+                self.rom.log_chr(addr, ChrFlags::READ);
                 res
             }
             0x2000..=0x3eff => {
@@ -567,20 +932,11 @@ impl PPU {
         self.regs.oam_addr = self.regs.oam_addr.wrapping_add(1);
     }
 
-    pub fn write_oam_dma_reg(&mut self, page: [u8; 256]) {
-        if self.regs.oam_addr == 0 {
-            self.attributes.copy_from_slice(&page);
-        } else {
-            for byte in page.iter() {
-                self.write_oam_data_reg(*byte);
-            }
-        }
-    }
-
     pub fn read_register(&mut self, addr: u16) -> u8 {
         match addr {
             0x2002 => {
                 let res = self.regs.read_status(self.open_bus);
+                self.vblank_race();
                 self.detect_nmi_edge();
                 res
             }
@@ -590,6 +946,19 @@ impl PPU {
         }
     }
 
+    /// value returned by a CPU read of the register, without side effects
+    pub fn peek_register(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x2002 => self.regs.peek_status(self.open_bus),
+            0x2004 => self.attributes[self.regs.oam_addr as usize],
+            0x2007 => match self.get_vram_addr() {
+                addr @ 0x3f00..=0x3fff => self.peek_data(addr),
+                _ => self.data_buffer,
+            },
+            _ => 0,
+        }
+    }
+
     pub fn write_register(&mut self, addr: u16, data: u8) {
         // https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
         self.open_bus = data;
@@ -610,6 +979,32 @@ impl PPU {
     pub fn get_frame(&self) -> &[u8] {
         self.frame_buffer_complete.as_slice()
     }
+
+    /// records an event at the current scanline and dot
+    pub(crate) fn log_event(&mut self, kind: EventKind, addr: u16, value: u8) {
+        if let Some(event_log) = &mut self.event_log {
+            event_log.push(Event {
+                kind,
+                scanline: self.scanline,
+                dot: self.cycle,
+                addr,
+                value,
+            });
+        }
+    }
+}
+
+// palette RAM is mirrored every 32 bytes from $3F00 to $3FFF
+// and $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+// https://www.nesdev.org/wiki/PPU_palettes#Memory_Map
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+
+    if index & 0x13 == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
 }
 
 impl savestate::Save for SpriteData {
@@ -647,6 +1042,7 @@ impl savestate::Save for PPU {
         s.data.write_bool(self.nmi_triggered);
         s.data.write_bool(self.nmi_edge_detector);
         s.data.write_bool(self.should_trigger_nmi);
+        s.data.write_bool(self.vblank_suppressed);
         s.data.write_bool(self.frame_complete);
         s.data.write_u64(self.tile_data);
         s.data.write_u8(self.nametable_byte);
@@ -674,6 +1070,7 @@ impl savestate::Save for PPU {
         self.nmi_triggered = s.data.read_bool()?;
         self.nmi_edge_detector = s.data.read_bool()?;
         self.should_trigger_nmi = s.data.read_bool()?;
+        self.vblank_suppressed = s.data.read_bool()?;
         self.frame_complete = s.data.read_bool()?;
         self.tile_data = s.data.read_u64()?;
         self.nametable_byte = s.data.read_u8()?;
@@ -685,7 +1082,87 @@ impl savestate::Save for PPU {
 
         self.regs.load(s)?;
         self.rom.mapper.load(s)?;
+        self.sync_rendered_dot();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        savestate::{Save, Section},
+        tests::{nestest, nrom},
+    };
+
+    #[test]
+    fn soft_reset_clears_the_write_toggle() {
+        let mut ppu = PPU::new(nestest());
+
+        ppu.write_register(0x2006, 0x21);
+        assert!(ppu.regs.w);
+
+        ppu.soft_reset();
+        assert!(!ppu.regs.w);
+
+        // the next write is the high byte again
+        ppu.write_register(0x2006, 0x23);
+        ppu.write_register(0x2006, 0x45);
+        assert_eq!(ppu.regs.v, 0x2345);
+    }
+
+    fn ppu() -> PPU {
+        let mut ppu = PPU::new(nrom());
+        ppu.write_register(0x2000, 0x80);
+        ppu
+    }
+
+    fn run_until(ppu: &mut PPU, scanline: u16, dot: u16) {
+        while ppu.scanline != scanline || ppu.cycle != dot {
+            ppu.run(1);
+        }
+    }
+
+    // vblank flag read at the given dot of the first vblank scanline,
+    // then the state of the flag and of the NMI a few dots later
+    fn read_status_at(dot: u16) -> (bool, bool, bool) {
+        let mut ppu = ppu();
+        run_until(&mut ppu, 241, dot);
+
+        let flag = ppu.read_register(0x2002) & 0x80 != 0;
+        ppu.run(10);
+
+        let flag_after = ppu.peek_register(0x2002) & 0x80 != 0;
+        (flag, flag_after, ppu.is_asserting_nmi())
+    }
+
+    // https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+    #[test]
+    fn vblank_race() {
+        // one dot early: the flag reads clear and is never set, no NMI
+        assert_eq!(read_status_at(0), (false, false, false));
+        // on the dot the flag is set or one dot later: the flag reads set but the NMI is lost
+        assert_eq!(read_status_at(1), (true, false, false));
+        assert_eq!(read_status_at(2), (true, false, false));
+        // later reads clear the flag after the NMI
+        assert_eq!(read_status_at(3), (true, false, true));
+    }
+
+    #[test]
+    fn vblank_suppression_is_saved() {
+        let mut ppu = ppu();
+        run_until(&mut ppu, 241, 0);
+        ppu.read_register(0x2002);
+
+        let mut root = Section::new("root");
+        ppu.save(&mut root);
+
+        let mut loaded = self::ppu();
+        loaded.load(&mut root).unwrap();
+        loaded.run(10);
+
+        assert_eq!(loaded.peek_register(0x2002) & 0x80, 0);
+        assert!(!loaded.is_asserting_nmi());
+    }
+}
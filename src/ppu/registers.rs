@@ -213,11 +213,15 @@ bitflags! {
 
 impl Registers {
     pub fn read_status(&mut self, open_bus: u8) -> u8 {
-        let res = (self.status.bits() & 0b1110_0000) | (open_bus & 0b0001_1111);
+        let res = self.peek_status(open_bus);
         self.status.remove(Status::VBLANK_STARTED);
         self.w = false;
         res
     }
+
+    pub fn peek_status(&self, open_bus: u8) -> u8 {
+        (self.status.bits() & 0b1110_0000) | (open_bus & 0b0001_1111)
+    }
 }
 
 impl Registers {
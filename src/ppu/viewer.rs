@@ -0,0 +1,426 @@
+// PPU viewers, render the nametables, pattern tables, OAM and palette RAM into RGB buffers
+// for debugging tools, the PPU registers and memory are only read
+// https://www.nesdev.org/wiki/PPU_nametables
+// https://www.nesdev.org/wiki/PPU_pattern_tables
+// https://www.nesdev.org/wiki/PPU_OAM
+
+use super::{registers::SpriteSize, COLOR_PALETTE, PPU};
+
+pub const NAMETABLES_WIDTH: usize = 512; // px
+pub const NAMETABLES_HEIGHT: usize = 480; // px
+pub const PATTERN_TABLES_WIDTH: usize = 256; // px
+pub const PATTERN_TABLES_HEIGHT: usize = 128; // px
+
+// 8 columns of 8x16 cells, 8x8 sprites use the top half of their cell
+pub const OAM_WIDTH: usize = 64; // px
+pub const OAM_HEIGHT: usize = 128; // px
+pub const PALETTE_SIZE: usize = 32;
+
+const SCROLL_RECT_COLOR: (u8, u8, u8) = (0xFF, 0x00, 0xFF);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub index: u8,
+    pub x: u8,
+    /// the sprite is drawn one scanline below this value
+    pub y: u8,
+    pub tile: u8,
+    /// sprite palette (0-3), palettes 4-7 in `pattern_tables`
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontally: bool,
+    pub flip_vertically: bool,
+    /// address of the top tile in the PPU address space
+    pub pattern_addr: u16,
+    pub height: u8,
+}
+
+/// the four nametables laid out as a 2x2 grid (512x480),
+/// the 256x240 area shown on screen is outlined when `scroll_rect` is set
+pub fn nametables(ppu: &mut PPU, scroll_rect: bool) -> Vec<u8> {
+    let mut buffer = vec![0u8; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 3];
+    let chr_bank = ppu.regs.ctrl.background_chr_offset();
+    let palette = palette_colors(ppu);
+    let mut tile = [0u8; 16];
+
+    for nametable in 0..4u16 {
+        let base = 0x2000 + nametable * 0x400;
+        let origin_x = (nametable as usize & 1) * 256;
+        let origin_y = (nametable as usize >> 1) * 240;
+
+        for row in 0..30u16 {
+            for col in 0..32u16 {
+                let tile_idx = ppu.read_nametable(base + row * 32 + col);
+                let attr_addr = base + 0x3C0 + (row / 4) * 8 + col / 4;
+                let shift = ((row & 2) << 1) | (col & 2);
+                let attr = (ppu.read_nametable(attr_addr) >> shift) & 0b11;
+
+                read_tile(ppu, chr_bank, tile_idx, &mut tile);
+
+                draw_tile(
+                    &mut buffer,
+                    NAMETABLES_WIDTH,
+                    origin_x + col as usize * 8,
+                    origin_y + row as usize * 8,
+                    &tile,
+                    |pattern| palette[background_color_index(attr, pattern)],
+                );
+            }
+        }
+    }
+
+    if scroll_rect {
+        draw_scroll_rect(&mut buffer, scroll(ppu));
+    }
+
+    buffer
+}
+
+/// the scroll position the next frame starts rendering from,
+/// in the 512x480 space of `nametables`
+pub fn scroll(ppu: &PPU) -> (usize, usize) {
+    // coarse Y values of 30 and 31 start in the attribute table,
+    // they are wrapped to stay in the nametables
+    let t = ppu.regs.t as usize;
+    let coarse_x = t & 0x1F;
+    let coarse_y = (t >> 5) & 0x1F;
+    let fine_y = (t >> 12) & 0b111;
+    let x = ((t >> 10) & 1) * 256 + coarse_x * 8 + ppu.regs.x as usize;
+    let y = ((t >> 11) & 1) * 240 + coarse_y * 8 + fine_y;
+
+    (x, y % NAMETABLES_HEIGHT)
+}
+
+/// both pattern tables side by side (256x128) drawn with one of the 8 palettes,
+/// 0-3 are the background palettes and 4-7 the sprite palettes
+pub fn pattern_tables(ppu: &mut PPU, palette_idx: u8) -> Vec<u8> {
+    let mut buffer = vec![0u8; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 3];
+    let palette = palette_colors(ppu);
+    let palette_idx = palette_idx & 0b111;
+    let mut tile = [0u8; 16];
+
+    for table in 0..2u16 {
+        for nth in 0..256u16 {
+            read_tile(ppu, table * 0x1000, nth as u8, &mut tile);
+
+            draw_tile(
+                &mut buffer,
+                PATTERN_TABLES_WIDTH,
+                table as usize * 128 + (nth as usize % 16) * 8,
+                (nth as usize / 16) * 8,
+                &tile,
+                |pattern| palette[background_color_index(palette_idx, pattern)],
+            );
+        }
+    }
+
+    buffer
+}
+
+/// decoded attributes of the 64 OAM entries
+pub fn oam_sprites(ppu: &PPU) -> Vec<Sprite> {
+    let sprite_size = ppu.regs.ctrl.sprite_size();
+
+    ppu.attributes
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, entry)| {
+            let (tile, attr) = (entry[1], entry[2]);
+
+            let pattern_addr = match sprite_size {
+                SpriteSize::Sprite8x8 => ppu.regs.ctrl.sprite_chr_offset() + tile as u16 * 16,
+                SpriteSize::Sprite8x16 => (tile as u16 & 1) * 0x1000 + (tile as u16 & 0xFE) * 16,
+            };
+
+            Sprite {
+                index: index as u8,
+                x: entry[3],
+                y: entry[0],
+                tile,
+                palette: attr & 0b11,
+                behind_background: attr & 0b0010_0000 != 0,
+                flip_horizontally: attr & 0b0100_0000 != 0,
+                flip_vertically: attr & 0b1000_0000 != 0,
+                pattern_addr,
+                height: sprite_size.height(),
+            }
+        })
+        .collect()
+}
+
+/// the 64 sprites in OAM order on an 8x8 grid of 8x16 cells (64x128),
+/// flips are applied and transparent pixels use the backdrop color
+pub fn oam(ppu: &mut PPU) -> Vec<u8> {
+    let mut buffer = vec![0u8; OAM_WIDTH * OAM_HEIGHT * 3];
+    let palette = palette_colors(ppu);
+    let mut top = [0u8; 16];
+    let mut bottom = [0u8; 16];
+
+    for sprite in oam_sprites(ppu) {
+        let cell_x = (sprite.index as usize % 8) * 8;
+        let cell_y = (sprite.index as usize / 8) * 16;
+        let chr_bank = sprite.pattern_addr & 0x1000;
+        let tile_idx = ((sprite.pattern_addr & 0xFFF) / 16) as u8;
+
+        read_tile(ppu, chr_bank, tile_idx, &mut top);
+
+        let mut tiles = vec![top];
+
+        if sprite.height == 16 {
+            read_tile(ppu, chr_bank, tile_idx.wrapping_add(1), &mut bottom);
+            tiles.push(bottom);
+        }
+
+        if sprite.flip_vertically {
+            tiles.reverse();
+        }
+
+        for (i, tile) in tiles.iter().enumerate() {
+            let mut tile = *tile;
+
+            if sprite.flip_vertically {
+                tile[..8].reverse();
+                tile[8..].reverse();
+            }
+
+            if sprite.flip_horizontally {
+                for byte in tile.iter_mut() {
+                    *byte = byte.reverse_bits();
+                }
+            }
+
+            draw_tile(
+                &mut buffer,
+                OAM_WIDTH,
+                cell_x,
+                cell_y + i * 8,
+                &tile,
+                |pattern| palette[background_color_index(4 + sprite.palette, pattern)],
+            );
+        }
+    }
+
+    buffer
+}
+
+/// the 32 entries of the palette RAM as RGB triples, $3F10/$3F14/$3F18/$3F1C mirror the
+/// background entries
+pub fn palette(ppu: &PPU) -> Vec<u8> {
+    palette_colors(ppu)
+        .iter()
+        .flat_map(|&(r, g, b)| [r, g, b])
+        .collect()
+}
+
+fn palette_colors(ppu: &PPU) -> [(u8, u8, u8); PALETTE_SIZE] {
+    let mut colors = [(0, 0, 0); PALETTE_SIZE];
+
+    for (i, color) in colors.iter_mut().enumerate() {
+        // entries 0, 4, 8 and 12 of the sprite palettes are mirrors of the background ones
+        let idx = if i >= 16 && i % 4 == 0 { i - 16 } else { i };
+        *color = COLOR_PALETTE[(ppu.palette[idx] & 63) as usize];
+    }
+
+    colors
+}
+
+// pattern 0 always selects the universal background color
+fn background_color_index(palette_idx: u8, pattern: u8) -> usize {
+    if pattern == 0 {
+        0
+    } else {
+        palette_idx as usize * 4 + pattern as usize
+    }
+}
+
+fn read_tile(ppu: &mut PPU, chr_bank: u16, nth: u8, tile: &mut [u8; 16]) {
+    let rom = &mut ppu.rom;
+    rom.mapper
+        .get_tile(&mut rom.cart, chr_bank, nth as usize, tile);
+}
+
+fn draw_tile(
+    buffer: &mut [u8],
+    width: usize,
+    x: usize,
+    y: usize,
+    tile: &[u8; 16],
+    color: impl Fn(u8) -> (u8, u8, u8),
+) {
+    for row in 0..8 {
+        let low = tile[row];
+        let high = tile[row + 8];
+
+        for col in 0..8 {
+            let p1 = (low >> (7 - col)) & 1;
+            let p2 = (high >> (7 - col)) & 1;
+            let (r, g, b) = color((p2 << 1) | p1);
+            let offset = ((y + row) * width + x + col) * 3;
+            buffer[offset..offset + 3].copy_from_slice(&[r, g, b]);
+        }
+    }
+}
+
+// the visible area wraps around the edges of the nametables
+fn draw_scroll_rect(buffer: &mut [u8], (scroll_x, scroll_y): (usize, usize)) {
+    let mut set_pixel = |x: usize, y: usize| {
+        let x = (scroll_x + x) % NAMETABLES_WIDTH;
+        let y = (scroll_y + y) % NAMETABLES_HEIGHT;
+        let offset = (y * NAMETABLES_WIDTH + x) * 3;
+        let (r, g, b) = SCROLL_RECT_COLOR;
+        buffer[offset..offset + 3].copy_from_slice(&[r, g, b]);
+    };
+
+    for x in 0..256 {
+        set_pixel(x, 0);
+        set_pixel(x, 239);
+    }
+
+    for y in 0..240 {
+        set_pixel(0, y);
+        set_pixel(255, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::nestest;
+
+    fn ppu() -> PPU {
+        PPU::new(nestest())
+    }
+
+    fn write_oam(ppu: &mut PPU, entries: &[[u8; 4]]) {
+        ppu.write_register(0x2003, 0);
+
+        for byte in entries.iter().flatten() {
+            ppu.write_register(0x2004, *byte);
+        }
+    }
+
+    fn set_scroll(ppu: &mut PPU, nametable: u8, x: u8, y: u8) {
+        ppu.write_register(0x2000, nametable);
+        ppu.write_register(0x2005, x);
+        ppu.write_register(0x2005, y);
+    }
+
+    #[test]
+    fn sprite_attributes() {
+        let mut ppu = ppu();
+        // 8x8 sprites in the second pattern table
+        ppu.write_register(0x2000, 0b0000_1000);
+        write_oam(
+            &mut ppu,
+            &[
+                [0x10, 0x21, 0b1110_0010, 0x30],
+                [0xEF, 0x05, 0b0000_0001, 0xF8],
+            ],
+        );
+
+        let sprites = oam_sprites(&ppu);
+        assert_eq!(sprites.len(), 64);
+
+        assert_eq!(
+            sprites[0],
+            Sprite {
+                index: 0,
+                x: 0x30,
+                y: 0x10,
+                tile: 0x21,
+                palette: 2,
+                behind_background: true,
+                flip_horizontally: true,
+                flip_vertically: true,
+                pattern_addr: 0x1210,
+                height: 8,
+            }
+        );
+
+        let sprite = sprites[1];
+        assert_eq!((sprite.index, sprite.x, sprite.y), (1, 0xF8, 0xEF));
+        assert_eq!(sprite.palette, 1);
+        assert!(!sprite.behind_background);
+        assert!(!sprite.flip_horizontally && !sprite.flip_vertically);
+        assert_eq!(sprite.pattern_addr, 0x1050);
+    }
+
+    #[test]
+    fn pattern_addr_of_8x16_sprites() {
+        let mut ppu = ppu();
+        // the pattern table of 8x16 sprites is selected by their tile index,
+        // not by PPUCTRL
+        ppu.write_register(0x2000, 0b0010_1000);
+        write_oam(
+            &mut ppu,
+            &[[0, 0x20, 0, 0], [0, 0x21, 0, 0], [0, 0xFF, 0, 0]],
+        );
+
+        let sprites = oam_sprites(&ppu);
+        let addrs = sprites[..3]
+            .iter()
+            .map(|sprite| sprite.pattern_addr)
+            .collect::<Vec<_>>();
+
+        assert_eq!(addrs, [0x0200, 0x1200, 0x1FE0]);
+        assert!(sprites.iter().all(|sprite| sprite.height == 16));
+    }
+
+    #[test]
+    fn sprite_backdrop_entries_mirror_the_background() {
+        let mut ppu = ppu();
+        // left over by the power on fill, never used by the PPU
+        ppu.palette[0x10] = 0x30;
+
+        ppu.poke_data(0x3F00, 0x16);
+        ppu.poke_data(0x3F14, 0x2A);
+        ppu.poke_data(0x3F11, 0x05);
+
+        let colors = palette(&ppu)
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(colors.len(), PALETTE_SIZE);
+        assert_eq!(colors[0x00], COLOR_PALETTE[0x16]);
+        assert_eq!(colors[0x10], COLOR_PALETTE[0x16]);
+        assert_eq!(colors[0x04], COLOR_PALETTE[0x2A]);
+        assert_eq!(colors[0x14], COLOR_PALETTE[0x2A]);
+        assert_eq!(colors[0x11], COLOR_PALETTE[0x05]);
+
+        for i in [0x08, 0x0C] {
+            assert_eq!(colors[i + 0x10], colors[i]);
+        }
+    }
+
+    #[test]
+    fn scroll_position() {
+        let mut ppu = ppu();
+
+        set_scroll(&mut ppu, 0, 0, 0);
+        assert_eq!(scroll(&ppu), (0, 0));
+
+        // coarse scroll 1, fine scroll 2
+        set_scroll(&mut ppu, 0, 10, 10);
+        assert_eq!(scroll(&ppu), (10, 10));
+
+        // the nametable bits add a whole nametable
+        set_scroll(&mut ppu, 0b11, 0xFF, 0xEF);
+        assert_eq!(scroll(&ppu), (511, 479));
+
+        set_scroll(&mut ppu, 0b01, 0x08, 0x00);
+        assert_eq!(scroll(&ppu), (264, 0));
+    }
+
+    #[test]
+    fn scroll_in_the_attribute_table_wraps() {
+        let mut ppu = ppu();
+
+        // coarse Y 30, fine Y 5
+        set_scroll(&mut ppu, 0b10, 0, 0xF5);
+        assert_eq!(scroll(&ppu), (0, 5));
+
+        set_scroll(&mut ppu, 0b00, 0, 0xF5);
+        assert_eq!(scroll(&ppu), (0, 245));
+    }
+}
@@ -0,0 +1,337 @@
+// RAM search, narrows down the addresses holding a game variable
+// by comparing successive snapshots of the internal RAM and the cartridge PRG RAM
+
+const RAM_SIZE: usize = 0x800;
+const PRG_RAM_START: u16 = 0x6000;
+
+/// copy of the 2KB internal RAM ($0000-$07FF) and of the PRG RAM ($6000-$7FFF)
+#[derive(Clone)]
+pub struct RamSnapshot {
+    ram: Vec<u8>,
+    prg_ram: Vec<u8>,
+}
+
+impl RamSnapshot {
+    pub fn new(ram: &[u8], prg_ram: &[u8]) -> Self {
+        RamSnapshot {
+            ram: ram.to_vec(),
+            prg_ram: prg_ram.to_vec(),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if (addr as usize) < RAM_SIZE {
+            self.ram.get(addr as usize).copied()
+        } else {
+            let offset = addr.checked_sub(PRG_RAM_START)? as usize;
+            self.prg_ram.get(offset).copied()
+        }
+    }
+
+    /// every searchable address
+    pub fn addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        let ram = (0..self.ram.len()).map(|offset| offset as u16);
+        let prg_ram = (0..self.prg_ram.len()).map(|offset| PRG_RAM_START + offset as u16);
+        ram.chain(prg_ram)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSize {
+    Byte,
+    /// little endian
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchView {
+    pub size: ValueSize,
+    pub signed: bool,
+}
+
+impl Default for SearchView {
+    fn default() -> Self {
+        SearchView {
+            size: ValueSize::Byte,
+            signed: false,
+        }
+    }
+}
+
+impl SearchView {
+    pub fn read(&self, snapshot: &RamSnapshot, addr: u16) -> Option<i32> {
+        let lo = snapshot.read(addr)?;
+
+        let val = match (self.size, self.signed) {
+            (ValueSize::Byte, false) => lo as i32,
+            (ValueSize::Byte, true) => lo as i8 as i32,
+            (ValueSize::Word, signed) => {
+                let hi = snapshot.read(addr.checked_add(1)?)?;
+                let word = u16::from_le_bytes([lo, hi]);
+
+                if signed {
+                    word as i16 as i32
+                } else {
+                    word as i32
+                }
+            }
+        };
+
+        Some(val)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn holds(&self, lhs: i32, rhs: i32) -> bool {
+        match self {
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+        }
+    }
+}
+
+/// right hand side of a comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// the value of the address in the previous snapshot
+    Previous,
+    Constant(i32),
+}
+
+pub struct RamSearch {
+    view: SearchView,
+    previous: RamSnapshot,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    /// starts a search where every address is a candidate
+    pub fn new(snapshot: RamSnapshot, view: SearchView) -> Self {
+        let candidates = snapshot
+            .addresses()
+            .filter(|&addr| view.read(&snapshot, addr).is_some())
+            .collect();
+
+        RamSearch {
+            view,
+            previous: snapshot,
+            candidates,
+        }
+    }
+
+    /// keeps the candidates whose current value satisfies `comparison` with `operand`
+    pub fn filter(
+        &mut self,
+        snapshot: RamSnapshot,
+        comparison: Comparison,
+        operand: Operand,
+    ) -> &[u16] {
+        let view = self.view;
+        let previous = &self.previous;
+
+        self.candidates.retain(|&addr| {
+            let current = view.read(&snapshot, addr);
+            let rhs = match operand {
+                Operand::Previous => view.read(previous, addr),
+                Operand::Constant(val) => Some(val),
+            };
+
+            match (current, rhs) {
+                (Some(lhs), Some(rhs)) => comparison.holds(lhs, rhs),
+                _ => false,
+            }
+        });
+
+        self.previous = snapshot;
+        &self.candidates
+    }
+
+    pub fn get_candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// value of a candidate in the last snapshot
+    pub fn get_value(&self, addr: u16) -> Option<i32> {
+        self.view.read(&self.previous, addr)
+    }
+
+    pub fn get_view(&self) -> SearchView {
+        self.view
+    }
+
+    /// changes how values are interpreted, the candidates are kept
+    pub fn set_view(&mut self, view: SearchView) {
+        self.view = view;
+        let previous = &self.previous;
+        self.candidates
+            .retain(|&addr| view.read(previous, addr).is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRG_RAM_SIZE: usize = 0x2000;
+
+    fn snapshot(writes: &[(u16, u8)]) -> RamSnapshot {
+        let mut ram = vec![0; RAM_SIZE];
+        let mut prg_ram = vec![0; PRG_RAM_SIZE];
+
+        for &(addr, val) in writes {
+            match addr {
+                0x0000..=0x07FF => ram[addr as usize] = val,
+                _ => prg_ram[(addr - PRG_RAM_START) as usize] = val,
+            }
+        }
+
+        RamSnapshot::new(&ram, &prg_ram)
+    }
+
+    fn view(size: ValueSize, signed: bool) -> SearchView {
+        SearchView { size, signed }
+    }
+
+    #[test]
+    fn filter_with_the_previous_value() {
+        let mut search = RamSearch::new(snapshot(&[]), SearchView::default());
+        assert_eq!(search.get_candidates().len(), RAM_SIZE + PRG_RAM_SIZE);
+
+        let candidates = search.filter(
+            snapshot(&[(0x0010, 5), (0x0011, 1), (0x6000, 3)]),
+            Comparison::Greater,
+            Operand::Previous,
+        );
+        assert_eq!(candidates, [0x0010, 0x0011, 0x6000]);
+
+        // compared with the snapshot of the previous filter, not the first one
+        let candidates = search.filter(
+            snapshot(&[(0x0010, 4), (0x0011, 1), (0x6000, 7)]),
+            Comparison::Less,
+            Operand::Previous,
+        );
+        assert_eq!(candidates, [0x0010]);
+        assert_eq!(search.get_value(0x0010), Some(4));
+
+        let candidates = search.filter(
+            snapshot(&[(0x0010, 4)]),
+            Comparison::Equal,
+            Operand::Previous,
+        );
+        assert_eq!(candidates, [0x0010]);
+    }
+
+    #[test]
+    fn filter_with_a_constant() {
+        let current = snapshot(&[(0x0001, 9), (0x0002, 10), (0x0003, 11), (0x7FFF, 10)]);
+
+        let mut search = RamSearch::new(snapshot(&[]), SearchView::default());
+        let candidates = search.filter(current.clone(), Comparison::Equal, Operand::Constant(10));
+        assert_eq!(candidates, [0x0002, 0x7FFF]);
+
+        let mut search = RamSearch::new(snapshot(&[]), SearchView::default());
+        let candidates = search.filter(
+            current.clone(),
+            Comparison::GreaterOrEqual,
+            Operand::Constant(10),
+        );
+        assert_eq!(candidates, [0x0002, 0x0003, 0x7FFF]);
+
+        let mut search = RamSearch::new(snapshot(&[]), SearchView::default());
+        let candidates = search.filter(current, Comparison::NotEqual, Operand::Constant(0));
+        assert_eq!(candidates, [0x0001, 0x0002, 0x0003, 0x7FFF]);
+    }
+
+    #[test]
+    fn signed_bytes() {
+        let snapshot = snapshot(&[(0x0020, 0xFF), (0x0021, 0x80), (0x0022, 0x7F)]);
+
+        assert_eq!(
+            view(ValueSize::Byte, false).read(&snapshot, 0x0020),
+            Some(255)
+        );
+        assert_eq!(
+            view(ValueSize::Byte, true).read(&snapshot, 0x0020),
+            Some(-1)
+        );
+        assert_eq!(
+            view(ValueSize::Byte, true).read(&snapshot, 0x0021),
+            Some(-128)
+        );
+        assert_eq!(
+            view(ValueSize::Byte, true).read(&snapshot, 0x0022),
+            Some(127)
+        );
+
+        let mut search = RamSearch::new(snapshot.clone(), view(ValueSize::Byte, true));
+        let candidates = search.filter(snapshot, Comparison::Less, Operand::Constant(0));
+        assert_eq!(candidates, [0x0020, 0x0021]);
+    }
+
+    #[test]
+    fn words() {
+        let snapshot = snapshot(&[
+            (0x0030, 0x34),
+            (0x0031, 0x12),
+            (0x0040, 0x00),
+            (0x0041, 0x80),
+            (0x07FF, 0x01),
+            (0x7FFE, 0xFE),
+            (0x7FFF, 0xFF),
+        ]);
+
+        let unsigned = view(ValueSize::Word, false);
+        let signed = view(ValueSize::Word, true);
+
+        assert_eq!(unsigned.read(&snapshot, 0x0030), Some(0x1234));
+        assert_eq!(unsigned.read(&snapshot, 0x0040), Some(0x8000));
+        assert_eq!(signed.read(&snapshot, 0x0040), Some(-32768));
+        assert_eq!(signed.read(&snapshot, 0x7FFE), Some(-2));
+
+        // the high byte would be outside of the RAM or of the PRG RAM
+        assert_eq!(unsigned.read(&snapshot, 0x07FF), None);
+        assert_eq!(unsigned.read(&snapshot, 0x7FFF), None);
+
+        let search = RamSearch::new(snapshot, unsigned);
+        let candidates = search.get_candidates();
+        assert_eq!(candidates.len(), RAM_SIZE - 1 + PRG_RAM_SIZE - 1);
+        assert!(!candidates.contains(&0x07FF) && !candidates.contains(&0x7FFF));
+    }
+
+    #[test]
+    fn changing_the_view_drops_unreadable_candidates() {
+        let mut search = RamSearch::new(snapshot(&[]), SearchView::default());
+        assert!(search.get_candidates().contains(&0x07FF));
+
+        search.set_view(view(ValueSize::Word, true));
+        assert_eq!(
+            search.get_candidates().len(),
+            RAM_SIZE - 1 + PRG_RAM_SIZE - 1
+        );
+        assert!(!search.get_candidates().contains(&0x07FF));
+    }
+
+    #[test]
+    fn cartridges_without_prg_ram() {
+        let snapshot = RamSnapshot::new(&[0; RAM_SIZE], &[]);
+
+        assert_eq!(snapshot.read(0x07FF), Some(0));
+        assert_eq!(snapshot.read(0x0800), None);
+        assert_eq!(snapshot.read(PRG_RAM_START), None);
+        assert_eq!(snapshot.addresses().count(), RAM_SIZE);
+    }
+}
@@ -0,0 +1,128 @@
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    /// in Hz
+    pub fn cpu_frequency(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1789772.5,
+            Region::Pal => 1662607.0,
+            Region::Dendy => 1773447.5,
+        }
+    }
+
+    /// number of PPU dots per CPU cycle, as a (numerator, denominator) pair
+    pub fn ppu_clock_ratio(&self) -> (u32, u32) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5), // 3.2
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    pub fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame() - 1
+    }
+
+    /// scanline at which the vblank flag is set and the NMI is triggered
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            // Dendy has 51 post-render scanlines, the vblank period stays 20 scanlines long
+            Region::Dendy => 291,
+        }
+    }
+
+    /// the dot skipped on odd frames only exists on NTSC consoles
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        matches!(self, Region::Ntsc)
+    }
+
+    /// in frames per second
+    pub fn frame_rate(&self) -> f64 {
+        let (num, den) = self.ppu_clock_ratio();
+        let dots_per_frame = self.scanlines_per_frame() as f64 * 341.0;
+        self.cpu_frequency() * num as f64 / den as f64 / dots_per_frame
+    }
+}
+
+impl From<Region> for u8 {
+    fn from(region: Region) -> Self {
+        match region {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for Region {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(Region::Ntsc),
+            1 => Ok(Region::Pal),
+            2 => Ok(Region::Dendy),
+            _ => Err(val),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Region;
+
+    const REGIONS: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+    #[test]
+    fn timings() {
+        let timings = REGIONS.map(|region| {
+            (
+                region.ppu_clock_ratio(),
+                region.scanlines_per_frame(),
+                region.vblank_scanline(),
+                region.skips_odd_frame_dot(),
+            )
+        });
+
+        assert_eq!(
+            timings,
+            [
+                ((3, 1), 262, 241, true),
+                ((16, 5), 312, 241, false),
+                ((3, 1), 312, 291, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_rates() {
+        let rates = REGIONS.map(|region| region.frame_rate());
+
+        // the dot skipped on odd frames is ignored
+        assert!((rates[0] - 60.0985).abs() < 1e-4, "{}", rates[0]);
+        assert!((rates[1] - 50.0070).abs() < 1e-4, "{}", rates[1]);
+        assert!((rates[2] - 50.0070).abs() < 1e-4, "{}", rates[2]);
+    }
+
+    #[test]
+    fn u8_round_trip() {
+        for region in REGIONS {
+            assert_eq!(Region::try_from(u8::from(region)), Ok(region));
+        }
+
+        assert_eq!(Region::try_from(3), Err(3));
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+
+const DEFAULT_INTERVAL: u32 = 2; // frames
+const DEFAULT_MEMORY_BUDGET: usize = 16 * 1024 * 1024; // bytes
+
+const DELTA_RAW: u8 = 0;
+const DELTA_XOR_RLE: u8 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RewindConfig {
+    /// number of frames between two snapshots
+    pub interval: u32,
+    /// maximum number of bytes used by the stored snapshots,
+    /// the oldest snapshots are dropped first
+    pub memory_budget: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            interval: DEFAULT_INTERVAL,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+        }
+    }
+}
+
+// Ring buffer of encoded save states.
+// Only the newest snapshot is kept in full, every older snapshot is stored
+// as a delta against the next (newer) one, so that evicting the oldest
+// snapshot never invalidates the others.
+pub struct Rewind {
+    config: RewindConfig,
+    frames_since_snapshot: u32,
+    newest: Vec<u8>,
+    // the newest snapshot is the state of the console, restoring it wouldn't change anything
+    newest_is_current: bool,
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Self {
+        Rewind {
+            config,
+            frames_since_snapshot: 0,
+            newest: Vec::new(),
+            newest_is_current: false,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    /// returns true when a snapshot should be taken for the frame that just completed
+    pub fn on_frame_complete(&mut self) -> bool {
+        self.frames_since_snapshot += 1;
+        self.newest_is_current = false;
+
+        if self.frames_since_snapshot >= self.config.interval.max(1) {
+            self.frames_since_snapshot = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn push(&mut self, snapshot: &[u8]) {
+        if !self.newest.is_empty() {
+            let mut delta = Vec::new();
+            encode_delta(snapshot, &self.newest, &mut delta);
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+
+        self.newest.clear();
+        self.newest.extend_from_slice(snapshot);
+        self.newest_is_current = true;
+        self.evict();
+    }
+
+    /// the console was restored from another state
+    pub fn on_state_loaded(&mut self) {
+        self.newest_is_current = false;
+    }
+
+    /// moves the newest snapshot into `dst`, the previous one becomes the newest,
+    /// a snapshot of the current state is skipped
+    pub fn pop(&mut self, dst: &mut Vec<u8>) -> bool {
+        if self.newest_is_current {
+            if self.deltas.is_empty() {
+                return false;
+            }
+
+            self.drop_newest();
+        }
+
+        if self.newest.is_empty() {
+            return false;
+        }
+
+        dst.clear();
+        dst.extend_from_slice(&self.newest);
+        self.drop_newest();
+        self.frames_since_snapshot = 0;
+
+        true
+    }
+
+    // the previous snapshot becomes the newest
+    fn drop_newest(&mut self) {
+        match self.deltas.pop_back() {
+            Some(delta) => {
+                self.deltas_size -= delta.len();
+                apply_delta(&delta, &mut self.newest);
+            }
+            None => self.newest.clear(),
+        }
+
+        self.newest_is_current = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.newest.clear();
+        self.newest_is_current = false;
+        self.deltas.clear();
+        self.deltas_size = 0;
+        self.frames_since_snapshot = 0;
+    }
+
+    /// number of snapshots available
+    pub fn len(&self) -> usize {
+        if self.newest.is_empty() {
+            0
+        } else {
+            self.deltas.len() + 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_empty()
+    }
+
+    /// in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.newest.len() + self.deltas_size
+    }
+
+    fn evict(&mut self) {
+        while self.memory_usage() > self.config.memory_budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+// Deltas are the XOR of two consecutive snapshots (which is mostly zeros)
+// encoded as a sequence of (zero run length, literal length, literal bytes)
+
+fn encode_delta(from: &[u8], to: &[u8], delta: &mut Vec<u8>) {
+    delta.clear();
+
+    if from.len() != to.len() {
+        delta.push(DELTA_RAW);
+        delta.extend_from_slice(to);
+        return;
+    }
+
+    delta.push(DELTA_XOR_RLE);
+    let mut i = 0;
+
+    while i < from.len() {
+        let zeros_start = i;
+        while i < from.len() && from[i] == to[i] {
+            i += 1;
+        }
+
+        let literals_start = i;
+        while i < from.len() && from[i] != to[i] {
+            i += 1;
+        }
+
+        write_varint(delta, literals_start - zeros_start);
+        write_varint(delta, i - literals_start);
+        delta.extend(
+            from[literals_start..i]
+                .iter()
+                .zip(&to[literals_start..i])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+}
+
+fn apply_delta(delta: &[u8], data: &mut Vec<u8>) {
+    match delta[0] {
+        DELTA_RAW => {
+            data.clear();
+            data.extend_from_slice(&delta[1..]);
+        }
+        _ => {
+            let mut offset = 1;
+            let mut i = 0;
+
+            while offset < delta.len() {
+                i += read_varint(delta, &mut offset);
+                let literals_len = read_varint(delta, &mut offset);
+
+                for byte in &delta[offset..offset + literals_len] {
+                    data[i] ^= byte;
+                    i += 1;
+                }
+
+                offset += literals_len;
+            }
+        }
+    }
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+
+    buffer.push(value as u8);
+}
+
+fn read_varint(buffer: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = buffer[*offset];
+        *offset += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(from: &[u8], to: &[u8]) {
+        let mut delta = Vec::new();
+        encode_delta(from, to, &mut delta);
+
+        let mut data = from.to_vec();
+        apply_delta(&delta, &mut data);
+        assert_eq!(data, to);
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let to = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let mut from = to.clone();
+        from[0] ^= 1;
+        from[500..520].fill(0xFF);
+        from[999] = 42;
+
+        round_trip(&from, &to);
+        round_trip(&to, &to);
+        round_trip(&[], &[]);
+    }
+
+    #[test]
+    fn delta_of_different_sizes_is_raw() {
+        let mut delta = Vec::new();
+        encode_delta(&[1, 2, 3], &[1, 2], &mut delta);
+        assert_eq!(delta, [DELTA_RAW, 1, 2]);
+
+        round_trip(&[1, 2, 3], &[1, 2]);
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, 1 << 20, usize::MAX >> 1];
+        let mut buffer = Vec::new();
+
+        for &value in &values {
+            write_varint(&mut buffer, value);
+        }
+
+        assert_eq!(buffer[..4], [0x00, 0x01, 0x7F, 0x80]);
+
+        let mut offset = 0;
+
+        for &value in &values {
+            assert_eq!(read_varint(&buffer, &mut offset), value);
+        }
+
+        assert_eq!(offset, buffer.len());
+    }
+
+    #[test]
+    fn pop_skips_the_current_state() {
+        let mut rewind = Rewind::new(RewindConfig::default());
+        let mut dst = Vec::new();
+
+        rewind.push(&[1, 1, 1]);
+        assert!(!rewind.pop(&mut dst));
+
+        rewind.on_frame_complete();
+        rewind.push(&[2, 2, 2]);
+        rewind.on_frame_complete();
+        rewind.push(&[3, 3, 3]);
+
+        assert!(rewind.pop(&mut dst));
+        assert_eq!(dst, [2, 2, 2]);
+        assert!(rewind.pop(&mut dst));
+        assert_eq!(dst, [1, 1, 1]);
+        assert!(!rewind.pop(&mut dst));
+    }
+
+    #[test]
+    fn pop_restores_the_newest_snapshot_after_frames() {
+        let mut rewind = Rewind::new(RewindConfig::default());
+        let mut dst = Vec::new();
+
+        rewind.push(&[1, 1, 1]);
+        rewind.push(&[2, 2, 2]);
+        rewind.on_frame_complete();
+
+        assert!(rewind.pop(&mut dst));
+        assert_eq!(dst, [2, 2, 2]);
+    }
+}
@@ -1,9 +1,23 @@
+use crate::region::Region;
+
 const NESSY: &[u8; 5] = b"NESSY";
 const HASH_SIZE: usize = 32; // bytes
-const SAVE_VERSION: u8 = 0;
+
+// bumped when the layout of a section changes, states of other versions are rejected
+// 1: the region is stored in the root section and the PPU clock remainder in the bus
+// 2: the CPU cycles the PPU and the APU lag behind are stored in the bus
+// 3: the pending OAM DMA page replaces the DMA transfer flag of the bus
+// 4: the bus stores the polled NMI and the PPU whether the next vblank flag is suppressed
+//    by a $2002 read
+const SAVE_VERSION: u8 = 4;
 const VERSION_SIZE: usize = 1; // bytes
 const HEADER_SIZE: usize = NESSY.len() + VERSION_SIZE + HASH_SIZE; // bytes
 
+/// whether `data` starts like a save state encoded by `SaveState::encode`
+pub fn is_save_state(data: &[u8]) -> bool {
+    data.starts_with(NESSY)
+}
+
 pub trait Save {
     fn save(&self, parent: &mut Section);
     fn load(&mut self, parent: &mut Section) -> Result<(), SaveStateError>;
@@ -13,6 +27,8 @@ pub struct Section {
     pub name: String,
     pub data: ByteBuffer,
     pub children: Vec<Section>,
+    // children of the previous save, reused by `create_child` after a `clear`
+    spare: Vec<Section>,
 }
 
 impl Section {
@@ -26,9 +42,18 @@ impl Section {
             name: name.into(),
             data: ByteBuffer::new(),
             children: vec![],
+            spare: vec![],
         }
     }
 
+    /// empties the section while keeping its allocations,
+    /// saving again in the same tree doesn't allocate once the sizes are stable
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.spare.clear();
+        self.spare.extend(self.children.drain(..).rev());
+    }
+
     pub fn encode_into(&self, buffer: &mut Vec<u8>) {
         // header: name, data size, number of children
         buffer.extend_from_slice(self.name.as_bytes());
@@ -80,6 +105,7 @@ impl Section {
             name,
             data,
             children,
+            spare: vec![],
         }
     }
 
@@ -88,7 +114,14 @@ impl Section {
     }
 
     pub fn create_child(&mut self, name: &str) -> &mut Section {
-        let child = Section::new(name);
+        let child = match self.spare.pop() {
+            Some(mut child) if child.name == name => {
+                child.clear();
+                child
+            }
+            _ => Section::new(name),
+        };
+
         self.add_child(child);
         self.children.last_mut().unwrap()
     }
@@ -130,7 +163,7 @@ impl Section {
 
     /// in bytes
     pub fn size(&self) -> usize {
-        self.name.as_bytes().len() + 1 // name + null terminator
+        self.name.len() + 1 // name + null terminator
         + 4 // data size
         + 1 // number of children
             + self.data.size()
@@ -150,6 +183,10 @@ pub enum SaveStateError {
         save_state_rom_hash: [u8; HASH_SIZE],
         cart_rom_hash: [u8; HASH_SIZE],
     },
+    IncoherentRegion {
+        save_state_region: Region,
+        region: Region,
+    },
     MissingSection(String),
     InvalidData,
 }
@@ -177,6 +214,11 @@ impl SaveState {
         &mut self.root
     }
 
+    /// the sections are kept for the next save, see `Section::clear`
+    pub fn clear(&mut self) {
+        self.root.clear();
+    }
+
     pub fn decode(data: &[u8]) -> Result<SaveState, SaveStateError> {
         let mut header = [0; HEADER_SIZE];
         header.copy_from_slice(&data[..HEADER_SIZE]);
@@ -192,7 +234,7 @@ impl SaveState {
         let version = header[offset];
         offset += 1;
 
-        if version > SAVE_VERSION {
+        if version != SAVE_VERSION {
             return Err(SaveStateError::InvalidVersion(version));
         }
 
@@ -211,11 +253,17 @@ impl SaveState {
 
     pub fn encode(self) -> Vec<u8> {
         let mut buffer = Vec::with_capacity(HEADER_SIZE + self.root.size());
-        buffer.extend_from_slice(&self.header);
-        self.root.encode_into(&mut buffer);
+        self.encode_into(&mut buffer);
 
         buffer
     }
+
+    /// reuses the allocation of `buffer`, which is cleared first
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) {
+        buffer.clear();
+        buffer.extend_from_slice(&self.header);
+        self.root.encode_into(buffer);
+    }
 }
 
 pub struct ByteBuffer {
@@ -224,6 +272,7 @@ pub struct ByteBuffer {
 }
 
 impl ByteBuffer {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         ByteBuffer {
             data: vec![],
@@ -243,6 +292,11 @@ impl ByteBuffer {
         self.data.len()
     }
 
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.read_index = 0;
+    }
+
     pub fn get_data(&self) -> &Vec<u8> {
         &self.data
     }
@@ -330,3 +384,37 @@ impl ByteBuffer {
         Ok(u64::from_le_bytes(buf))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Section;
+
+    fn save(root: &mut Section, val: u8) {
+        root.data.write_u8(val);
+        let child = root.create_child("child");
+        child.data.write_u16(val as u16);
+        child.create_child("grandchild").data.write_u8(val);
+        root.create_child("other").data.write_u8(val);
+    }
+
+    fn encode(section: &Section) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        section.encode_into(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn cleared_sections_are_reused() {
+        let mut root = Section::new("root");
+        save(&mut root, 1);
+        let data = root.children[0].children[0].data.get_data().as_ptr();
+
+        root.clear();
+        save(&mut root, 2);
+        assert_eq!(root.children[0].children[0].data.get_data().as_ptr(), data);
+
+        let mut fresh = Section::new("root");
+        save(&mut fresh, 2);
+        assert_eq!(encode(&root), encode(&fresh));
+    }
+}
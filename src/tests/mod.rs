@@ -0,0 +1,31 @@
+// helpers shared by the unit tests
+
+use crate::cpu::rom::ROM;
+
+pub const NESTEST: &[u8] = include_bytes!("nestest.nes");
+
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const CHR_ROM_BANK_SIZE: usize = 0x2000;
+
+pub fn nestest() -> ROM {
+    ROM::new(NESTEST.to_vec()).unwrap()
+}
+
+/// iNES image with `prg_banks` 16 KiB PRG ROM banks filled with their index
+/// and one CHR ROM bank of zeros
+pub fn rom_bytes(mapper: u8, prg_banks: u8) -> Vec<u8> {
+    let mut bytes = vec![b'N', b'E', b'S', 0x1A, prg_banks, 1, mapper << 4];
+    bytes.resize(16, 0);
+
+    for bank in 0..prg_banks {
+        bytes.resize(bytes.len() + PRG_ROM_BANK_SIZE, bank);
+    }
+
+    bytes.resize(bytes.len() + CHR_ROM_BANK_SIZE, 0);
+    bytes
+}
+
+/// NROM cartridge with 16 KiB of PRG ROM and 8 KiB of CHR ROM, all zeros
+pub fn nrom() -> ROM {
+    ROM::new(rom_bytes(0, 1)).unwrap()
+}
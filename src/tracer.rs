@@ -0,0 +1,491 @@
+// Execution trace logger, writes one line (or record) per executed instruction
+//
+// the text format follows the nestest.log / Mesen layout so that traces can be diffed
+// against other emulators, unofficial opcodes are marked with a '*':
+// 0F:C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+//
+// the binary format is a header followed by fixed size little endian records
+
+use std::io::{self, BufWriter, Read, Write};
+
+use crate::cpu::{
+    disasm::{self, Instruction, Symbols},
+    Registers, CPU,
+};
+
+const BINARY_MAGIC: &[u8; 8] = b"NESTRACE";
+const BINARY_VERSION: u8 = 1;
+const RECORD_SIZE: usize = 24;
+const NO_PRG_OFFSET: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    InvalidHeader,
+    UnsupportedVersion(u8),
+}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> Self {
+        TraceError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+/// the column flags only apply to the text format, binary records contain everything
+#[derive(Debug, Clone, Copy)]
+pub struct TraceConfig {
+    pub format: TraceFormat,
+    /// PRG ROM bank of the instruction, in the bank size of the mapper
+    pub bank: bool,
+    /// raw instruction bytes
+    pub bytes: bool,
+    /// PPU scanline and dot
+    pub ppu: bool,
+    /// total CPU cycles
+    pub cycles: bool,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            format: TraceFormat::Text,
+            bank: true,
+            bytes: true,
+            ppu: true,
+            cycles: true,
+        }
+    }
+}
+
+/// state of the console right before an instruction is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub registers: Registers,
+    pub scanline: u16,
+    pub dot: u16,
+    pub cycles: u32,
+    /// offset of the instruction in the PRG ROM, None when executing from RAM
+    pub prg_offset: Option<usize>,
+    /// in bytes, 16KB or 8KB depending on the mapper
+    pub prg_bank_size: usize,
+    pub bytes: [u8; 3],
+}
+
+impl TraceRecord {
+    pub fn prg_bank(&self) -> Option<usize> {
+        self.prg_offset.map(|offset| offset / self.prg_bank_size)
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        disasm::decode(self.registers.pc, self.bytes)
+    }
+
+    pub fn format(&self, config: &TraceConfig) -> String {
+        self.format_instruction(&self.instruction(), config, None)
+    }
+
+    fn format_instruction(
+        &self,
+        inst: &Instruction,
+        config: &TraceConfig,
+        symbols: Option<&Symbols>,
+    ) -> String {
+        let regs = &self.registers;
+        let mut line = String::with_capacity(96);
+
+        if config.bank {
+            match self.prg_bank() {
+                Some(bank) => line.push_str(&format!("{:02X}:", bank)),
+                None => line.push_str("--:"),
+            }
+        }
+
+        line.push_str(&format!("{:04X}  ", regs.pc));
+
+        if config.bytes {
+            line.push_str(&format!("{:<8} ", inst.format_bytes()));
+        }
+
+        line.push(if inst.official { ' ' } else { '*' });
+        line.push_str(&format!("{:<32}", inst.format(symbols)));
+        line.push_str(&format!(
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            regs.a, regs.x, regs.y, regs.p, regs.sp
+        ));
+
+        if config.ppu {
+            line.push_str(&format!(" PPU:{:>3},{:>3}", self.scanline, self.dot));
+        }
+
+        if config.cycles {
+            line.push_str(&format!(" CYC:{}", self.cycles));
+        }
+
+        line
+    }
+
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let regs = &self.registers;
+        let prg_offset = self
+            .prg_offset
+            .map_or(NO_PRG_OFFSET, |offset| offset as u32);
+        let mut buf = [0u8; RECORD_SIZE];
+
+        buf[0..2].copy_from_slice(&regs.pc.to_le_bytes());
+        buf[2..7].copy_from_slice(&[regs.a, regs.x, regs.y, regs.sp, regs.p]);
+        buf[7..9].copy_from_slice(&self.scanline.to_le_bytes());
+        buf[9..11].copy_from_slice(&self.dot.to_le_bytes());
+        buf[11..15].copy_from_slice(&self.cycles.to_le_bytes());
+        buf[15..19].copy_from_slice(&prg_offset.to_le_bytes());
+        buf[19] = self.instruction().len as u8;
+        buf[20..23].copy_from_slice(&self.bytes);
+        buf[23] = (self.prg_bank_size / 1024) as u8;
+
+        buf
+    }
+
+    fn decode(buf: &[u8; RECORD_SIZE]) -> TraceRecord {
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let prg_offset = u32_at(15);
+        let len = (buf[19] as usize).min(3);
+        let mut bytes = [0u8; 3];
+        bytes[..len].copy_from_slice(&buf[20..20 + len]);
+
+        TraceRecord {
+            registers: Registers {
+                pc: u16_at(0),
+                a: buf[2],
+                x: buf[3],
+                y: buf[4],
+                sp: buf[5],
+                p: buf[6],
+            },
+            scanline: u16_at(7),
+            dot: u16_at(9),
+            cycles: u32_at(11),
+            prg_offset: (prg_offset != NO_PRG_OFFSET).then_some(prg_offset as usize),
+            prg_bank_size: buf[23] as usize * 1024,
+            bytes,
+        }
+    }
+}
+
+pub struct Tracer {
+    config: TraceConfig,
+    writer: BufWriter<Box<dyn Write + Send>>,
+    symbols: Option<Symbols>,
+    instruction_count: u64,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(config: TraceConfig, writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        let mut writer = BufWriter::new(writer);
+
+        if config.format == TraceFormat::Binary {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_all(&[BINARY_VERSION])?;
+        }
+
+        Ok(Tracer {
+            config,
+            writer,
+            symbols: None,
+            instruction_count: 0,
+            error: None,
+        })
+    }
+
+    pub fn get_config(&self) -> &TraceConfig {
+        &self.config
+    }
+
+    /// labels used in the text format
+    pub fn set_symbols(&mut self, symbols: Option<Symbols>) {
+        self.symbols = symbols;
+    }
+
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// tracing stops after the first write error
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// flushes the trace and returns the underlying writer
+    pub fn finish(self) -> io::Result<Box<dyn Write + Send>> {
+        self.writer.into_inner().map_err(|err| err.into_error())
+    }
+
+    /// `cycles` includes the cycles spent entering an interrupt handler
+    pub(crate) fn trace(&mut self, cpu: &mut CPU, cycles: u32) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let registers = cpu.get_registers();
+        let inst = disasm::disassemble(&mut cpu.bus, registers.pc);
+        let record = TraceRecord {
+            registers,
+            scanline: cpu.bus.ppu.get_scanline(),
+            dot: cpu.bus.ppu.cycle,
+            cycles,
+            prg_offset: inst.prg_offset,
+            prg_bank_size: cpu.bus.ppu.rom.mapper.prg_bank_size(),
+            bytes: inst.bytes,
+        };
+
+        let res = match self.config.format {
+            TraceFormat::Text => {
+                let line = record.format_instruction(&inst, &self.config, self.symbols.as_ref());
+                writeln!(self.writer, "{}", line)
+            }
+            TraceFormat::Binary => self.writer.write_all(&record.encode()),
+        };
+
+        match res {
+            Ok(()) => self.instruction_count += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+/// iterates over the records of a binary trace
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, TraceError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != BINARY_MAGIC {
+            return Err(TraceError::InvalidHeader);
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+
+        if version[0] != BINARY_VERSION {
+            return Err(TraceError::UnsupportedVersion(version[0]));
+        }
+
+        Ok(TraceReader { reader })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0u8; RECORD_SIZE];
+        let mut read = 0;
+
+        while read < RECORD_SIZE {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return None,
+                Ok(0) => return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+
+        Some(Ok(TraceRecord::decode(&buf)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{TraceConfig, TraceError, TraceFormat, TraceReader, TraceRecord, Tracer};
+    use crate::{
+        bus::Bus,
+        cpu::{Registers, CPU},
+        tests::nestest,
+    };
+
+    const NESTEST_LOG: &str = include_str!("tests/nestest.log");
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // runs nestest in automation mode, starting at $C000
+    fn trace_nestest(config: TraceConfig, instructions: usize) -> Vec<u8> {
+        let mut cpu = CPU::new(Bus::new(nestest(), 44100.0));
+        let buffer = SharedBuffer::default();
+        cpu.pc = 0xC000;
+        cpu.bus.tracer = Some(Box::new(
+            Tracer::new(config, Box::new(buffer.clone())).unwrap(),
+        ));
+
+        while cpu.bus.tracer.as_ref().unwrap().get_instruction_count() < instructions as u64 {
+            let cycles = cpu.step();
+            cpu.bus.advance(cycles);
+        }
+
+        cpu.bus.tracer.take().unwrap().flush().unwrap();
+        let bytes = buffer.0.lock().unwrap().clone();
+        bytes
+    }
+
+    // nestest.log shows the memory accessed by the instruction: "STX $00 = 00"
+    fn without_annotation(line: &str) -> String {
+        let (start, rest) = line.split_at(16);
+        let (disasm, registers) = rest.split_at(32);
+        let disasm = disasm
+            .split(" @ ")
+            .next()
+            .unwrap()
+            .split(" = ")
+            .next()
+            .unwrap();
+
+        format!("{start}{disasm:<32}{registers}")
+    }
+
+    #[test]
+    fn text_format_matches_nestest_log() {
+        let config = TraceConfig {
+            bank: false,
+            ppu: false,
+            cycles: false,
+            ..Default::default()
+        };
+
+        // the official opcodes, the CPU doesn't skip the operands of the unofficial NOPs
+        let expected = NESTEST_LOG.lines().take(5003).collect::<Vec<_>>();
+        let trace = String::from_utf8(trace_nestest(config, expected.len())).unwrap();
+
+        for (index, (line, expected)) in trace.lines().zip(&expected).enumerate() {
+            let expected = without_annotation(&expected[..expected.find(" PPU:").unwrap()]);
+            assert_eq!(line, expected, "line {}", index + 1);
+        }
+    }
+
+    #[test]
+    fn ppu_and_cycle_columns() {
+        let record = TraceRecord {
+            registers: Registers {
+                a: 0,
+                x: 0,
+                y: 0,
+                pc: 0xC5F5,
+                sp: 0xFD,
+                p: 0x24,
+            },
+            scanline: 0,
+            dot: 30,
+            cycles: 10,
+            prg_offset: Some(0x05F5),
+            prg_bank_size: 0x4000,
+            bytes: [0xA2, 0x00, 0x00],
+        };
+
+        let expected = NESTEST_LOG.lines().nth(1).unwrap();
+        assert_eq!(
+            record.format(&TraceConfig::default()),
+            format!("00:{expected}")
+        );
+    }
+
+    #[test]
+    fn banks_use_the_bank_size_of_the_mapper() {
+        let mut record = TraceRecord {
+            registers: Registers {
+                a: 0,
+                x: 0,
+                y: 0,
+                pc: 0x8123,
+                sp: 0xFD,
+                p: 0x24,
+            },
+            scanline: 0,
+            dot: 0,
+            cycles: 0,
+            prg_offset: Some(0x6123),
+            prg_bank_size: 0x2000,
+            bytes: [0xEA, 0x00, 0x00],
+        };
+
+        assert_eq!(record.prg_bank(), Some(3));
+        assert!(record
+            .format(&TraceConfig::default())
+            .starts_with("03:8123"));
+
+        record.prg_offset = None;
+        assert_eq!(record.prg_bank(), None);
+        assert!(record
+            .format(&TraceConfig::default())
+            .starts_with("--:8123"));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let text_config = TraceConfig::default();
+        let binary_config = TraceConfig {
+            format: TraceFormat::Binary,
+            ..Default::default()
+        };
+
+        let text = String::from_utf8(trace_nestest(text_config, 100)).unwrap();
+        let binary = trace_nestest(binary_config, 100);
+
+        let records = TraceReader::new(binary.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 100);
+
+        for (record, line) in records.iter().zip(text.lines()) {
+            assert_eq!(record.format(&text_config), line);
+            assert_eq!(TraceRecord::decode(&record.encode()), *record);
+        }
+    }
+
+    #[test]
+    fn invalid_binary_traces() {
+        assert!(matches!(
+            TraceReader::new(&b"NESTRACX\x01"[..]),
+            Err(TraceError::InvalidHeader)
+        ));
+        assert!(matches!(
+            TraceReader::new(&b"NESTRACE\x09"[..]),
+            Err(TraceError::UnsupportedVersion(9))
+        ));
+
+        // a truncated record
+        let mut reader = TraceReader::new(&b"NESTRACE\x01\x00\xC0"[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(TraceError::Io(_)))));
+    }
+}
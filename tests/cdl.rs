@@ -0,0 +1,56 @@
+// code/data log of a program playing a DMC sample from the PRG ROM
+
+mod common;
+
+use nessy::cdl::PrgFlags;
+
+const SAMPLE_OFFSET: usize = 0x400;
+// $4013 = 1
+const SAMPLE_LEN: usize = 17;
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 24] = [
+    0x78,             // SEI
+    0xA9, 0x4F,       // LDA #$4F, loop at the highest rate
+    0x8D, 0x10, 0x40, // STA $4010
+    0xA9, 0x10,       // LDA #$10, sample at $C400
+    0x8D, 0x12, 0x40, // STA $4012
+    0xA9, 0x01,       // LDA #$01
+    0x8D, 0x13, 0x40, // STA $4013
+    0xA9, 0x10,       // LDA #$10
+    0x8D, 0x15, 0x40, // STA $4015
+    0x4C, 0x15, 0xC0, // JMP $C015
+];
+
+#[test]
+fn dmc_samples_are_logged_as_pcm_data_only() {
+    let mut prg = vec![0xEA; SAMPLE_OFFSET + SAMPLE_LEN];
+    prg[..PROGRAM.len()].copy_from_slice(&PROGRAM);
+
+    let mut nes = common::nrom(&prg);
+    nes.enable_cdl();
+
+    for _ in 0..5 {
+        nes.next_frame();
+    }
+
+    let cdl = nes.get_cdl().unwrap();
+
+    for offset in 0..PROGRAM.len() {
+        assert!(cdl.get_prg_flags(offset).contains(PrgFlags::CODE));
+    }
+
+    for offset in SAMPLE_OFFSET..SAMPLE_OFFSET + SAMPLE_LEN {
+        let flags = cdl.get_prg_flags(offset);
+        assert!(
+            flags.contains(PrgFlags::PCM_DATA),
+            "{offset:04X}: {flags:?}"
+        );
+        assert!(
+            !flags.intersects(PrgFlags::CODE | PrgFlags::DATA),
+            "{offset:04X}: {flags:?}"
+        );
+    }
+
+    assert!(cdl.get_prg_flags(SAMPLE_OFFSET + SAMPLE_LEN).is_empty());
+}
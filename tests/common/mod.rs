@@ -0,0 +1,209 @@
+// helpers shared by the integration tests
+//
+// the test ROMs are not distributed with the emulator, they are looked up in `tests/roms`
+// or in the directory set by the NESSY_TEST_ROMS environment variable,
+// a test whose ROM is missing fails
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use nessy::{
+    cpu::{rom::ROM, CpuMode},
+    Nes,
+};
+use sha2::{Digest, Sha256};
+
+const ROMS_DIR_VAR: &str = "NESSY_TEST_ROMS";
+pub const SAMPLE_RATE: f64 = 44100.0;
+// nestest is distributed with the emulator
+pub const NESTEST_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/nestest.nes");
+
+// test ROMs by blargg and kevtris report their result in the cartridge RAM:
+// $6000 holds the status and $6004 a null terminated message, the signature
+// at $6001-$6003 tells that the status is valid
+// https://github.com/christopherpow/nes-test-roms/blob/master/readme.txt
+const STATUS_ADDR: u16 = 0x6000;
+const SIGNATURE_ADDR: u16 = 0x6001;
+const TEXT_ADDR: u16 = 0x6004;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUESTED: u8 = 0x81;
+const MAX_TEXT_LEN: u16 = 0x1000;
+// the reset button must be pressed at least 100 ms after the request
+const RESET_DELAY_FRAMES: u32 = 10;
+const MAX_FRAMES: u32 = 60 * 60;
+
+pub fn rom_path(name: &str) -> PathBuf {
+    let dir = match std::env::var(ROMS_DIR_VAR) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"),
+    };
+
+    dir.join(name)
+}
+
+pub fn nestest_rom() -> ROM {
+    ROM::new(std::fs::read(NESTEST_PATH).unwrap()).unwrap()
+}
+
+pub fn nestest() -> Nes {
+    Nes::new(nestest_rom(), SAMPLE_RATE)
+}
+
+pub fn load_rom(name: &str, mode: CpuMode) -> Nes {
+    let path = rom_path(name);
+    let bytes = std::fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "{name}: cannot read {} ({err}), copy the nes-test-roms collection to tests/roms \
+             or set {ROMS_DIR_VAR}",
+            path.display()
+        )
+    });
+
+    let rom = ROM::new(bytes).unwrap_or_else(|err| panic!("{name}: {err:?}"));
+    let mut nes = Nes::new(rom, SAMPLE_RATE);
+    nes.set_cpu_mode(mode);
+    nes
+}
+
+fn read_text(nes: &mut Nes) -> String {
+    let mut bytes = Vec::new();
+
+    for addr in TEXT_ADDR..TEXT_ADDR + MAX_TEXT_LEN {
+        match nes.peek(addr) {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+fn has_signature(nes: &mut Nes) -> bool {
+    (0..3).all(|i| nes.peek(SIGNATURE_ADDR + i) == SIGNATURE[i as usize])
+}
+
+/// runs a ROM reporting its result at $6000 until the test completes,
+/// returns the status code and the message written by the ROM
+pub fn run_status_test(nes: &mut Nes) -> (u8, String) {
+    let mut reset_frame = None;
+
+    for frame in 0..MAX_FRAMES {
+        nes.next_frame();
+
+        if !has_signature(nes) {
+            continue;
+        }
+
+        match nes.peek(STATUS_ADDR) {
+            STATUS_RUNNING => {}
+            STATUS_RESET_REQUESTED => match reset_frame {
+                None => reset_frame = Some(frame + RESET_DELAY_FRAMES),
+                Some(reset) if frame >= reset => {
+                    nes.soft_reset();
+                    reset_frame = None;
+                }
+                Some(_) => {}
+            },
+            status => return (status, read_text(nes)),
+        }
+    }
+
+    panic!(
+        "timed out after {MAX_FRAMES} frames, output:\n{}",
+        read_text(nes)
+    );
+}
+
+/// asserts that the ROM reports a success, the output of the ROM is printed on failure
+pub fn assert_status_test(name: &str, mode: CpuMode) {
+    let mut nes = load_rom(name, mode);
+    let (status, text) = run_status_test(&mut nes);
+    assert_eq!(status, 0, "{name} failed with status {status}:\n{text}");
+}
+
+const PRG_ROM_SIZE: usize = 0x4000;
+const CHR_ROM_SIZE: usize = 0x2000;
+const PRG_ROM_START: u16 = 0xC000;
+
+/// NROM cartridge with 16 KiB of PRG ROM, `prg` is placed at $C000 and the reset vector
+/// points to it, the NMI and IRQ vectors point to an RTI at the end of the PRG ROM
+pub fn nrom(prg: &[u8]) -> Nes {
+    let mut bytes = vec![0u8; 16 + PRG_ROM_SIZE + CHR_ROM_SIZE];
+    bytes[..6].copy_from_slice(&[b'N', b'E', b'S', 0x1A, 1, 1]);
+
+    let rom = &mut bytes[16..16 + PRG_ROM_SIZE];
+    rom[..prg.len()].copy_from_slice(prg);
+
+    let rti = PRG_ROM_SIZE - 7;
+    rom[rti] = 0x40;
+
+    let rti_addr = (PRG_ROM_START + rti as u16).to_le_bytes();
+    let start_addr = PRG_ROM_START.to_le_bytes();
+    rom[PRG_ROM_SIZE - 6..].copy_from_slice(&[
+        rti_addr[0],
+        rti_addr[1],
+        start_addr[0],
+        start_addr[1],
+        rti_addr[0],
+        rti_addr[1],
+    ]);
+
+    Nes::new(ROM::new(bytes).unwrap(), SAMPLE_RATE)
+}
+
+pub fn frame_hash(nes: &Nes) -> String {
+    let hash = Sha256::digest(nes.get_frame());
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// for ROMs only reporting their result on screen: compares the hash of the frame
+/// displayed after `frames` frames with the one of a known good screenshot
+pub fn assert_screenshot_test(name: &str, frames: u32, expected_hash: &str) {
+    let mut nes = load_rom(name, CpuMode::Instruction);
+
+    for _ in 0..frames {
+        nes.next_frame();
+    }
+
+    assert_eq!(frame_hash(&nes), expected_hash, "{name}: unexpected frame");
+}
+
+// first nametable without the attribute table
+const NAMETABLE_ADDR: u16 = 0x2000;
+const NAMETABLE_WIDTH: u16 = 32;
+const NAMETABLE_HEIGHT: u16 = 30;
+
+/// text displayed in the first nametable, one line per row of tiles,
+/// for ROMs whose font tiles are laid out in ASCII order
+fn screen_text(nes: &mut Nes) -> String {
+    (0..NAMETABLE_HEIGHT)
+        .map(|row| {
+            (0..NAMETABLE_WIDTH)
+                .map(
+                    |col| match nes.peek_ppu(NAMETABLE_ADDR + row * NAMETABLE_WIDTH + col) {
+                        tile @ 0x20..=0x7E => tile as char,
+                        _ => ' ',
+                    },
+                )
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// for the older ROMs only printing their result on screen, without a known good screenshot:
+/// asserts that `expected` is displayed after `frames` frames
+pub fn assert_screen_text_test(name: &str, mode: CpuMode, frames: u32, expected: &str) {
+    let mut nes = load_rom(name, mode);
+
+    for _ in 0..frames {
+        nes.next_frame();
+    }
+
+    let text = screen_text(&mut nes);
+    assert!(text.contains(expected), "{name} displays:\n{text}");
+}
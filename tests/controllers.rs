@@ -0,0 +1,40 @@
+// reads of the controller ports by a program
+
+mod common;
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 27] = [
+    0xA9, 0x01,       // LDA #$01
+    0x8D, 0x16, 0x40, // STA $4016, strobe
+    0xA9, 0x00,       // LDA #$00
+    0x8D, 0x16, 0x40, // STA $4016
+    0xA2, 0x00,       // LDX #$00
+    0xAD, 0x17, 0x40, // LDA $4017
+    0x29, 0x01,       // AND #$01
+    0x95, 0x10,       // STA $10,X
+    0xE8,             // INX
+    0xE0, 0x08,       // CPX #$08
+    0xD0, 0xF4,       // BNE LDA $4017
+    0x4C, 0x18, 0xC0, // JMP $C018
+];
+
+#[test]
+fn second_controller_is_read_through_4017() {
+    let mut nes = common::nrom(&PROGRAM);
+    nes.get_joypad1_mut().update(0b1111_1111);
+    nes.get_joypad2_mut().update(0b0100_0100);
+
+    // first button without shifting
+    assert_eq!(nes.peek(0x4017), 0);
+    assert_eq!(nes.peek(0x4017), 0);
+
+    // the frame in progress at power on ends right away
+    for _ in 0..2 {
+        nes.next_frame();
+    }
+
+    let buttons = (0..8).map(|i| nes.peek(0x10 + i)).collect::<Vec<_>>();
+    assert_eq!(buttons, [0, 0, 1, 0, 0, 0, 1, 0]);
+    // all the buttons were shifted out
+    assert_eq!(nes.peek(0x4017), 1);
+}
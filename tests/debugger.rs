@@ -0,0 +1,198 @@
+mod common;
+
+use common::nestest;
+use nessy::{
+    debugger::{AccessKind, AddressSpace, Breakpoint, StopReason, Watchpoint},
+    Nes,
+};
+
+const MAX_STEPS: usize = 1_000_000;
+
+// addresses in the nestest menu initialization, which runs from reset
+const JSR_ADDR: u16 = 0xC07E; // JSR $C2A7
+const JSR_RETURN_ADDR: u16 = 0xC081;
+const SUBROUTINE_ADDR: u16 = 0xC2A7;
+const STA_ZP_ADDR: u16 = 0xC060; // STA $D0, with A = $78
+const LDA_PPUSTATUS_ADDR: u16 = 0xC009; // LDA $2002
+
+fn new_nes() -> Nes {
+    let mut nes = nestest();
+    nes.enable_debugger();
+    nes
+}
+
+fn run_until_stop(nes: &mut Nes) -> StopReason {
+    for _ in 0..MAX_STEPS {
+        if let Some(reason) = nes.step() {
+            return reason;
+        }
+    }
+
+    panic!("the debugger didn't stop");
+}
+
+fn pc(nes: &Nes) -> u16 {
+    nes.get_cpu().get_registers().pc
+}
+
+fn run_to(nes: &mut Nes, addr: u16) {
+    let debugger = nes.get_debugger_mut().unwrap();
+    let id = debugger.add_breakpoint(Breakpoint::new(addr));
+
+    assert_eq!(run_until_stop(nes), StopReason::Breakpoint { id, pc: addr });
+
+    nes.get_debugger_mut().unwrap().remove_breakpoint(id);
+}
+
+#[test]
+fn breakpoint() {
+    let mut nes = new_nes();
+    let id = nes
+        .get_debugger_mut()
+        .unwrap()
+        .add_breakpoint(Breakpoint::new(JSR_ADDR));
+
+    let reason = run_until_stop(&mut nes);
+    assert_eq!(reason, StopReason::Breakpoint { id, pc: JSR_ADDR });
+    assert_eq!(pc(&nes), JSR_ADDR);
+
+    // nothing is emulated while stopped
+    assert_eq!(nes.step(), Some(reason));
+    assert_eq!(pc(&nes), JSR_ADDR);
+
+    // resuming executes the instruction instead of breaking again
+    nes.get_debugger_mut().unwrap().resume();
+    assert_eq!(nes.step(), None);
+    assert_eq!(pc(&nes), SUBROUTINE_ADDR);
+}
+
+#[test]
+fn disabled_breakpoint() {
+    let mut nes = new_nes();
+    let debugger = nes.get_debugger_mut().unwrap();
+    let disabled = debugger.add_breakpoint(Breakpoint::new(STA_ZP_ADDR));
+    debugger.get_breakpoint_mut(disabled).unwrap().enabled = false;
+    let id = debugger.add_breakpoint(Breakpoint::new(JSR_ADDR));
+
+    assert_eq!(
+        run_until_stop(&mut nes),
+        StopReason::Breakpoint { id, pc: JSR_ADDR }
+    );
+}
+
+#[test]
+fn write_watchpoint() {
+    let mut nes = new_nes();
+    let id = nes
+        .get_debugger_mut()
+        .unwrap()
+        .add_watchpoint(Watchpoint::new(
+            AddressSpace::Cpu,
+            0x0000,
+            0x07FF,
+            AccessKind::WRITE,
+        ));
+
+    assert_eq!(
+        run_until_stop(&mut nes),
+        StopReason::Watchpoint {
+            id,
+            space: AddressSpace::Cpu,
+            addr: 0x00D0,
+            kind: AccessKind::WRITE,
+            value: 0x78,
+        }
+    );
+    // the watchpoint stops once the instruction completed
+    assert_eq!(pc(&nes), STA_ZP_ADDR + 2);
+}
+
+#[test]
+fn read_watchpoint() {
+    let mut nes = new_nes();
+    let id = nes
+        .get_debugger_mut()
+        .unwrap()
+        .add_watchpoint(Watchpoint::new(
+            AddressSpace::Cpu,
+            0x2002,
+            0x2002,
+            AccessKind::READ,
+        ));
+
+    let reason = run_until_stop(&mut nes);
+    assert!(
+        matches!(
+            reason,
+            StopReason::Watchpoint {
+                id: hit_id,
+                space: AddressSpace::Cpu,
+                addr: 0x2002,
+                kind: AccessKind::READ,
+                ..
+            } if hit_id == id
+        ),
+        "{reason:?}"
+    );
+    assert_eq!(pc(&nes), LDA_PPUSTATUS_ADDR + 3);
+}
+
+#[test]
+fn step_over() {
+    let mut nes = new_nes();
+    run_to(&mut nes, JSR_ADDR);
+    let sp = nes.get_cpu().get_registers().sp;
+
+    nes.get_debugger_mut().unwrap().step_over();
+
+    assert_eq!(run_until_stop(&mut nes), StopReason::Step);
+    assert_eq!(pc(&nes), JSR_RETURN_ADDR);
+    assert_eq!(nes.get_cpu().get_registers().sp, sp);
+}
+
+#[test]
+fn step_into() {
+    let mut nes = new_nes();
+    run_to(&mut nes, JSR_ADDR);
+
+    nes.get_debugger_mut().unwrap().step_into();
+
+    assert_eq!(run_until_stop(&mut nes), StopReason::Step);
+    assert_eq!(pc(&nes), SUBROUTINE_ADDR);
+}
+
+#[test]
+fn step_out() {
+    let mut nes = new_nes();
+    run_to(&mut nes, SUBROUTINE_ADDR);
+
+    nes.get_debugger_mut().unwrap().step_out();
+
+    assert_eq!(run_until_stop(&mut nes), StopReason::Step);
+    assert_eq!(pc(&nes), JSR_RETURN_ADDR);
+}
+
+#[test]
+fn run_to_scanline() {
+    let mut nes = new_nes();
+    run_to(&mut nes, JSR_ADDR);
+
+    nes.get_debugger_mut().unwrap().run_to_scanline(100);
+
+    assert_eq!(run_until_stop(&mut nes), StopReason::Scanline(100));
+    assert_eq!(nes.get_cpu().bus.ppu.get_scanline(), 100);
+    // in the loop clearing the nametables
+    assert_eq!(pc(&nes), 0xC300);
+}
+
+#[test]
+fn run_to_frame() {
+    let mut nes = new_nes();
+
+    nes.get_debugger_mut().unwrap().run_to_frame(1);
+
+    assert_eq!(run_until_stop(&mut nes), StopReason::Frame(1));
+    assert_eq!(nes.get_cpu().bus.ppu.get_frame_count(), 1);
+    // in the loop waiting for the second vblank
+    assert_eq!(pc(&nes), 0xC011);
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use nessy::{
+    event_log::{Event, EventKind},
+    Nes,
+};
+
+const NMI_HANDLER: u16 = 0xC040;
+const SPRITE_X: u8 = 40;
+const SPRITE_Y: u8 = 49; // drawn from scanline 50
+
+/// a program drawing an opaque background under sprite 0 with NMIs enabled from the third frame,
+/// the NMI handler writes $2005 twice
+#[rustfmt::skip]
+fn program() -> Vec<u8> {
+    let mut prg = vec![
+        0x78,             // SEI
+        0xA2, 0x00,       // LDX #0
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB,       // BPL $C003
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB,       // BPL $C008
+        // sprite 0 with the opaque tile 1
+        0xA9, 0x00,       // LDA #0
+        0x8D, 0x03, 0x20, // STA $2003
+        0xA9, SPRITE_Y,   // LDA #SPRITE_Y
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x01,       // LDA #1
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x00,       // LDA #0
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, SPRITE_X,   // LDA #SPRITE_X
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x80,       // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA9, 0x1E,       // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        0x4C, 0x30, 0xC0, // JMP $C030
+    ];
+    // NMI handler at $C040
+    prg.resize(0x40, 0xEA);
+    prg.extend([
+        0x8D, 0x05, 0x20, // STA $2005
+        0x8D, 0x05, 0x20, // STA $2005
+        0x40,             // RTI
+    ]);
+    prg
+}
+
+fn new_nes() -> Nes {
+    let mut nes = common::nrom(&program());
+    let [lo, hi] = NMI_HANDLER.to_le_bytes();
+    nes.poke(0xFFFA, lo);
+    nes.poke(0xFFFB, hi);
+
+    // tile 1 uses the color 1 everywhere and fills the nametables
+    for addr in 0x0010..0x0018 {
+        nes.poke_ppu(addr, 0xFF);
+    }
+    for addr in 0x2000..0x2800 {
+        nes.poke_ppu(addr, if addr & 0x3FF < 0x3C0 { 1 } else { 0 });
+    }
+
+    nes.enable_event_log();
+    nes
+}
+
+fn frame_events(nes: &Nes) -> Vec<Event> {
+    nes.get_event_log().unwrap().get_frame_events().to_vec()
+}
+
+fn event(kind: EventKind, scanline: u16, dot: u16, addr: u16, value: u8) -> Event {
+    Event {
+        kind,
+        scanline,
+        dot,
+        addr,
+        value,
+    }
+}
+
+// writes are logged at the dot of their bus cycle, the last cycle of a STA
+
+#[test]
+fn register_writes() {
+    let mut nes = new_nes();
+    for _ in 0..3 {
+        nes.next_frame();
+    }
+
+    // the setup runs after the $2002 read which saw the second vblank
+    let writes = &frame_events(&nes)[..7];
+    assert_eq!(
+        writes,
+        [
+            event(EventKind::PpuRegisterWrite, 241, 33, 0x2003, 0),
+            event(EventKind::PpuRegisterWrite, 241, 51, 0x2004, SPRITE_Y),
+            event(EventKind::PpuRegisterWrite, 241, 69, 0x2004, 1),
+            event(EventKind::PpuRegisterWrite, 241, 87, 0x2004, 0),
+            event(EventKind::PpuRegisterWrite, 241, 105, 0x2004, SPRITE_X),
+            event(EventKind::PpuRegisterWrite, 241, 123, 0x2000, 0x80),
+            event(EventKind::PpuRegisterWrite, 241, 141, 0x2001, 0x1E),
+        ]
+    );
+}
+
+#[test]
+fn nmi_and_sprite_zero_hit() {
+    let mut nes = new_nes();
+    for _ in 0..4 {
+        nes.next_frame();
+    }
+
+    assert_eq!(
+        frame_events(&nes),
+        [
+            // raised when vblank starts at dot 1 and taken after the JMP running then
+            event(EventKind::Nmi, 241, 11, NMI_HANDLER, 0),
+            // 7 cycles for the NMI and 3 before the write of the first STA
+            event(EventKind::PpuRegisterWrite, 241, 41, 0x2005, 0x1E),
+            event(EventKind::PpuRegisterWrite, 241, 53, 0x2005, 0x1E),
+            // the first opaque pixel of sprite 0 is drawn at dot x + 1
+            event(
+                EventKind::SpriteZeroHit,
+                SPRITE_Y as u16 + 1,
+                SPRITE_X as u16 + 1,
+                0,
+                0
+            ),
+        ]
+    );
+}
+
+#[test]
+fn rolls_over_at_the_end_of_the_frame() {
+    let mut nes = new_nes();
+    for _ in 0..3 {
+        nes.next_frame();
+    }
+
+    let log = nes.get_event_log().unwrap();
+    assert_eq!(log.get_frame_count(), 3);
+    assert!(log.get_current_events().is_empty());
+    let setup = frame_events(&nes);
+
+    // the NMI handler belongs to the frame which started with its vblank
+    nes.step();
+    nes.step();
+    let log = nes.get_event_log().unwrap();
+    assert_eq!(log.get_frame_events(), setup);
+    assert_eq!(log.get_current_events()[0].kind, EventKind::Nmi);
+
+    nes.next_frame();
+    let log = nes.get_event_log().unwrap();
+    assert_eq!(log.get_frame_count(), 4);
+    assert!(log.get_current_events().is_empty());
+    assert_eq!(log.get_frame_events()[0].kind, EventKind::Nmi);
+    assert!(!log
+        .get_frame_events()
+        .iter()
+        .any(|e| e.addr == 0x2000 || e.addr == 0x2001));
+}
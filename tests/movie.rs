@@ -0,0 +1,66 @@
+mod common;
+
+use common::nestest;
+use nessy::movie::{Movie, MovieAnchor, MovieStatus};
+
+const START: u8 = 0b0000_1000;
+const DOWN: u8 = 0b0010_0000;
+
+// nestest moves its cursor with down and runs the selected tests with start
+fn inputs(frame: usize) -> u8 {
+    match frame {
+        10 => DOWN,
+        20 => START,
+        _ => 0,
+    }
+}
+
+fn record(anchor: MovieAnchor) -> (Movie, Vec<Vec<u8>>) {
+    let mut nes = nestest();
+
+    for _ in 0..5 {
+        nes.next_frame();
+    }
+
+    nes.start_recording(anchor);
+    let mut frames = Vec::new();
+
+    for frame in 0..60 {
+        nes.get_joypad1_mut().update(inputs(frame));
+        nes.next_frame();
+        frames.push(nes.get_frame().to_vec());
+    }
+
+    // the inputs changed the screen
+    assert!(frames[0] != frames[frames.len() - 1]);
+
+    (nes.stop_movie().unwrap(), frames)
+}
+
+fn assert_replay(anchor: MovieAnchor) {
+    let (movie, frames) = record(anchor);
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    assert_eq!(movie.len(), frames.len());
+
+    // the inputs of the movie replace the ones of the joypad
+    let mut nes = nestest();
+    nes.get_joypad1_mut().update(START);
+    nes.play_movie(movie).unwrap();
+
+    for (frame, expected) in frames.iter().enumerate() {
+        nes.next_frame();
+        assert!(nes.get_frame() == expected.as_slice(), "frame {frame}");
+    }
+
+    assert_eq!(nes.get_movie_status(), MovieStatus::Idle);
+}
+
+#[test]
+fn movies_recorded_at_power_on_replay_after_an_fm2_round_trip() {
+    assert_replay(MovieAnchor::PowerOn);
+}
+
+#[test]
+fn movies_recorded_from_a_state_replay_after_an_fm2_round_trip() {
+    assert_replay(MovieAnchor::CurrentState);
+}
@@ -0,0 +1,61 @@
+// nestest runs every official opcode when started at $C000 (automation mode),
+// the registers are compared with the reference log before each instruction
+// https://www.qmtpro.com/~nes/misc/nestest.txt
+
+mod common;
+
+use common::{NESTEST_PATH, SAMPLE_RATE};
+use nessy::{cpu::rom::ROM, cpu::Registers, Nes};
+
+const LOG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/nestest.log");
+const HEADER_SIZE: usize = 16;
+const RESET_VECTOR_OFFSET: usize = HEADER_SIZE + 0x3ffc;
+const AUTOMATION_START: u16 = 0xc000;
+// result of the official opcodes tests, 0 when all of them passed
+const OFFICIAL_RESULT_ADDR: u16 = 0x02;
+
+fn parse_hex_field(line: &str, name: &str) -> u8 {
+    let start = line.find(name).expect(name) + name.len();
+    u8::from_str_radix(&line[start..start + 2], 16).unwrap()
+}
+
+fn parse_log_line(line: &str) -> Registers {
+    Registers {
+        a: parse_hex_field(line, "A:"),
+        x: parse_hex_field(line, "X:"),
+        y: parse_hex_field(line, "Y:"),
+        pc: u16::from_str_radix(&line[0..4], 16).unwrap(),
+        sp: parse_hex_field(line, "SP:"),
+        p: parse_hex_field(line, "P:"),
+    }
+}
+
+#[test]
+fn nestest_official_opcodes() {
+    let mut bytes = std::fs::read(NESTEST_PATH).unwrap();
+    // automation mode: the reset vector is patched to start at $C000
+    bytes[RESET_VECTOR_OFFSET] = AUTOMATION_START as u8;
+    bytes[RESET_VECTOR_OFFSET + 1] = (AUTOMATION_START >> 8) as u8;
+
+    let mut nes = Nes::new(ROM::new(bytes).unwrap(), SAMPLE_RATE);
+    let log = std::fs::read_to_string(LOG_PATH).unwrap();
+
+    // unofficial opcodes are marked with a '*' and are not supported
+    for (line_number, line) in log
+        .lines()
+        .take_while(|line| line.as_bytes()[15] != b'*')
+        .enumerate()
+    {
+        let expected = parse_log_line(line);
+        assert_eq!(
+            nes.get_cpu().get_registers(),
+            expected,
+            "line {}: {line}",
+            line_number + 1
+        );
+
+        nes.step();
+    }
+
+    assert_eq!(nes.peek(OFFICIAL_RESULT_ADDR), 0);
+}
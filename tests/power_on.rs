@@ -0,0 +1,37 @@
+mod common;
+
+use common::{nestest_rom, SAMPLE_RATE};
+use nessy::{
+    power_on::{PowerOnConfig, RamFill},
+    Nes,
+};
+
+const START: u8 = 0b0000_1000;
+
+fn new_nes(ram_fill: RamFill) -> Nes {
+    Nes::with_power_on_config(nestest_rom(), SAMPLE_RATE, PowerOnConfig { ram_fill })
+}
+
+// states and frames of a run starting nestest's tests
+fn run(ram_fill: RamFill) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut nes = new_nes(ram_fill);
+    let mut run = vec![(nes.save_state().encode(), nes.get_frame().to_vec())];
+
+    for frame in 0..30 {
+        nes.get_joypad1_mut()
+            .update(if frame == 10 { START } else { 0 });
+        nes.next_frame();
+        run.push((nes.save_state().encode(), nes.get_frame().to_vec()));
+    }
+
+    run
+}
+
+#[test]
+fn runs_with_the_same_seed_are_identical() {
+    let run1 = run(RamFill::Random(0x5EED));
+    assert!(run1 == run(RamFill::Random(0x5EED)));
+
+    let other_seed = run(RamFill::Random(0x5EED + 1));
+    assert!(run1[0].0 != other_seed[0].0);
+}
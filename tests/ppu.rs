@@ -0,0 +1,179 @@
+// PPU behaviors checked on nestest, whose mapper and CHR ROM are the simplest,
+// and on small NROM programs
+
+mod common;
+
+use common::{nestest, nrom};
+use nessy::{
+    event_log::{Event, EventKind},
+    ppu::RenderMode,
+    Nes,
+};
+
+// https://www.nesdev.org/wiki/PPU_palettes#Memory_Map
+#[test]
+fn palette_mirrors() {
+    let mut nes = nestest();
+
+    for i in 0..0x20 {
+        nes.poke_ppu(0x3F00 + i, i as u8);
+    }
+
+    // the last write to a mirrored entry wins
+    for (entry, val) in [(0x00, 0x10), (0x04, 0x14), (0x08, 0x18), (0x0C, 0x1C)] {
+        assert_eq!(nes.peek_ppu(0x3F00 + entry), val);
+        assert_eq!(nes.peek_ppu(0x3F10 + entry), val);
+    }
+
+    // the 32 bytes repeat up to $3FFF, including the sprite palette mirrors
+    for addr in 0x3F20..0x4000 {
+        assert_eq!(nes.peek_ppu(addr), nes.peek_ppu(0x3F00 + (addr & 0x1F)));
+    }
+
+    nes.poke_ppu(0x3FF0, 0x2A);
+    assert_eq!(nes.peek_ppu(0x3F00), 0x2A);
+    nes.poke_ppu(0x3F34, 0x15);
+    assert_eq!(nes.peek_ppu(0x3F04), 0x15);
+}
+
+// the menu is drawn, then Start runs the tests and their results are displayed
+fn render_frames(mode: RenderMode, frames: usize) -> Vec<Vec<u8>> {
+    let mut nes = nestest();
+    nes.set_render_mode(mode);
+
+    (0..frames)
+        .map(|frame| {
+            let start = if frame % 60 == 30 { 0b0000_1000 } else { 0 };
+            nes.get_joypad1_mut().update(start);
+            nes.next_frame();
+            nes.get_frame().to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn scanline_renderer_matches_dot_renderer() {
+    let dot = render_frames(RenderMode::Dot, 180);
+    let scanline = render_frames(RenderMode::Scanline, 180);
+
+    for (frame, (dot, scanline)) in dot.iter().zip(&scanline).enumerate() {
+        assert!(dot == scanline, "frame {frame} differs");
+    }
+}
+
+const NMI_HANDLER: u16 = 0xC060;
+const SPRITE_X: u8 = 40;
+const SPRITE_Y: u8 = 49;
+
+/// sprite 0 over a checkerboard, once its hit is polled the emphasis bits and the fine X
+/// scroll change in the middle of the line, the NMI handler restores them
+#[rustfmt::skip]
+fn mid_line_writes_program() -> Vec<u8> {
+    let mut prg = vec![
+        0x78,             // SEI
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB,       // BPL $C001
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB,       // BPL $C006
+        // sprite 0 with the opaque tile 1
+        0xA9, 0x00,       // LDA #0
+        0x8D, 0x03, 0x20, // STA $2003
+        0xA9, SPRITE_Y,   // LDA #SPRITE_Y
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x01,       // LDA #1
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x00,       // LDA #0
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, SPRITE_X,   // LDA #SPRITE_X
+        0x8D, 0x04, 0x20, // STA $2004
+        0xA9, 0x80,       // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA9, 0x1E,       // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        // waits for the hit flag to be cleared, then set
+        0xA9, 0x40,       // LDA #$40
+        0x2C, 0x02, 0x20, // BIT $2002
+        0xD0, 0xFB,       // BNE $C030
+        0x2C, 0x02, 0x20, // BIT $2002
+        0xF0, 0xFB,       // BEQ $C035
+        0xA9, 0xFE,       // LDA #$FE
+        0x8D, 0x01, 0x20, // STA $2001
+        0xA9, 0x03,       // LDA #3
+        0x8D, 0x05, 0x20, // STA $2005
+        0x8D, 0x05, 0x20, // STA $2005
+        0x4C, 0x2E, 0xC0, // JMP $C02E
+    ];
+    // NMI handler at $C060
+    prg.resize(0x60, 0xEA);
+    prg.extend([
+        0x48,             // PHA
+        0xA9, 0x1E,       // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        0xA9, 0x00,       // LDA #0
+        0x8D, 0x05, 0x20, // STA $2005
+        0x8D, 0x05, 0x20, // STA $2005
+        0x68,             // PLA
+        0x40,             // RTI
+    ]);
+    prg
+}
+
+fn mid_line_writes_nes(mode: RenderMode) -> Nes {
+    let mut nes = nrom(&mid_line_writes_program());
+    let [lo, hi] = NMI_HANDLER.to_le_bytes();
+    nes.poke(0xFFFA, lo);
+    nes.poke(0xFFFB, hi);
+
+    // tile 1 uses the color 1 everywhere, the nametables alternate tiles 0 and 1
+    for addr in 0x0010..0x0018 {
+        nes.poke_ppu(addr, 0xFF);
+    }
+    for addr in 0x2000..0x2800 {
+        let tile = ((addr >> 5) ^ addr) & 1;
+        nes.poke_ppu(addr, if addr & 0x3FF < 0x3C0 { tile as u8 } else { 0 });
+    }
+    for (addr, color) in [(0x3F00, 0x0F), (0x3F01, 0x30), (0x3F11, 0x16)] {
+        nes.poke_ppu(addr, color);
+    }
+
+    nes.set_render_mode(mode);
+    nes.enable_event_log();
+    nes
+}
+
+fn render_mid_line_writes(mode: RenderMode, frames: usize) -> Vec<(Vec<u8>, Vec<Event>)> {
+    let mut nes = mid_line_writes_nes(mode);
+
+    (0..frames)
+        .map(|_| {
+            nes.next_frame();
+            let events = nes.get_event_log().unwrap().get_frame_events().to_vec();
+            (nes.get_frame().to_vec(), events)
+        })
+        .collect()
+}
+
+// the scanline renderer falls back to the dot renderer for the rest of an interrupted line
+#[test]
+fn scanline_renderer_matches_dot_renderer_with_mid_line_writes() {
+    let dot = render_mid_line_writes(RenderMode::Dot, 6);
+    let scanline = render_mid_line_writes(RenderMode::Scanline, 6);
+
+    let (_, events) = dot.last().unwrap();
+    let hit = events
+        .iter()
+        .find(|e| e.kind == EventKind::SpriteZeroHit)
+        .expect("sprite 0 hit");
+    assert!(events.iter().any(|e| {
+        e.kind == EventKind::PpuRegisterWrite
+            && e.addr == 0x2001
+            && e.scanline == hit.scanline
+            && e.dot > hit.dot
+            && e.dot < 256
+    }));
+
+    for (frame, (dot, scanline)) in dot.iter().zip(&scanline).enumerate() {
+        assert!(dot.0 == scanline.0, "frame {frame} differs");
+        assert_eq!(dot.1, scanline.1, "frame {frame}");
+    }
+}
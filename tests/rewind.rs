@@ -0,0 +1,47 @@
+mod common;
+
+use common::nestest;
+use nessy::rewind::RewindConfig;
+
+#[test]
+fn first_rewind_step_goes_back_one_snapshot() {
+    let mut nes = nestest();
+    nes.enable_rewind(RewindConfig {
+        interval: 1,
+        ..Default::default()
+    });
+
+    let mut states = Vec::new();
+
+    for _ in 0..10 {
+        nes.next_frame();
+        states.push(nes.save_state().encode());
+    }
+
+    for (frame, expected) in states.iter().enumerate().rev().skip(1) {
+        assert!(nes.rewind_step().unwrap());
+        assert!(&nes.save_state().encode() == expected, "frame {frame}");
+    }
+
+    assert!(!nes.rewind_step().unwrap());
+}
+
+#[test]
+fn rewind_keeps_the_cheat_list() {
+    let mut nes = nestest();
+    nes.enable_rewind(RewindConfig {
+        interval: 1,
+        ..Default::default()
+    });
+
+    for _ in 0..5 {
+        nes.next_frame();
+    }
+
+    let id = nes.add_cheat("0300:12").unwrap();
+    nes.next_frame();
+    assert!(nes.rewind_step().unwrap());
+    assert!(nes.rewind_step().unwrap());
+
+    assert!(nes.get_cheats().get(id).is_some());
+}
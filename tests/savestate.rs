@@ -0,0 +1,58 @@
+mod common;
+
+use common::nestest;
+use nessy::{savestate::SaveStateError, Nes, Region};
+
+fn new_nes(region: Region) -> Nes {
+    let mut nes = nestest();
+    nes.set_region(region);
+    nes
+}
+
+fn encoded_state(region: Region) -> Vec<u8> {
+    let mut nes = new_nes(region);
+    nes.next_frame();
+    nes.save_state().encode()
+}
+
+#[test]
+fn states_are_rejected_in_another_region() {
+    let state = encoded_state(Region::Pal);
+
+    let mut nes = new_nes(Region::Ntsc);
+    let ntsc_state = nes.save_state().encode();
+
+    assert!(matches!(
+        nes.load_state(&state),
+        Err(SaveStateError::IncoherentRegion {
+            save_state_region: Region::Pal,
+            region: Region::Ntsc,
+        })
+    ));
+    // nothing was loaded
+    assert!(nes.save_state().encode() == ntsc_state);
+
+    nes.set_region(Region::Pal);
+    nes.load_state(&state).unwrap();
+    assert!(nes.save_state().encode() == state);
+}
+
+fn run_frames(nes: &mut Nes, frames: usize) -> (Vec<u8>, Vec<u8>) {
+    for _ in 0..frames {
+        nes.next_frame();
+    }
+
+    let ram = (0..0x800).map(|addr| nes.peek(addr)).collect();
+    (nes.get_frame().to_vec(), ram)
+}
+
+#[test]
+fn loading_a_state_resumes_the_same_emulation() {
+    let mut nes = new_nes(Region::Ntsc);
+    run_frames(&mut nes, 10);
+    let state = nes.save_state().encode();
+    let expected = run_frames(&mut nes, 30);
+
+    nes.load_state(&state).unwrap();
+    assert!(run_frames(&mut nes, 30) == expected);
+}
@@ -0,0 +1,201 @@
+// accuracy test ROMs, the paths follow the layout of the nes-test-roms collection:
+// https://github.com/christopherpow/nes-test-roms
+// the tests of the collection are ignored by default, `cargo test -- --ignored` runs them
+// once it is copied to tests/roms (or $NESSY_TEST_ROMS), a missing ROM fails its test
+
+mod common;
+
+use common::{
+    assert_screen_text_test, assert_screenshot_test, assert_status_test, nrom, run_status_test,
+    NESTEST_PATH,
+};
+use nessy::cpu::CpuMode;
+
+macro_rules! status_tests {
+    ($mode:expr; $($name:ident: $path:expr,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the nes-test-roms collection"]
+            fn $name() {
+                assert_status_test($path, $mode);
+            }
+        )*
+    };
+}
+
+macro_rules! screen_text_tests {
+    ($mode:expr; $($name:ident: $path:expr, $frames:expr, $text:expr,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the nes-test-roms collection"]
+            fn $name() {
+                assert_screen_text_test($path, $mode, $frames, $text);
+            }
+        )*
+    };
+}
+
+macro_rules! screenshot_tests {
+    ($($name:ident: $path:expr, $frames:expr, $hash:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                assert_screenshot_test($path, $frames, $hash);
+            }
+        )*
+    };
+}
+
+// CPU, the dummy reads and writes and the timing of each access need the cycle mode
+status_tests! {
+    CpuMode::Cycle;
+    instr_test_official: "instr_test-v5/official_only.nes",
+    instr_misc: "instr_misc/instr_misc.nes",
+    instr_timing: "instr_timing/instr_timing.nes",
+    cpu_dummy_reads: "cpu_dummy_reads/cpu_dummy_reads.nes",
+    cpu_dummy_writes_oam: "cpu_dummy_writes/cpu_dummy_writes_oam.nes",
+    cpu_dummy_writes_ppumem: "cpu_dummy_writes/cpu_dummy_writes_ppumem.nes",
+    cpu_exec_space_ppuio: "cpu_exec_space/test_cpu_exec_space_ppuio.nes",
+    cpu_reset_registers: "cpu_reset/registers.nes",
+    cpu_reset_ram_after_reset: "cpu_reset/ram_after_reset.nes",
+    cpu_interrupts_cli_latency: "cpu_interrupts_v2/rom_singles/1-cli_latency.nes",
+    cpu_interrupts_nmi_and_brk: "cpu_interrupts_v2/rom_singles/2-nmi_and_brk.nes",
+    cpu_interrupts_nmi_and_irq: "cpu_interrupts_v2/rom_singles/3-nmi_and_irq.nes",
+    cpu_interrupts_irq_and_dma: "cpu_interrupts_v2/rom_singles/4-irq_and_dma.nes",
+    cpu_interrupts_branch_delays_irq: "cpu_interrupts_v2/rom_singles/5-branch_delays_irq.nes",
+}
+
+// prints its result after about 16 seconds
+screen_text_tests! {
+    CpuMode::Cycle;
+    cpu_timing_test6: "cpu_timing_test6/cpu_timing_test.nes", 20 * 60, "PASSED",
+}
+
+// PPU
+status_tests! {
+    CpuMode::Instruction;
+    ppu_vbl_nmi_basics: "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes",
+    ppu_vbl_nmi_vbl_set_time: "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes",
+    ppu_vbl_nmi_vbl_clear_time: "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes",
+    ppu_vbl_nmi_nmi_control: "ppu_vbl_nmi/rom_singles/04-nmi_control.nes",
+    ppu_vbl_nmi_nmi_timing: "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes",
+    ppu_vbl_nmi_suppression: "ppu_vbl_nmi/rom_singles/06-suppression.nes",
+    ppu_vbl_nmi_nmi_on_timing: "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes",
+    ppu_vbl_nmi_nmi_off_timing: "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes",
+    ppu_vbl_nmi_even_odd_frames: "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes",
+    ppu_vbl_nmi_even_odd_timing: "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes",
+    ppu_open_bus: "ppu_open_bus/ppu_open_bus.nes",
+    ppu_read_buffer: "ppu_read_buffer/test_ppu_read_buffer.nes",
+    oam_read: "oam_read/oam_read.nes",
+    oam_stress: "oam_stress/oam_stress.nes",
+    sprite_dma_and_dmc_dma: "sprdma_and_dmc_dma/sprdma_and_dmc_dma.nes",
+}
+
+// APU
+status_tests! {
+    CpuMode::Instruction;
+    apu_len_ctr: "apu_test/rom_singles/1-len_ctr.nes",
+    apu_len_table: "apu_test/rom_singles/2-len_table.nes",
+    apu_irq_flag: "apu_test/rom_singles/3-irq_flag.nes",
+    apu_jitter: "apu_test/rom_singles/4-jitter.nes",
+    apu_len_timing: "apu_test/rom_singles/5-len_timing.nes",
+    apu_irq_flag_timing: "apu_test/rom_singles/6-irq_flag_timing.nes",
+    apu_dmc_basics: "apu_test/rom_singles/7-dmc_basics.nes",
+    apu_dmc_rates: "apu_test/rom_singles/8-dmc_rates.nes",
+    apu_reset_4015_cleared: "apu_reset/4015_cleared.nes",
+    apu_reset_4017_timing: "apu_reset/4017_timing.nes",
+    apu_reset_4017_written: "apu_reset/4017_written.nes",
+    apu_reset_irq_flag_cleared: "apu_reset/irq_flag_cleared.nes",
+    apu_reset_len_ctrs_enabled: "apu_reset/len_ctrs_enabled.nes",
+    apu_reset_works_immediately: "apu_reset/works_immediately.nes",
+    dmc_dma_during_read4_dma_2007_read: "dmc_dma_during_read4/dma_2007_read.nes",
+    dmc_dma_during_read4_dma_4016_read: "dmc_dma_during_read4/dma_4016_read.nes",
+    dmc_dma_during_read4_read_write_2007: "dmc_dma_during_read4/read_write_2007.nes",
+}
+
+// mappers
+status_tests! {
+    CpuMode::Instruction;
+    mmc3_clocking: "mmc3_test_2/rom_singles/1-clocking.nes",
+    mmc3_details: "mmc3_test_2/rom_singles/2-details.nes",
+    mmc3_a12_clocking: "mmc3_test_2/rom_singles/3-A12_clocking.nes",
+    mmc3_scanline_timing: "mmc3_test_2/rom_singles/4-scanline_timing.nes",
+    mmc3_mmc3: "mmc3_test_2/rom_singles/5-MMC3.nes",
+}
+
+// ROMs without the $6000 protocol, the hash is the SHA-256 of the RGB frame
+// nestest is distributed with the emulator, these tests are not ignored
+screenshot_tests! {
+    nestest_menu: NESTEST_PATH, 60,
+        "b488d4d18058444c462cb5ada24a97c99db871d364491c6c95380696f7c254cc",
+}
+
+// the $6000 protocol itself, with programs reporting like the test ROMs
+
+const PRG_ROM_START: u16 = 0xC000;
+
+// LDA #val, STA addr
+fn store(addr: u16, val: u8) -> [u8; 5] {
+    let [lo, hi] = addr.to_le_bytes();
+    [0xA9, val, 0x8D, lo, hi]
+}
+
+// writes the message, the signature and the status, then loops forever
+fn report(start: u16, status: u8, text: &str) -> Vec<u8> {
+    let mut prg = Vec::new();
+
+    for (addr, &byte) in (0x6004..).zip(text.as_bytes().iter().chain([&0])) {
+        prg.extend(store(addr, byte));
+    }
+
+    for (addr, byte) in [
+        (0x6001, 0xDE),
+        (0x6002, 0xB0),
+        (0x6003, 0x61),
+        (0x6000, status),
+    ] {
+        prg.extend(store(addr, byte));
+    }
+
+    let [lo, hi] = (start + prg.len() as u16).to_le_bytes();
+    prg.extend([0x4C, lo, hi]); // JMP to itself
+    prg
+}
+
+#[test]
+fn status_protocol_success() {
+    let mut nes = nrom(&report(PRG_ROM_START, 0, "Passed"));
+    assert_eq!(run_status_test(&mut nes), (0, "Passed".to_string()));
+}
+
+#[test]
+fn status_protocol_failure() {
+    let mut nes = nrom(&report(PRG_ROM_START, 3, "Failed #3"));
+    assert_eq!(run_status_test(&mut nes), (3, "Failed #3".to_string()));
+}
+
+#[test]
+fn status_protocol_reset_request() {
+    // asks for a reset, then passes once the console was reset with the status still in PRG RAM
+    const CHECK_LEN: u16 = 7;
+    let request = report(PRG_ROM_START + CHECK_LEN, 0x81, "");
+
+    #[rustfmt::skip]
+    let mut prg = vec![
+        0xAD, 0x00, 0x60,          // LDA $6000
+        0xC9, 0x81,                // CMP #$81
+        0xF0, request.len() as u8, // BEQ to the success report
+    ];
+    prg.extend(&request);
+    prg.extend(report(
+        PRG_ROM_START + prg.len() as u16,
+        0,
+        "Passed after reset",
+    ));
+
+    let mut nes = nrom(&prg);
+    assert_eq!(
+        run_status_test(&mut nes),
+        (0, "Passed after reset".to_string())
+    );
+}
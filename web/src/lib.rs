@@ -4,9 +4,20 @@ extern crate wasm_bindgen;
 mod js;
 
 use nessy::{
-    cpu::rom::{RomError, ROM},
+    apu::{
+        mixer::{AudioChannel, AudioOutput, ChannelSettings},
+        rate_control::RateControlConfig,
+        ring::AudioConsumer,
+    },
+    cpu::{
+        rom::{RomError, ROM},
+        CpuMode,
+    },
+    movie::{Movie, MovieAnchor},
+    ppu::RenderMode,
+    rewind::RewindConfig,
     savestate::SaveStateError,
-    Nes,
+    Nes, Region,
 };
 extern crate console_error_panic_hook;
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
@@ -36,6 +47,9 @@ impl From<SaveStateErrorWrapper> for JsValue {
             SaveStateError::IncoherentRomHash { .. } => {
                 JsValue::from_str("Incoherent savestate hash")
             }
+            SaveStateError::IncoherentRegion { .. } => {
+                JsValue::from_str("Incoherent savestate region")
+            }
             SaveStateError::InvalidData => JsValue::from_str("Invalid save state data"),
             SaveStateError::MissingSection(name) => {
                 JsValue::from_str(&format!("Missing savestate section: {:?}", name))
@@ -44,6 +58,48 @@ impl From<SaveStateErrorWrapper> for JsValue {
     }
 }
 
+#[wasm_bindgen]
+pub struct AudioBufferHealth {
+    #[wasm_bindgen(js_name = bufferedFrames)]
+    pub buffered_frames: usize,
+    #[wasm_bindgen(js_name = capacityFrames)]
+    pub capacity_frames: usize,
+    #[wasm_bindgen(js_name = targetFrames)]
+    pub target_frames: Option<usize>,
+    #[wasm_bindgen(js_name = rateRatio)]
+    pub rate_ratio: f64,
+    pub underruns: u32,
+    pub overruns: u32,
+}
+
+/// reading end of the audio buffer, to pop the samples from the audio callback
+/// instead of `fillAudioBuffer`
+#[wasm_bindgen(js_name = AudioConsumer)]
+pub struct WasmAudioConsumer {
+    consumer: AudioConsumer,
+}
+
+#[wasm_bindgen(js_class = AudioConsumer)]
+impl WasmAudioConsumer {
+    /// returns the number of samples written, the rest of the buffer is left untouched
+    pub fn pop(&mut self, buffer: &mut [f32]) -> usize {
+        self.consumer.pop(buffer)
+    }
+
+    /// samples per frame, the samples of a frame are interleaved
+    pub fn channels(&self) -> usize {
+        self.consumer.channels()
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.consumer.underruns()
+    }
+
+    pub fn overruns(&self) -> u32 {
+        self.consumer.overruns()
+    }
+}
+
 #[wasm_bindgen(js_name = Nes)]
 pub struct WasmNes {
     nes: Nes,
@@ -69,6 +125,48 @@ impl WasmNes {
         self.nes.soft_reset();
     }
 
+    #[wasm_bindgen(js_name = powerCycle)]
+    pub fn power_cycle(&mut self) {
+        self.nes.power_cycle();
+    }
+
+    /// 0: NTSC, 1: PAL, 2: Dendy
+    #[wasm_bindgen(js_name = setRegion)]
+    pub fn set_region(&mut self, region: u8) -> Result<(), JsValue> {
+        let region = Region::try_from(region)
+            .map_err(|val| JsValue::from_str(&format!("Invalid region: {}", val)))?;
+
+        self.nes.set_region(region);
+
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = getRegion)]
+    pub fn get_region(&self) -> u8 {
+        self.nes.get_region().into()
+    }
+
+    /// renders whole scanlines at once when possible, the output is the same
+    #[wasm_bindgen(js_name = setScanlineRenderer)]
+    pub fn set_scanline_renderer(&mut self, enabled: bool) {
+        self.nes.set_render_mode(if enabled {
+            RenderMode::Scanline
+        } else {
+            RenderMode::Dot
+        });
+    }
+
+    /// performs every bus access of the CPU at the cycle it happens, slower but needed
+    /// by games relying on dummy reads or on the double writes of read-modify-write instructions
+    #[wasm_bindgen(js_name = setCycleAccurateCpu)]
+    pub fn set_cycle_accurate_cpu(&mut self, enabled: bool) {
+        self.nes.set_cpu_mode(if enabled {
+            CpuMode::Cycle
+        } else {
+            CpuMode::Instruction
+        });
+    }
+
     #[wasm_bindgen(js_name = nextFrame)]
     pub fn next_frame(&mut self, buffer: &mut [u8]) {
         self.nes.next_frame();
@@ -95,6 +193,46 @@ impl WasmNes {
         self.nes.get_joypad2_mut().update(buttons);
     }
 
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        self.nes.peek(addr)
+    }
+
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.nes.poke(addr, val);
+    }
+
+    #[wasm_bindgen(js_name = viewNametables)]
+    pub fn view_nametables(&mut self) -> Vec<u8> {
+        self.nes.view_nametables()
+    }
+
+    #[wasm_bindgen(js_name = viewPatternTables)]
+    pub fn view_pattern_tables(&mut self, palette_idx: u8) -> Vec<u8> {
+        self.nes.view_pattern_tables(palette_idx)
+    }
+
+    #[wasm_bindgen(js_name = viewOam)]
+    pub fn view_oam(&mut self) -> Vec<u8> {
+        self.nes.view_oam()
+    }
+
+    #[wasm_bindgen(js_name = viewPalette)]
+    pub fn view_palette(&self) -> Vec<u8> {
+        self.nes.view_palette()
+    }
+
+    #[wasm_bindgen(js_name = addCheat)]
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, JsValue> {
+        self.nes
+            .add_cheat(code)
+            .map_err(|err| JsValue::from_str(&format!("Invalid cheat code: {:?}", err)))
+    }
+
+    #[wasm_bindgen(js_name = removeCheat)]
+    pub fn remove_cheat(&mut self, id: usize) -> bool {
+        self.nes.remove_cheat(id)
+    }
+
     #[wasm_bindgen(js_name = saveState)]
     pub fn save_state(&self) -> Vec<u8> {
         self.nes.save_state().encode()
@@ -105,6 +243,50 @@ impl WasmNes {
         self.nes.load_state(data).map_err(SaveStateErrorWrapper)
     }
 
+    #[wasm_bindgen(js_name = enableRewind)]
+    pub fn enable_rewind(&mut self, interval: u32, memory_budget: usize) {
+        self.nes.enable_rewind(RewindConfig {
+            interval,
+            memory_budget,
+        });
+    }
+
+    #[wasm_bindgen(js_name = disableRewind)]
+    pub fn disable_rewind(&mut self) {
+        self.nes.disable_rewind();
+    }
+
+    #[wasm_bindgen(js_name = rewindStep)]
+    pub fn rewind_step(&mut self) -> Result<bool, SaveStateErrorWrapper> {
+        self.nes.rewind_step().map_err(SaveStateErrorWrapper)
+    }
+
+    /// starts from a power cycle when `from_power_on` is set, from the current state otherwise
+    #[wasm_bindgen(js_name = startRecording)]
+    pub fn start_recording(&mut self, from_power_on: bool) {
+        self.nes.start_recording(if from_power_on {
+            MovieAnchor::PowerOn
+        } else {
+            MovieAnchor::CurrentState
+        });
+    }
+
+    /// returns the movie in the FM2 format
+    #[wasm_bindgen(js_name = stopMovie)]
+    pub fn stop_movie(&mut self) -> Option<String> {
+        self.nes.stop_movie().map(|movie| movie.to_fm2())
+    }
+
+    #[wasm_bindgen(js_name = playFm2)]
+    pub fn play_fm2(&mut self, fm2: &str) -> Result<(), JsValue> {
+        let movie = Movie::from_fm2(fm2)
+            .map_err(|err| JsValue::from_str(&format!("Invalid movie: {:?}", err)))?;
+
+        self.nes.play_movie(movie).map_err(SaveStateErrorWrapper)?;
+
+        Ok(())
+    }
+
     #[wasm_bindgen(js_name = fillAudioBuffer)]
     pub fn fill_audio_buffer(&mut self, buffer: &mut [f32], avoid_underruns: bool) {
         self.nes.fill_audio_buffer(buffer, avoid_underruns);
@@ -114,4 +296,97 @@ impl WasmNes {
     pub fn clear_audio_buffer(&mut self) {
         self.nes.clear_audio_buffer();
     }
+
+    fn update_audio_channel(
+        &mut self,
+        channel: u8,
+        update: impl FnOnce(&mut ChannelSettings),
+    ) -> Result<(), JsValue> {
+        let channel = AudioChannel::try_from(channel)
+            .map_err(|val| JsValue::from_str(&format!("Invalid audio channel: {}", val)))?;
+
+        let mut settings = self.nes.get_audio_channel(channel);
+        update(&mut settings);
+        self.nes.set_audio_channel(channel, settings);
+
+        Ok(())
+    }
+
+    /// 0: pulse 1, 1: pulse 2, 2: triangle, 3: noise, 4: DMC
+    #[wasm_bindgen(js_name = setAudioChannelEnabled)]
+    pub fn set_audio_channel_enabled(&mut self, channel: u8, enabled: bool) -> Result<(), JsValue> {
+        self.update_audio_channel(channel, |settings| settings.enabled = enabled)
+    }
+
+    /// when at least one channel is soloed, only the soloed channels are heard
+    #[wasm_bindgen(js_name = setAudioChannelSolo)]
+    pub fn set_audio_channel_solo(&mut self, channel: u8, solo: bool) -> Result<(), JsValue> {
+        self.update_audio_channel(channel, |settings| settings.solo = solo)
+    }
+
+    /// 1.0 is the volume of the console
+    #[wasm_bindgen(js_name = setAudioChannelVolume)]
+    pub fn set_audio_channel_volume(&mut self, channel: u8, volume: f32) -> Result<(), JsValue> {
+        self.update_audio_channel(channel, |settings| settings.volume = volume)
+    }
+
+    /// -1.0: left only, 0.0: centered, 1.0: right only, only heard with the stereo output
+    #[wasm_bindgen(js_name = setAudioChannelPan)]
+    pub fn set_audio_channel_pan(&mut self, channel: u8, pan: f32) -> Result<(), JsValue> {
+        self.update_audio_channel(channel, |settings| settings.pan = pan)
+    }
+
+    /// 0: mono, 1: stems (5 channels), 2: stereo, the samples of a frame are interleaved
+    /// in the audio buffers, the buffered samples are dropped
+    #[wasm_bindgen(js_name = setAudioOutput)]
+    pub fn set_audio_output(&mut self, output: u8) -> Result<(), JsValue> {
+        let output = AudioOutput::try_from(output)
+            .map_err(|val| JsValue::from_str(&format!("Invalid audio output: {}", val)))?;
+
+        self.nes.set_audio_output(output);
+
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = getAudioOutput)]
+    pub fn get_audio_output(&self) -> u8 {
+        self.nes.get_audio_output().into()
+    }
+
+    /// keeps `target_frames` frames in the audio buffer by changing the sample rate
+    /// by at most `max_delta` (0.005 is not audible)
+    #[wasm_bindgen(js_name = enableAudioRateControl)]
+    pub fn enable_audio_rate_control(&mut self, target_frames: usize, max_delta: f64) {
+        self.nes.enable_audio_rate_control(RateControlConfig {
+            target_frames,
+            max_delta,
+        });
+    }
+
+    #[wasm_bindgen(js_name = disableAudioRateControl)]
+    pub fn disable_audio_rate_control(&mut self) {
+        self.nes.disable_audio_rate_control();
+    }
+
+    /// `fillAudioBuffer` doesn't output anything once the consumer was taken
+    #[wasm_bindgen(js_name = takeAudioConsumer)]
+    pub fn take_audio_consumer(&mut self) -> Option<WasmAudioConsumer> {
+        self.nes
+            .take_audio_consumer()
+            .map(|consumer| WasmAudioConsumer { consumer })
+    }
+
+    #[wasm_bindgen(js_name = getAudioBufferHealth)]
+    pub fn get_audio_buffer_health(&self) -> AudioBufferHealth {
+        let health = self.nes.get_audio_buffer_health();
+
+        AudioBufferHealth {
+            buffered_frames: health.buffered_frames,
+            capacity_frames: health.capacity_frames,
+            target_frames: health.target_frames,
+            rate_ratio: health.rate_ratio,
+            underruns: health.underruns,
+            overruns: health.overruns,
+        }
+    }
 }